@@ -1,104 +1,723 @@
-use std::collections::HashMap;
+use std::{cmp::Ordering, collections::HashSet};
 
-use lm_sensors::{prelude::SharedChip, ChipRef, LMSensors};
+use log::error;
 
 use crate::{
-    components::temperature_graphs::get_temperature, ring_buffer::RingBuf,
-    sensors,
+    collector::Collector,
+    history::{Sample, Viewport},
+    pins::{Pin, Pins},
+    search::Search,
+    sensors::{Reading, SensorId, SensorKind},
+    snapshot::ChipSnapshot,
+    source::{HostStatus, SensorSource, SourceStatus},
+    state_file::PersistedState,
 };
 
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum InputMode {
+    Normal,
+    Search,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Screen {
+    Main,
+    Overview,
+}
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum OverviewSort {
+    Name,
+    Value,
+    Headroom,
+}
+
+impl OverviewSort {
+    pub fn name(&self) -> &'static str {
+        match self {
+            OverviewSort::Name => "name",
+            OverviewSort::Value => "value",
+            OverviewSort::Headroom => "headroom",
+        }
+    }
+
+    fn next(&self) -> Self {
+        match self {
+            OverviewSort::Name => OverviewSort::Value,
+            OverviewSort::Value => OverviewSort::Headroom,
+            OverviewSort::Headroom => OverviewSort::Name,
+        }
+    }
+}
+
+/// Panels whose charts can be switched between stacked and combined
+#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+pub enum ChartPanel {
+    Main,
+    Pin(Pin),
+}
+
 pub struct AppState {
-    selected_chip: Option<i32>,
-    pinned_chip: Option<i32>,
-    sensors: LMSensors,
-    historical_data: HashMap<String, RingBuf<f64>>,
+    selected_chip: Option<String>,
+    selected_feature: Option<String>,
+    pins: Pins,
+    marked_features: Vec<SensorId>,
+    combined_charts: HashSet<ChartPanel>,
+    auto_scaled_panels: HashSet<ChartPanel>,
+    auto_scaled_sensors: HashSet<SensorId>,
+    source: Box<dyn SensorSource>,
+    collector: Collector,
+    viewport: Viewport,
+    input_mode: InputMode,
+    search: Option<Search>,
+    screen: Screen,
+    overview_sort: OverviewSort,
+    overview_selected: usize,
+    is_log_visible: bool,
+    is_cpu_panel_visible: bool,
 }
 
 impl AppState {
-    pub fn get_sensors(&self) -> &LMSensors {
-        &self.sensors
+    pub fn new(mut source: Box<dyn SensorSource>) -> Self {
+        let persisted_state = PersistedState::load();
+        // Poll once so the first frame already has chips to show
+        let mut collector = Collector::default();
+        source.poll(&mut collector);
+        AppState {
+            selected_chip: None,
+            selected_feature: None,
+            source,
+            collector,
+            pins: Pins::from(persisted_state.pins),
+            marked_features: vec![],
+            combined_charts: HashSet::new(),
+            auto_scaled_panels: HashSet::new(),
+            auto_scaled_sensors: HashSet::new(),
+            viewport: Viewport::default(),
+            input_mode: InputMode::Normal,
+            search: None,
+            screen: Screen::Main,
+            overview_sort: OverviewSort::Name,
+            overview_selected: 0,
+            is_log_visible: false,
+            is_cpu_panel_visible: false,
+        }
+    }
+
+    pub fn get_input_mode(&self) -> InputMode {
+        self.input_mode
+    }
+
+    pub fn get_search(&self) -> Option<&Search> {
+        self.search.as_ref()
+    }
+
+    pub fn get_screen(&self) -> Screen {
+        self.screen
+    }
+
+    pub fn is_log_visible(&self) -> bool {
+        self.is_log_visible
+    }
+
+    pub fn toggle_log(&mut self) {
+        self.is_log_visible = !self.is_log_visible;
+    }
+
+    pub fn is_cpu_panel_visible(&self) -> bool {
+        self.is_cpu_panel_visible
+    }
+
+    pub fn toggle_cpu_panel(&mut self) {
+        self.is_cpu_panel_visible = !self.is_cpu_panel_visible;
     }
 
-    pub fn get_selected_chip(&self) -> ChipRef {
-        if let Some(selected_chip) = self.selected_chip {
-            self.sensors
-                .chip_iter(None)
-                .find(|chip| chip.address().unwrap() == selected_chip)
-                .unwrap()
+    pub fn toggle_screen(&mut self) {
+        self.screen = match self.screen {
+            Screen::Main => Screen::Overview,
+            Screen::Overview => Screen::Main,
+        };
+    }
+
+    pub fn get_collector(&self) -> &Collector {
+        &self.collector
+    }
+
+    pub fn get_source_status(&self) -> SourceStatus {
+        self.source.status()
+    }
+
+    /// Hosts shown by a fleet, empty otherwise
+    pub fn get_hosts(&self) -> Vec<HostStatus> {
+        self.source.hosts()
+    }
+
+    /// The hottest temperature on a fleet host, summarizes the host in the list
+    pub fn get_hottest_reading(&self, host: &str) -> Option<Reading> {
+        self.collector
+            .get_chips()
+            .iter()
+            .filter(|chip| chip.host.as_deref() == Some(host))
+            .flat_map(ChipSnapshot::readings)
+            .filter(|reading| reading.kind == SensorKind::Temperature)
+            .filter_map(|reading| Some((*reading.value.as_ref().ok()?, reading)))
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, reading)| reading)
+    }
+
+    /// Chips are keyed by name everywhere, so selection, pins and history carry
+    /// over when chips come and go
+    pub fn rescan_chips(&mut self) {
+        self.source.rescan();
+    }
+
+    pub fn is_chip_offline(&self, name: &str) -> bool {
+        self.collector.is_chip_offline(name)
+    }
+
+    /// Offline chips that are not hidden by the current search filter
+    pub fn get_visible_offline_chips(&self) -> Vec<&str> {
+        self.collector
+            .get_offline_chips()
+            .iter()
+            .filter(|name| match &self.search {
+                Some(search) => search.is_match(name),
+                None => true,
+            })
+            .map(String::as_str)
+            .collect()
+    }
+
+    pub fn get_chip_by_name(&self, name: &str) -> Option<&ChipSnapshot> {
+        self.collector.get_chip(name)
+    }
+
+    /// Chips that are not hidden by the current search filter, grouped by host
+    /// in a fleet
+    pub fn get_visible_chips(&self) -> Vec<&ChipSnapshot> {
+        let mut chips: Vec<&ChipSnapshot> = self
+            .collector
+            .get_chips()
+            .iter()
+            .filter(|chip| self.is_chip_visible(chip))
+            .collect();
+        let hosts = self.get_hosts();
+        if !hosts.is_empty() {
+            chips.sort_by_key(|chip| {
+                hosts
+                    .iter()
+                    .position(|host| Some(&host.name) == chip.host.as_ref())
+            });
+        }
+        chips
+    }
+
+    fn chip_matches(&self, chip: &ChipSnapshot) -> bool {
+        if let Some(search) = &self.search {
+            search.is_match(&chip.prefix) || search.is_match(&chip.name)
         } else {
-            self.sensors.chip_iter(None).next().unwrap()
+            true
         }
     }
 
-    pub fn get_pinned_chip(&self) -> Option<ChipRef> {
-        if let Some(pinned_chip) = self.pinned_chip {
-            self.sensors
-                .chip_iter(None)
-                .find(|chip| chip.address().unwrap() == pinned_chip)
+    pub fn is_chip_visible(&self, chip: &ChipSnapshot) -> bool {
+        self.chip_matches(chip)
+            || chip
+                .features
+                .iter()
+                .any(|feature| self.is_feature_visible(chip, &feature.label))
+    }
+
+    /// A feature is visible if its label matches the search, or if the whole chip matched
+    pub fn is_feature_visible(&self, chip: &ChipSnapshot, label: &str) -> bool {
+        if let Some(search) = &self.search {
+            search.is_feature_match(chip, label) || self.chip_matches(chip)
         } else {
-            None
+            true
         }
     }
 
-    fn get_nth_chip(&self, n: isize) -> ChipRef {
-        let max_length: usize = self.sensors.chip_iter(None).collect::<Vec<ChipRef>>().len();
-        let n = isize::min(isize::max(n, 0), (max_length - 1) as isize) as usize;
-        let (_, next_chip) = self
-            .sensors
-            .chip_iter(None)
-            .enumerate()
-            .find(|(i, _)| *i == n)
-            .unwrap();
-        next_chip
+    /// `None` only when the system has no chips at all
+    pub fn get_selected_chip(&self) -> Option<&ChipSnapshot> {
+        let visible_chips = self.get_visible_chips();
+        let selected_chip = self.selected_chip.as_ref().and_then(|selected_chip| {
+            visible_chips
+                .iter()
+                .find(|chip| chip.name == *selected_chip)
+                .copied()
+        });
+        selected_chip
+            .or(visible_chips.first().copied())
+            .or_else(|| {
+                self.selected_chip
+                    .as_ref()
+                    .and_then(|selected_chip| self.get_chip_by_name(selected_chip))
+            })
+            .or_else(|| self.collector.get_chips().first())
+    }
+
+    fn get_selected_chip_name(&self) -> Option<String> {
+        self.get_selected_chip().map(|chip| chip.name.clone())
+    }
+
+    pub fn get_selected_feature(&self) -> Option<&str> {
+        self.selected_feature.as_deref()
+    }
+
+    fn get_nth_chip(&self, n: isize) -> Option<&ChipSnapshot> {
+        let visible_chips = self.get_visible_chips();
+        let max_index = visible_chips.len().checked_sub(1)? as isize;
+        let n = isize::min(isize::max(n, 0), max_index) as usize;
+        visible_chips.get(n).copied()
     }
 
     fn get_current_chip_index(&self) -> usize {
-        let (current_chip_index, _) = self
-            .sensors
-            .chip_iter(None)
-            .enumerate()
-            .find(|(_, chip)| *chip == self.get_selected_chip())
-            .unwrap();
-        current_chip_index
+        let selected_chip = self.get_selected_chip_name();
+        self.get_visible_chips()
+            .iter()
+            .position(|chip| Some(&chip.name) == selected_chip.as_ref())
+            .unwrap_or(0)
+    }
+
+    fn select_chip(&mut self, name: Option<String>) {
+        if let Some(name) = name {
+            self.selected_chip = Some(name);
+            self.selected_feature = None;
+        }
     }
 
     pub fn select_next_chip(&mut self) {
         let current_chip_index = self.get_current_chip_index();
-        self.selected_chip = self
+        let next_chip = self
             .get_nth_chip((current_chip_index + 1) as isize)
-            .address();
+            .map(|chip| chip.name.clone());
+        self.select_chip(next_chip);
     }
 
     pub fn select_previous_chip(&mut self) {
         let current_chip_index = self.get_current_chip_index();
-        self.selected_chip = self
-            .get_nth_chip((current_chip_index.checked_sub(1).unwrap_or(0)) as isize)
-            .address();
+        let previous_chip = self
+            .get_nth_chip(current_chip_index.saturating_sub(1) as isize)
+            .map(|chip| chip.name.clone());
+        self.select_chip(previous_chip);
     }
 
-    pub fn set_pinned_chip(&mut self) {
-        if let Some(_) = self.pinned_chip {
-            self.pinned_chip = None;
-        } else {
-            let selected_chip = self.get_selected_chip();
-            self.pinned_chip = selected_chip.address();
+    /// Jumps to the first chip of the next fleet host that has visible chips
+    pub fn select_next_host(&mut self) {
+        self.select_host_by_offset(1);
+    }
+
+    pub fn select_previous_host(&mut self) {
+        self.select_host_by_offset(-1);
+    }
+
+    fn select_host_by_offset(&mut self, offset: isize) {
+        let visible_chips = self.get_visible_chips();
+        let hosts: Vec<String> = self
+            .get_hosts()
+            .into_iter()
+            .map(|host| host.name)
+            .filter(|host| visible_chips.iter().any(|chip| chip.host.as_ref() == Some(host)))
+            .collect();
+        let Some(max_index) = hosts.len().checked_sub(1) else {
+            return;
         };
+        let selected_host = self.get_selected_chip().and_then(|chip| chip.host.clone());
+        let current = hosts
+            .iter()
+            .position(|host| Some(host) == selected_host.as_ref())
+            .unwrap_or(0);
+        let next = (current as isize + offset).clamp(0, max_index as isize) as usize;
+        let chip = visible_chips
+            .iter()
+            .find(|chip| chip.host.as_ref() == Some(&hosts[next]))
+            .map(|chip| chip.name.clone());
+        self.select_chip(chip);
     }
 
-    pub fn get_historical_data(&self, label: &str) -> Option<&RingBuf<f64>> {
-        self.historical_data.get(label)
+    /// Labels of the visible charted features of the selected chip
+    fn get_selected_chip_features(&self) -> Vec<String> {
+        let Some(chip) = self.get_selected_chip() else {
+            return vec![];
+        };
+        chip.features
+            .iter()
+            .filter(|feature| feature.kind == Some(chip.charted_kind()))
+            .map(|feature| feature.label.clone())
+            .filter(|label| self.is_feature_visible(chip, label))
+            .collect()
     }
-}
 
-impl Default for AppState {
-    fn default() -> Self {
-        AppState {
-            selected_chip: None,
-            sensors: sensors::get_all_sensors().unwrap(),
-            pinned_chip: None,
-            historical_data: HashMap::new(),
+    pub fn select_next_feature(&mut self) {
+        let features = self.get_selected_chip_features();
+        let next = match &self.selected_feature {
+            Some(selected) => features
+                .iter()
+                .position(|label| label == selected)
+                .map_or(0, |index| (index + 1).min(features.len() - 1)),
+            None => 0,
+        };
+        if let Some(label) = features.get(next) {
+            self.selected_feature = Some(label.clone());
         }
     }
+
+    pub fn select_previous_feature(&mut self) {
+        let features = self.get_selected_chip_features();
+        let previous = match &self.selected_feature {
+            Some(selected) => features
+                .iter()
+                .position(|label| label == selected)
+                .map_or(0, |index| index.saturating_sub(1)),
+            None => 0,
+        };
+        if let Some(label) = features.get(previous) {
+            self.selected_feature = Some(label.clone());
+        }
+    }
+
+    pub fn get_pins(&self) -> &Pins {
+        &self.pins
+    }
+
+    pub fn toggle_pinned_chip(&mut self) {
+        if let Some(name) = self.get_selected_chip_name() {
+            self.pins.toggle(Pin::Chip(name));
+            self.save_pins();
+        }
+    }
+
+    pub fn toggle_pinned_feature(&mut self) {
+        let Some(label) = self.selected_feature.clone() else {
+            return;
+        };
+        if let Some(name) = self.get_selected_chip_name() {
+            self.pins.toggle(Pin::Feature(SensorId::new(&name, &label)));
+            self.save_pins();
+        }
+    }
+
+    pub fn is_marked(&self, sensor_id: &SensorId) -> bool {
+        self.marked_features.contains(sensor_id)
+    }
+
+    pub fn get_marked_features(&self) -> &[SensorId] {
+        &self.marked_features
+    }
+
+    /// Marks the selected feature for overlaying, marks can span several chips
+    pub fn toggle_marked_feature(&mut self) {
+        let Some(label) = self.selected_feature.clone() else {
+            return;
+        };
+        if let Some(name) = self.get_selected_chip_name() {
+            let sensor_id = SensorId::new(&name, &label);
+            if let Some(index) = self.marked_features.iter().position(|id| *id == sensor_id) {
+                self.marked_features.remove(index);
+            } else {
+                self.marked_features.push(sensor_id);
+            }
+        }
+    }
+
+    /// Pins the marked features as a single overlaid chart and clears the marks
+    pub fn pin_marked_features(&mut self) {
+        if self.marked_features.is_empty() {
+            return;
+        }
+        let group = std::mem::take(&mut self.marked_features);
+        self.pins.toggle(Pin::Group(group));
+        self.save_pins();
+    }
+
+    pub fn is_combined_chart(&self, panel: &ChartPanel) -> bool {
+        self.combined_charts.contains(panel)
+    }
+
+    fn toggle_combined_chart(&mut self, panel: ChartPanel) {
+        if !self.combined_charts.remove(&panel) {
+            self.combined_charts.insert(panel);
+        }
+    }
+
+    pub fn toggle_combined_main_chart(&mut self) {
+        self.toggle_combined_chart(ChartPanel::Main);
+    }
+
+    pub fn toggle_combined_pin_chart(&mut self) {
+        if let Some(pin) = self.pins.get_focused().cloned() {
+            self.toggle_combined_chart(ChartPanel::Pin(pin));
+        }
+    }
+
+    pub fn is_auto_scaled_panel(&self, panel: &ChartPanel) -> bool {
+        self.auto_scaled_panels.contains(panel)
+    }
+
+    pub fn is_auto_scaled_sensor(&self, sensor_id: &SensorId) -> bool {
+        self.auto_scaled_sensors.contains(sensor_id)
+    }
+
+    /// Toggles auto scaling of the selected feature's chart, or of the whole
+    /// main panel when no feature is selected
+    pub fn toggle_auto_scaled_main_chart(&mut self) {
+        let selected_chip = self.get_selected_chip_name();
+        match (selected_chip, self.selected_feature.clone()) {
+            (Some(chip), Some(label)) if !self.is_combined_chart(&ChartPanel::Main) => {
+                let sensor_id = SensorId::new(&chip, &label);
+                if !self.auto_scaled_sensors.remove(&sensor_id) {
+                    self.auto_scaled_sensors.insert(sensor_id);
+                }
+            }
+            _ => {
+                if !self.auto_scaled_panels.remove(&ChartPanel::Main) {
+                    self.auto_scaled_panels.insert(ChartPanel::Main);
+                }
+            }
+        }
+    }
+
+    pub fn toggle_auto_scaled_pin_chart(&mut self) {
+        if let Some(pin) = self.pins.get_focused().cloned() {
+            let panel = ChartPanel::Pin(pin);
+            if !self.auto_scaled_panels.remove(&panel) {
+                self.auto_scaled_panels.insert(panel);
+            }
+        }
+    }
+
+    /// Current readings for `sensor_ids` of any kind, in the same order
+    pub fn get_readings(&self, sensor_ids: &[SensorId]) -> Vec<Reading> {
+        let readings = self.collector.get_readings();
+        sensor_ids
+            .iter()
+            .filter_map(|sensor_id| readings.iter().find(|reading| reading.id == *sensor_id))
+            .cloned()
+            .collect()
+    }
+
+    pub fn focus_next_pin(&mut self) {
+        self.pins.focus_next();
+    }
+
+    pub fn focus_previous_pin(&mut self) {
+        self.pins.focus_previous();
+    }
+
+    pub fn move_focused_pin_down(&mut self) {
+        self.pins.move_focused_down();
+        self.save_pins();
+    }
+
+    pub fn move_focused_pin_up(&mut self) {
+        self.pins.move_focused_up();
+        self.save_pins();
+    }
+
+    pub fn remove_focused_pin(&mut self) {
+        self.pins.remove_focused();
+        self.save_pins();
+    }
+
+    fn save_pins(&self) {
+        let state = PersistedState {
+            pins: self.pins.get().to_vec(),
+        };
+        if let Err(e) = state.save() {
+            error!("failed to save pins: {}", e);
+        }
+    }
+
+    pub fn get_viewport(&self) -> &Viewport {
+        &self.viewport
+    }
+
+    pub fn get_viewport_mut(&mut self) -> &mut Viewport {
+        &mut self.viewport
+    }
+
+    /// The samples of `sensor_id` inside the viewport, including `current` when
+    /// live and readable
+    pub fn get_visible_samples(&self, sensor_id: &SensorId, current: Option<f64>) -> Vec<Sample> {
+        let mut samples = self
+            .collector
+            .get_historical_data(sensor_id)
+            .map(|history| self.viewport.window(&history.buf))
+            .unwrap_or_default();
+        if let Some(current) = current.filter(|_| self.viewport.is_live()) {
+            samples.push(Sample::now(current));
+        }
+        samples
+    }
+
+    pub fn get_overview_sort(&self) -> OverviewSort {
+        self.overview_sort
+    }
+
+    pub fn cycle_overview_sort(&mut self) {
+        self.overview_sort = self.overview_sort.next();
+        self.clamp_overview_selected();
+    }
+
+    pub fn get_overview_selected(&self) -> usize {
+        self.overview_selected
+    }
+
+    /// Readings of every visible sensor across all chips, in the overview's sort order
+    pub fn get_overview_readings(&self) -> Vec<Reading> {
+        let mut readings: Vec<Reading> = self
+            .collector
+            .get_chips()
+            .iter()
+            .flat_map(|chip| {
+                chip.readings()
+                    .into_iter()
+                    .filter(|reading| self.is_feature_visible(chip, &reading.id.label))
+                    .collect::<Vec<Reading>>()
+            })
+            .collect();
+        match self.overview_sort {
+            OverviewSort::Name => readings.sort_by(|a, b| {
+                (&a.id.chip, &a.id.label).cmp(&(&b.id.chip, &b.id.label))
+            }),
+            // Values only compare within a kind, so temperatures come first, then
+            // fans and so on. Highest first, unreadable sensors last
+            OverviewSort::Value => readings.sort_by(|a, b| {
+                a.kind.cmp(&b.kind).then_with(|| match (&a.value, &b.value) {
+                    (Ok(a), Ok(b)) => b.partial_cmp(a).unwrap_or(Ordering::Equal),
+                    (Ok(_), Err(_)) => Ordering::Less,
+                    (Err(_), Ok(_)) => Ordering::Greater,
+                    (Err(_), Err(_)) => Ordering::Equal,
+                })
+            }),
+            // Closest to the limit first, sensors without limits last
+            OverviewSort::Headroom => readings.sort_by(|a, b| match (a.headroom(), b.headroom()) {
+                (Some(a), Some(b)) => a.partial_cmp(&b).unwrap_or(Ordering::Equal),
+                (Some(_), None) => Ordering::Less,
+                (None, Some(_)) => Ordering::Greater,
+                (None, None) => Ordering::Equal,
+            }),
+        }
+        readings
+    }
+
+    pub fn select_next_overview_row(&mut self) {
+        let rows = self.get_overview_readings().len();
+        self.overview_selected = (self.overview_selected + 1).min(rows.saturating_sub(1));
+    }
+
+    pub fn select_previous_overview_row(&mut self) {
+        self.overview_selected = self.overview_selected.saturating_sub(1);
+    }
+
+    /// Keeps the selection on a row after the rows changed
+    fn clamp_overview_selected(&mut self) {
+        let rows = self.get_overview_readings().len();
+        self.overview_selected = self.overview_selected.min(rows.saturating_sub(1));
+    }
+
+    /// Jumps to the selected overview row's chip and feature in the main screen
+    pub fn open_overview_row(&mut self) {
+        if let Some(reading) = self.get_overview_readings().get(self.overview_selected) {
+            self.selected_chip = Some(reading.id.chip.clone());
+            self.selected_feature = Some(reading.id.label.clone());
+            self.screen = Screen::Main;
+        }
+    }
+
+    pub fn start_search(&mut self) {
+        self.input_mode = InputMode::Search;
+        if self.search.is_none() {
+            self.search = Some(Search::new(""));
+        }
+    }
+
+    pub fn push_search_char(&mut self, c: char) {
+        let mut query = self.search.as_ref().map(|s| s.query().to_string()).unwrap_or_default();
+        query.push(c);
+        self.update_search(&query);
+    }
+
+    pub fn pop_search_char(&mut self) {
+        let mut query = self.search.as_ref().map(|s| s.query().to_string()).unwrap_or_default();
+        query.pop();
+        self.update_search(&query);
+    }
+
+    fn update_search(&mut self, query: &str) {
+        self.search = Some(Search::new(query));
+        // Jump to the first match as the query is being typed
+        self.selected_feature = None;
+        self.select_match(0);
+        self.clamp_overview_selected();
+    }
+
+    pub fn confirm_search(&mut self) {
+        self.input_mode = InputMode::Normal;
+        if self.search.as_ref().is_some_and(|search| search.is_empty()) {
+            self.search = None;
+        }
+    }
+
+    pub fn cancel_search(&mut self) {
+        self.input_mode = InputMode::Normal;
+        self.search = None;
+        self.selected_feature = None;
+    }
+
+    /// Every (chip, feature label) pair matched by the current search
+    fn get_matches(&self) -> Vec<SensorId> {
+        if self.search.is_none() {
+            return vec![];
+        }
+        self.get_visible_chips()
+            .iter()
+            .flat_map(|chip| {
+                chip.features
+                    .iter()
+                    .filter(|feature| self.is_feature_visible(chip, &feature.label))
+                    .map(|feature| SensorId::new(&chip.name, &feature.label))
+                    .collect::<Vec<SensorId>>()
+            })
+            .collect()
+    }
+
+    fn select_match(&mut self, index: usize) {
+        if let Some(sensor_id) = self.get_matches().get(index) {
+            self.selected_chip = Some(sensor_id.chip.clone());
+            self.selected_feature = Some(sensor_id.label.clone());
+        }
+    }
+
+    fn get_current_match_index(&self) -> Option<usize> {
+        let selected_chip = self.get_selected_chip_name()?;
+        let selected_feature = self.selected_feature.as_ref()?;
+        self.get_matches().iter().position(|sensor_id| {
+            sensor_id.chip == selected_chip && sensor_id.label == *selected_feature
+        })
+    }
+
+    pub fn select_next_match(&mut self) {
+        let matches_len = self.get_matches().len();
+        if matches_len == 0 {
+            return;
+        }
+        let next = self
+            .get_current_match_index()
+            .map_or(0, |index| (index + 1) % matches_len);
+        self.select_match(next);
+    }
+
+    pub fn select_previous_match(&mut self) {
+        let matches_len = self.get_matches().len();
+        if matches_len == 0 {
+            return;
+        }
+        let previous = self
+            .get_current_match_index()
+            .map_or(matches_len - 1, |index| (index + matches_len - 1) % matches_len);
+        self.select_match(previous);
+    }
 }
 
 pub struct App {
@@ -106,27 +725,13 @@ pub struct App {
 }
 
 impl App {
-    pub fn new() -> Self {
+    pub fn new(source: Box<dyn SensorSource>) -> Self {
         App {
-            state: AppState::default(),
+            state: AppState::new(source),
         }
     }
 
     pub fn tick(&mut self) {
-        self.append_historical_data();
-    }
-
-    pub fn append_historical_data(&mut self) {
-        for chip in self.state.sensors.chip_iter(None) {
-            for (label, current_t) in get_temperature(&chip).iter() {
-                if let Some(entry) = self.state.historical_data.get_mut(label) {
-                    entry.put(*current_t);
-                } else {
-                    let mut ring_buf = RingBuf::new(100);
-                    ring_buf.put(*current_t);
-                    self.state.historical_data.insert(label.to_string(), ring_buf);
-                }
-            }
-        }
+        self.state.source.poll(&mut self.state.collector);
     }
 }
@@ -0,0 +1,244 @@
+use std::{
+    error::Error,
+    io::{self, ErrorKind, Write},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use clap::ValueEnum;
+use serde_json::json;
+
+use crate::{
+    collector::Collector,
+    selector::Selector,
+    sensors::{Reading, Status},
+    snapshot::ChipSnapshot,
+    source::SensorSource,
+    template::{format_reading, Template},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum BarFormat {
+    /// One JSON object per line, for a custom module without an interval
+    Waybar,
+    /// The i3bar protocol, for `status_command`
+    I3bar,
+    /// Colored with `%{F}` tags, for a `tail = true` script module
+    Polybar,
+    /// Colored with `#[fg]` styles, for `status-right` with `#(...)`
+    Tmux,
+}
+
+/// Same colors as the charts, `None` keeps the bar's own color
+fn hex_color(status: Status) -> Option<&'static str> {
+    match status {
+        Status::High => Some("#ffff00"),
+        Status::Critical => Some("#ff0000"),
+        Status::Emergency => Some("#ff00ff"),
+        Status::Normal | Status::Unknown => None,
+    }
+}
+
+fn tmux_color(status: Status) -> Option<&'static str> {
+    match status {
+        Status::High => Some("yellow"),
+        Status::Critical => Some("red"),
+        Status::Emergency => Some("magenta"),
+        Status::Normal | Status::Unknown => None,
+    }
+}
+
+/// The filled in template, the worst status of the sensors in it and a
+/// tooltip listing them
+#[derive(Debug, Clone, PartialEq)]
+pub struct BarText {
+    pub text: String,
+    pub status: Status,
+    pub tooltip: String,
+}
+
+/// The template is filled in for the whole system, see `Scope::System`
+pub fn render(template: &Template, chips: &[ChipSnapshot], time: SystemTime) -> BarText {
+    let readings = template.readings(chips, time);
+    let status = readings
+        .iter()
+        .map(Reading::status)
+        .max_by_key(Status::severity)
+        .unwrap_or(Status::Unknown);
+    let tooltip = readings
+        .iter()
+        .map(|reading| {
+            format!(
+                "{}/{}: {}{}",
+                reading.id.chip,
+                reading.id.label,
+                format_reading(reading),
+                reading.kind.unit()
+            )
+        })
+        .collect::<Vec<_>>();
+    BarText {
+        text: String::from(template.render(chips, time).trim_end_matches('\n')),
+        status,
+        tooltip: tooltip.join("\n"),
+    }
+}
+
+/// One line of output, i3bar lines still need to be joined into an array
+pub fn format_line(format: BarFormat, bar: &BarText) -> String {
+    match format {
+        BarFormat::Waybar => json!({
+            "text": bar.text,
+            "class": bar.status.name(),
+            "tooltip": bar.tooltip,
+        })
+        .to_string(),
+        BarFormat::I3bar => {
+            let mut block = json!({ "name": "senso", "full_text": bar.text });
+            if let Some(color) = hex_color(bar.status) {
+                block["color"] = json!(color);
+            }
+            format!("[{}]", block)
+        }
+        BarFormat::Polybar => match hex_color(bar.status) {
+            Some(color) => format!("%{{F{}}}{}%{{F-}}", color, bar.text),
+            None => bar.text.clone(),
+        },
+        BarFormat::Tmux => match tmux_color(bar.status) {
+            Some(color) => format!("#[fg={}]{}#[default]", color, bar.text),
+            None => bar.text.clone(),
+        },
+    }
+}
+
+fn print_lines(
+    format: BarFormat,
+    template: &Template,
+    sensors: Option<&Selector>,
+    interval: Duration,
+    source: &mut dyn SensorSource,
+) -> io::Result<()> {
+    let mut collector = Collector::default();
+    let mut stdout = io::stdout().lock();
+    if format == BarFormat::I3bar {
+        writeln!(stdout, "{{\"version\": 1}}\n[")?;
+    }
+    let mut is_first = true;
+    loop {
+        source.poll(&mut collector);
+        let chips = match sensors {
+            Some(sensors) => sensors.filter_chips(collector.get_chips()),
+            None => collector.get_chips().to_vec(),
+        };
+        let bar = render(template, &chips, SystemTime::now());
+        // i3bar updates are elements of an endless array
+        let separator = if format == BarFormat::I3bar && !is_first {
+            ","
+        } else {
+            ""
+        };
+        writeln!(stdout, "{}{}", separator, format_line(format, &bar))?;
+        stdout.flush()?;
+        is_first = false;
+        thread::sleep(interval);
+    }
+}
+
+/// Prints a line every `interval` until the bar stops reading. Only the
+/// selected sensors are looked up, `{hottest}` included
+pub fn run_bar(
+    format: BarFormat,
+    template: &Template,
+    sensors: Option<&Selector>,
+    interval: Duration,
+    mut source: Box<dyn SensorSource>,
+) -> Result<(), Box<dyn Error>> {
+    match print_lines(format, template, sensors, interval, source.as_mut()) {
+        // The bar went away, e.g. on reload
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::{format_line, render, BarFormat, BarText};
+    use crate::{
+        sensors::{SensorKind, Status},
+        snapshot::{
+            fixtures::{chip, feature, input},
+            ChipSnapshot,
+        },
+        template::{Scope, Template},
+    };
+
+    fn chips() -> Vec<ChipSnapshot> {
+        let temperature = |label: &str, input: f64| {
+            feature(
+                "temp1",
+                label,
+                SensorKind::Temperature,
+                &[("input", input), ("max", 80.0), ("crit", 100.0)],
+            )
+        };
+        vec![
+            chip("coretemp-isa-0000", vec![temperature("Package id 0", 85.4)]),
+            chip("nvme-pci-0100", vec![temperature("Composite", 40.0)]),
+            chip(
+                "it8688-isa-0a40",
+                vec![input("fan1", "fan1", SensorKind::Fan, 1234.0)],
+            ),
+        ]
+    }
+
+    fn bar(template: &str) -> BarText {
+        let template = Template::parse(template, Scope::System).unwrap();
+        render(&template, &chips(), SystemTime::now())
+    }
+
+    #[test]
+    fn test_hottest_is_a_cpu_temperature() {
+        let template = Template::parse("{hottest}", Scope::System).unwrap();
+        let bar = render(&template, &chips()[1..], SystemTime::now());
+
+        assert!(bar.text == "N/A");
+        assert!(bar.status == Status::Unknown);
+    }
+
+    #[test]
+    fn test_render_takes_the_worst_status() {
+        let bar = bar("{Package id 0}°C {fan1}rpm {nvme-pci-0100/Composite} {missing} {{");
+
+        assert!(bar.text == "85°C 1234rpm 40 N/A {");
+        assert!(bar.status == Status::High);
+        assert!(bar.tooltip.lines().count() == 3);
+    }
+
+    #[test]
+    fn test_hottest() {
+        assert!(bar("{hottest}°C").text == "85°C");
+        assert!(bar(r#"{value("Package id 0"):.1}"#).text == "85.4");
+        assert!(
+            bar(r#"{if value("*/fan1") > 1000}fan{end}"#).tooltip
+                == "it8688-isa-0a40/fan1: 1234RPM"
+        );
+    }
+
+    #[test]
+    fn test_formats() {
+        let bar = bar("{Package id 0}");
+
+        assert!(
+            format_line(BarFormat::Waybar, &bar)
+                == r#"{"class":"high","text":"85","tooltip":"coretemp-isa-0000/Package id 0: 85C"}"#
+        );
+        assert!(
+            format_line(BarFormat::I3bar, &bar)
+                == r##"[{"color":"#ffff00","full_text":"85","name":"senso"}]"##
+        );
+        assert!(format_line(BarFormat::Polybar, &bar) == "%{F#ffff00}85%{F-}");
+        assert!(format_line(BarFormat::Tmux, &bar) == "#[fg=yellow]85#[default]");
+    }
+}
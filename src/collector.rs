@@ -0,0 +1,291 @@
+use std::{collections::HashMap, mem, time::SystemTime};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    cpu::{CoreActivity, CpuState, CpuTimes, Throttling},
+    history::{Sample, HISTORY_CAPACITY},
+    ring_buffer::RingBuf,
+    sensors::{Reading, SensorError, SensorId},
+    snapshot::ChipSnapshot,
+};
+
+/// Lowest and highest values seen since senso started
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SensorStats {
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Failed reads of a sensor since senso started, reads are retried every tick
+#[derive(Debug, Clone)]
+pub struct ReadFailures {
+    pub count: usize,
+    pub last_error: SensorError,
+}
+
+/// Keeps the latest chip snapshots along with the history of every reading.
+/// Shared by the TUI, the daemon and the other modes so they sample the same way
+#[derive(Debug, Default)]
+pub struct Collector {
+    chips: Vec<ChipSnapshot>,
+    /// Chips that disappeared, their history is kept until they return
+    offline_chips: Vec<String>,
+    historical_data: HashMap<SensorId, RingBuf<Sample>>,
+    sensor_stats: HashMap<SensorId, SensorStats>,
+    read_failures: HashMap<SensorId, ReadFailures>,
+    /// Only fed by local sources
+    throttling: Throttling,
+    core_activity: CoreActivity,
+}
+
+impl Collector {
+    pub fn get_chips(&self) -> &[ChipSnapshot] {
+        &self.chips
+    }
+
+    pub fn get_chip(&self, name: &str) -> Option<&ChipSnapshot> {
+        self.chips.iter().find(|chip| chip.name == name)
+    }
+
+    pub fn get_offline_chips(&self) -> &[String] {
+        &self.offline_chips
+    }
+
+    pub fn is_chip_offline(&self, name: &str) -> bool {
+        self.offline_chips.iter().any(|chip| chip == name)
+    }
+
+    pub fn get_readings(&self) -> Vec<Reading> {
+        self.chips.iter().flat_map(ChipSnapshot::readings).collect()
+    }
+
+    pub fn get_historical_data(&self, sensor_id: &SensorId) -> Option<&RingBuf<Sample>> {
+        self.historical_data.get(sensor_id)
+    }
+
+    pub fn get_sensor_stats(&self, sensor_id: &SensorId) -> Option<&SensorStats> {
+        self.sensor_stats.get(sensor_id)
+    }
+
+    pub fn get_read_failures(&self, sensor_id: &SensorId) -> Option<&ReadFailures> {
+        self.read_failures.get(sensor_id)
+    }
+
+    pub fn get_throttling(&self) -> &Throttling {
+        &self.throttling
+    }
+
+    pub fn get_core_activity(&self) -> &CoreActivity {
+        &self.core_activity
+    }
+
+    pub fn get_total_read_failures(&self) -> usize {
+        self.read_failures
+            .values()
+            .map(|failures| failures.count)
+            .sum()
+    }
+
+    /// Replaces the current chips and records a sample for every readable sensor
+    pub fn ingest(&mut self, chips: Vec<ChipSnapshot>, time: SystemTime) {
+        self.replace_chips(None, chips, time);
+    }
+
+    /// Records the throttle counters, frequencies and load of the local CPUs
+    pub fn ingest_cpus(
+        &mut self,
+        cpus: &[CpuState],
+        times: &HashMap<usize, CpuTimes>,
+        time: SystemTime,
+    ) {
+        self.throttling.update(cpus, time);
+        self.core_activity.update(cpus, times, time);
+    }
+
+    /// Like `ingest`, but only replaces the chips of `host`, for sources that
+    /// show several hosts at once. The chips have to be tagged with `on_host`
+    pub fn ingest_host(&mut self, host: &str, chips: Vec<ChipSnapshot>, time: SystemTime) {
+        self.replace_chips(Some(host), chips, time);
+    }
+
+    fn replace_chips(&mut self, host: Option<&str>, chips: Vec<ChipSnapshot>, time: SystemTime) {
+        let (replaced, kept): (Vec<_>, Vec<_>) = mem::take(&mut self.chips)
+            .into_iter()
+            .partition(|chip| chip.host.as_deref() == host);
+        // Everything is new on the first ingest, only log chips that show up later
+        if !replaced.is_empty() {
+            for chip in chips
+                .iter()
+                .filter(|chip| !replaced.iter().any(|old_chip| old_chip.name == chip.name))
+            {
+                info!("chip added: {}", chip.name);
+            }
+        }
+        self.offline_chips
+            .retain(|name| !chips.iter().any(|chip| chip.name == *name));
+        for chip in &replaced {
+            if !chips.iter().any(|new_chip| new_chip.name == chip.name) {
+                info!("chip went offline: {}", chip.name);
+                self.offline_chips.push(chip.name.clone());
+            }
+        }
+
+        let readings: Vec<Reading> = chips.iter().flat_map(ChipSnapshot::readings).collect();
+        self.chips = kept;
+        self.chips.extend(chips);
+        for reading in readings {
+            match reading.value {
+                Ok(value) => self.put_sample(reading.id, Sample { time, value }),
+                Err(e) => self.record_failure(reading.id, e),
+            }
+        }
+    }
+
+    fn record_failure(&mut self, sensor_id: SensorId, e: SensorError) {
+        let failures = self
+            .read_failures
+            .entry(sensor_id.clone())
+            .or_insert(ReadFailures {
+                count: 0,
+                last_error: e.clone(),
+            });
+        // Log the first failure only, a broken sensor fails on every tick
+        if failures.count == 0 {
+            warn!("{}/{}: {}", sensor_id.chip, sensor_id.label, e);
+        }
+        failures.count += 1;
+        failures.last_error = e;
+    }
+
+    fn put_sample(&mut self, sensor_id: SensorId, sample: Sample) {
+        let value = sample.value;
+        self.sensor_stats
+            .entry(sensor_id.clone())
+            .and_modify(|stats| {
+                stats.min = stats.min.min(value);
+                stats.max = stats.max.max(value);
+            })
+            .or_insert(SensorStats {
+                min: value,
+                max: value,
+            });
+        if let Some(entry) = self.historical_data.get_mut(&sensor_id) {
+            entry.put(sample);
+        } else {
+            let mut ring_buf = RingBuf::new(HISTORY_CAPACITY);
+            ring_buf.put(sample);
+            self.historical_data.insert(sensor_id, ring_buf);
+        }
+    }
+
+    /// Prepends history recorded elsewhere, e.g. by a daemon before the TUI attached
+    pub fn import_history(&mut self, sensor_id: &SensorId, samples: Vec<Sample>) {
+        let existing = self
+            .historical_data
+            .remove(sensor_id)
+            .map(|history| history.buf)
+            .unwrap_or_default();
+        let newest_imported = samples.last().map(|sample| sample.time);
+        let combined = samples
+            .into_iter()
+            .chain(existing.into_iter().filter(|sample| match newest_imported {
+                Some(time) => sample.time > time,
+                None => true,
+            }));
+        for sample in combined {
+            self.put_sample(sensor_id.clone(), sample);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::Collector;
+    use crate::{
+        sensors::{SensorError, SensorId, SensorKind},
+        snapshot::{
+            fixtures::{self, input},
+            ChipSnapshot,
+        },
+    };
+
+    fn chip(name: &str, value: Result<f64, SensorError>) -> ChipSnapshot {
+        let mut chip = fixtures::chip(
+            name,
+            vec![input("temp1", "temp1", SensorKind::Temperature, 0.0)],
+        );
+        chip.features[0].sub_features[0].value = value;
+        chip
+    }
+
+    #[test]
+    fn test_ingest_records_history_and_stats() {
+        let mut collector = Collector::default();
+        collector.ingest(vec![chip("a", Ok(40.0))], SystemTime::now());
+        collector.ingest(vec![chip("a", Ok(50.0))], SystemTime::now());
+        let sensor_id = SensorId::new("a", "temp1");
+
+        assert!(
+            collector
+                .get_historical_data(&sensor_id)
+                .map(|h| h.buf.len())
+                == Some(2)
+        );
+        assert!(
+            collector
+                .get_sensor_stats(&sensor_id)
+                .map(|s| (s.min, s.max))
+                == Some((40.0, 50.0))
+        );
+    }
+
+    #[test]
+    fn test_vanished_chips_go_offline_and_come_back() {
+        let mut collector = Collector::default();
+        collector.ingest(
+            vec![chip("a", Ok(40.0)), chip("b", Ok(40.0))],
+            SystemTime::now(),
+        );
+        collector.ingest(vec![chip("a", Ok(40.0))], SystemTime::now());
+
+        assert!(collector.is_chip_offline("b"));
+        assert!(collector
+            .get_historical_data(&SensorId::new("b", "temp1"))
+            .is_some());
+
+        collector.ingest(
+            vec![chip("a", Ok(40.0)), chip("b", Ok(40.0))],
+            SystemTime::now(),
+        );
+        assert!(!collector.is_chip_offline("b"));
+    }
+
+    #[test]
+    fn test_hosts_are_ingested_separately() {
+        let mut collector = Collector::default();
+        collector.ingest_host("lab1", vec![chip("a", Ok(40.0)).on_host("lab1")], SystemTime::now());
+        collector.ingest_host("lab2", vec![chip("a", Ok(50.0)).on_host("lab2")], SystemTime::now());
+        collector.ingest_host("lab1", vec![], SystemTime::now());
+
+        assert!(collector.is_chip_offline("lab1/a"));
+        assert!(collector.get_chip("lab2/a").is_some());
+        assert!(collector.get_historical_data(&SensorId::new("lab2/a", "temp1")).is_some());
+    }
+
+    #[test]
+    fn test_read_failures_are_counted() {
+        let mut collector = Collector::default();
+        let error = SensorError::Read(String::from("EIO"));
+        collector.ingest(vec![chip("a", Err(error.clone()))], SystemTime::now());
+        collector.ingest(vec![chip("a", Err(error))], SystemTime::now());
+
+        assert!(collector.get_total_read_failures() == 2);
+        assert!(collector
+            .get_historical_data(&SensorId::new("a", "temp1"))
+            .is_none());
+    }
+}
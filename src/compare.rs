@@ -0,0 +1,454 @@
+use std::{
+    error::Error,
+    fs,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use chrono::{DateTime, FixedOffset};
+use crossterm::event::{self, Event, KeyCode, KeyModifiers};
+use ratatui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout},
+    style::{Color, Style},
+    text::{Span, Spans, Text},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+use serde::Deserialize;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+use crate::{
+    components::temperature_graphs::overlay_chart,
+    sensors::SensorId,
+    session::{cell, sensor_cell, time_above},
+    stats::mean,
+    terminal::TerminalGuard,
+};
+
+/// One line of `senso watch --format jsonl`
+#[derive(Debug, Deserialize)]
+struct Record {
+    time: String,
+    chip: String,
+    label: String,
+    value: Option<f64>,
+    unit: String,
+    #[serde(default)]
+    crit: Option<f64>,
+}
+
+/// The values of one sensor in a recording
+#[derive(Debug, Clone, PartialEq)]
+pub struct Series {
+    pub id: SensorId,
+    pub unit: String,
+    pub critical: Option<f64>,
+    /// Seconds since the first record of the recording
+    pub samples: Vec<(f64, f64)>,
+}
+
+/// What a recording shows for a sensor, over the time both recordings cover
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats {
+    pub peak: Option<f64>,
+    pub mean: Option<f64>,
+    /// `None` if the sensor has no critical threshold
+    pub time_above_crit: Option<f64>,
+}
+
+impl Series {
+    fn samples_until(&self, duration: f64) -> Vec<(f64, f64)> {
+        self.samples
+            .iter()
+            .copied()
+            .filter(|(time, _)| *time <= duration)
+            .collect()
+    }
+
+    fn stats(&self, duration: f64) -> Stats {
+        let samples = self.samples_until(duration);
+        let values = samples.iter().map(|(_, value)| *value);
+        let interval = match samples.as_slice() {
+            [.., (previous, _), (last, _)] => last - previous,
+            _ => 0.0,
+        };
+        Stats {
+            peak: values.clone().max_by(f64::total_cmp),
+            mean: mean(values),
+            time_above_crit: self
+                .critical
+                .map(|critical| time_above(&samples, critical, interval)),
+        }
+    }
+}
+
+/// A file written by `senso watch --format jsonl`, with times made relative
+/// to its first record so runs started at different times line up
+#[derive(Debug, Clone)]
+pub struct WatchRecording {
+    pub name: String,
+    pub series: Vec<Series>,
+}
+
+impl WatchRecording {
+    pub fn parse(name: &str, text: &str) -> Result<Self, String> {
+        let mut start: Option<DateTime<FixedOffset>> = None;
+        let mut series: Vec<Series> = vec![];
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: Record =
+                serde_json::from_str(line).map_err(|e| format!("{}:{}: {}", name, i + 1, e))?;
+            let time = DateTime::parse_from_rfc3339(&record.time).map_err(|e| {
+                format!("{}:{}: invalid time {:?}: {}", name, i + 1, record.time, e)
+            })?;
+            let start = *start.get_or_insert(time);
+            let Some(value) = record.value else {
+                continue;
+            };
+            let seconds = (time - start).num_milliseconds() as f64 / 1000.0;
+            let id = SensorId::new(&record.chip, &record.label);
+            match series.iter_mut().find(|series| series.id == id) {
+                Some(series) => {
+                    series.samples.push((seconds, value));
+                    series.critical = record.crit.or(series.critical);
+                }
+                None => series.push(Series {
+                    id,
+                    unit: record.unit,
+                    critical: record.crit,
+                    samples: vec![(seconds, value)],
+                }),
+            }
+        }
+        if series.is_empty() {
+            return Err(format!(
+                "{}: no readings, expected the output of senso watch --format jsonl",
+                name
+            ));
+        }
+        Ok(Self {
+            name: String::from(name),
+            series,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn Error>> {
+        let text = fs::read_to_string(path)
+            .map_err(|e| format!("can't read {}: {}", path.display(), e))?;
+        Ok(Self::parse(&path.display().to_string(), &text)?)
+    }
+
+    pub fn duration(&self) -> f64 {
+        self.series
+            .iter()
+            .flat_map(|series| series.samples.last())
+            .map(|(time, _)| *time)
+            .fold(0.0, f64::max)
+    }
+
+    fn get(&self, id: &SensorId) -> Option<&Series> {
+        self.series.iter().find(|series| series.id == *id)
+    }
+}
+
+/// A sensor found in both recordings
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensorComparison {
+    pub id: SensorId,
+    pub unit: String,
+    pub a: Stats,
+    pub b: Stats,
+}
+
+/// Two recordings lined up by the time since they started
+#[derive(Debug, Clone)]
+pub struct Comparison {
+    pub a: WatchRecording,
+    pub b: WatchRecording,
+    /// Both recordings cover this many seconds, the stats only look at those
+    pub duration: f64,
+    pub sensors: Vec<SensorComparison>,
+}
+
+impl Comparison {
+    pub fn new(a: WatchRecording, b: WatchRecording) -> Self {
+        let duration = a.duration().min(b.duration());
+        let sensors = a
+            .series
+            .iter()
+            .filter_map(|series_a| {
+                let series_b = b.get(&series_a.id)?;
+                Some(SensorComparison {
+                    id: series_a.id.clone(),
+                    unit: series_a.unit.clone(),
+                    a: series_a.stats(duration),
+                    b: series_b.stats(duration),
+                })
+            })
+            .collect();
+        Self {
+            a,
+            b,
+            duration,
+            sensors,
+        }
+    }
+
+    /// Sensors only one of the recordings has, they aren't compared
+    fn unmatched(&self) -> Vec<String> {
+        let only_in = |recording: &WatchRecording, other: &WatchRecording| {
+            recording
+                .series
+                .iter()
+                .filter(|series| other.get(&series.id).is_none())
+                .map(|series| {
+                    format!(
+                        "Only in {}: {}/{}",
+                        recording.name, series.id.chip, series.id.label
+                    )
+                })
+                .collect::<Vec<String>>()
+        };
+        let mut unmatched = only_in(&self.a, &self.b);
+        unmatched.extend(only_in(&self.b, &self.a));
+        unmatched
+    }
+
+    pub fn to_text(&self) -> String {
+        let mut lines = vec![
+            format!(
+                "A: {} ({:.1} s), B: {} ({:.1} s), compared over the first {:.1} s",
+                self.a.name,
+                self.a.duration(),
+                self.b.name,
+                self.b.duration(),
+                self.duration
+            ),
+            String::new(),
+            String::from("| Sensor | Peak A | Peak B | Δ | Mean A | Mean B | Δ | Above crit A | Above crit B | Δ |"),
+            String::from("|---|---:|---:|---:|---:|---:|---:|---:|---:|---:|"),
+        ];
+        lines.extend(self.sensors.iter().map(|sensor| {
+            let unit = sensor.unit.as_str();
+            let columns = [
+                (sensor.a.peak, sensor.b.peak, unit),
+                (sensor.a.mean, sensor.b.mean, unit),
+                (sensor.a.time_above_crit, sensor.b.time_above_crit, " s"),
+            ]
+            .map(|(a, b, unit)| {
+                format!(
+                    "{} | {} | {}",
+                    cell(a, unit),
+                    cell(b, unit),
+                    delta_cell(a, b, unit)
+                )
+            });
+            format!(
+                "| {} | {} |",
+                sensor_cell(&sensor.id.chip, &sensor.id.label),
+                columns.join(" | ")
+            )
+        }));
+        let unmatched = self.unmatched();
+        if !unmatched.is_empty() {
+            lines.push(String::new());
+            lines.extend(unmatched);
+        }
+        lines.join("\n") + "\n"
+    }
+}
+
+fn delta_cell(a: Option<f64>, b: Option<f64>, unit: &str) -> String {
+    match (a, b) {
+        (Some(a), Some(b)) => format!("{:+.1}{}", b - a, unit),
+        _ => String::from("-"),
+    }
+}
+
+fn draw_comparison<B: Backend>(f: &mut Frame<B>, comparison: &Comparison, selected: usize) {
+    let layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Percentage(30), Constraint::Percentage(70)])
+        .split(f.size());
+    let sensor_lines: Vec<Spans> = comparison
+        .sensors
+        .iter()
+        .enumerate()
+        .map(|(i, sensor)| {
+            let style = if i == selected {
+                Style::default().fg(Color::Black).bg(Color::Yellow)
+            } else {
+                Style::default()
+            };
+            Spans::from(Span::styled(
+                format!(" {}/{}", sensor.id.chip, sensor.id.label),
+                style,
+            ))
+        })
+        .collect();
+    // Keep the selection in view
+    let scroll = selected.saturating_sub(layout[0].height.saturating_sub(3) as usize) as u16;
+    let list = Paragraph::new(Text::from(sensor_lines))
+        .scroll((scroll, 0))
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .title(" Sensors | Down (J/🠋) | Up (K/🠉) | Quit (q) "),
+        );
+    f.render_widget(list, layout[0]);
+
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(5), Constraint::Length(5)])
+        .split(layout[1]);
+    let Some(sensor) = comparison.sensors.get(selected) else {
+        f.render_widget(Paragraph::new(" No sensor is in both recordings"), right[0]);
+        return;
+    };
+    let points = |recording: &WatchRecording| {
+        recording
+            .get(&sensor.id)
+            .map(|series| series.samples.clone())
+            .unwrap_or_default()
+    };
+    let series = [
+        (format!("A {}", comparison.a.name), points(&comparison.a)),
+        (format!("B {}", comparison.b.name), points(&comparison.b)),
+    ];
+    let critical = comparison
+        .a
+        .get(&sensor.id)
+        .and_then(|series| series.critical);
+    let title = format!("{}/{}", sensor.id.chip, sensor.id.label);
+    overlay_chart(f, right[0], &title, &series, critical, &sensor.unit);
+
+    let unit = sensor.unit.as_str();
+    let row = |name: &str, a: Option<f64>, b: Option<f64>, unit: &str| {
+        Spans::from(format!(
+            " {:<11} A {:>9}  B {:>9}  Δ {:>9}",
+            name,
+            cell(a, unit),
+            cell(b, unit),
+            delta_cell(a, b, unit)
+        ))
+    };
+    let table = Paragraph::new(Text::from(vec![
+        row("Peak", sensor.a.peak, sensor.b.peak, unit),
+        row("Mean", sensor.a.mean, sensor.b.mean, unit),
+        row(
+            "Above crit",
+            sensor.a.time_above_crit,
+            sensor.b.time_above_crit,
+            " s",
+        ),
+    ]))
+    .block(
+        Block::default()
+            .borders(Borders::ALL)
+            .title(format!(" First {:.1} s of both ", comparison.duration)),
+    );
+    f.render_widget(table, right[1]);
+}
+
+/// Shows the sensors of both recordings overlaid, one at a time
+pub fn run_compare_gui(comparison: &Comparison) -> Result<(), Box<dyn Error>> {
+    let should_quit = Arc::new(AtomicBool::new(false));
+    for signal in [SIGTERM, SIGHUP, SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(&should_quit))?;
+    }
+    let mut terminal = TerminalGuard::new()?;
+    let mut selected = 0;
+    while !should_quit.load(Ordering::Relaxed) {
+        terminal.draw(|f| draw_comparison(f, comparison, selected))?;
+        if !event::poll(Duration::from_millis(250))? {
+            continue;
+        }
+        let Event::Key(key_event) = event::read()? else {
+            continue;
+        };
+        match key_event.code {
+            KeyCode::Char('c') if key_event.modifiers.contains(KeyModifiers::CONTROL) => break,
+            KeyCode::Esc | KeyCode::Char('q') => break,
+            KeyCode::Down | KeyCode::Char('j') => {
+                selected = (selected + 1).min(comparison.sensors.len().saturating_sub(1));
+            }
+            KeyCode::Up | KeyCode::Char('k') => selected = selected.saturating_sub(1),
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Comparison, WatchRecording};
+
+    fn record(second: u32, label: &str, value: f64) -> String {
+        format!(
+            r#"{{"time":"2024-05-01T10:00:{:02}.000+02:00","chip":"coretemp-isa-0000","label":"{}","kind":"temperature","value":{},"unit":"C","status":"normal","max":80.0,"crit":90.0}}"#,
+            second, label, value
+        )
+    }
+
+    fn recording(name: &str, values: &[f64]) -> WatchRecording {
+        let mut lines: Vec<String> = values
+            .iter()
+            .enumerate()
+            .map(|(i, value)| record(i as u32, "Package id 0", *value))
+            .collect();
+        lines.push(record(0, &format!("only in {}", name), 40.0));
+        WatchRecording::parse(name, &lines.join("\n")).unwrap()
+    }
+
+    #[test]
+    fn test_parse() {
+        let recording = recording("a.jsonl", &[40.0, 50.0, 60.0]);
+
+        assert!(recording.series.len() == 2);
+        assert!(recording.series[0].samples == [(0.0, 40.0), (1.0, 50.0), (2.0, 60.0)]);
+        assert!(recording.series[0].critical == Some(90.0));
+        assert!(recording.duration() == 2.0);
+        assert!(WatchRecording::parse("a.jsonl", "").is_err());
+        assert!(WatchRecording::parse("a.jsonl", "{}")
+            .unwrap_err()
+            .starts_with("a.jsonl:1:"));
+    }
+
+    #[test]
+    fn test_compare_over_common_duration() {
+        let a = recording("a.jsonl", &[40.0, 95.0, 92.0, 60.0, 99.0]);
+        let b = recording("b.jsonl", &[40.0, 80.0, 91.0, 70.0]);
+        let comparison = Comparison::new(a, b);
+        let sensor = &comparison.sensors[0];
+
+        assert!(comparison.duration == 3.0);
+        assert!(comparison.sensors.len() == 1);
+        assert!(sensor.a.peak == Some(95.0));
+        assert!(sensor.b.peak == Some(91.0));
+        assert!(sensor.a.mean == Some(71.75));
+        assert!(sensor.a.time_above_crit == Some(2.0));
+        assert!(sensor.b.time_above_crit == Some(1.0));
+    }
+
+    #[test]
+    fn test_text() {
+        let comparison = Comparison::new(
+            recording("a.jsonl", &[40.0, 95.0, 92.0, 60.0]),
+            recording("b.jsonl", &[40.0, 80.0, 91.0, 70.0]),
+        );
+        let text = comparison.to_text();
+
+        assert!(text.contains(
+            "| coretemp-isa-0000/Package id 0 | 95.0C | 91.0C | -4.0C | 71.8C | 70.2C | -1.5C | 2.0 s | 1.0 s | -1.0 s |"
+        ));
+        assert!(text.contains("Only in a.jsonl: coretemp-isa-0000/only in a.jsonl"));
+        assert!(text.contains("Only in b.jsonl: coretemp-isa-0000/only in b.jsonl"));
+    }
+}
@@ -1,41 +1,132 @@
-use lm_sensors::{prelude::SharedChip, feature};
-use ratatui::{widgets::{Paragraph, Block, Borders}, text::Text, backend::Backend, Frame, layout::Rect};
+use ratatui::{widgets::{Paragraph, Block, Borders}, text::{Spans, Span, Text}, backend::Backend, Frame, layout::Rect, style::{Style, Color, Modifier}};
 
-use crate::app::App;
+use chrono::{DateTime, Local};
 
-use super::chip_list::ChipListProps;
+use crate::{app::App, cpu::is_cpu_chip, sensors::SensorId};
 
+use super::{chip_list::ChipListProps, overview::status_color};
 
 pub fn chip_info_panel<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect, props: &ChipListProps) {
-    let chip = if props.is_pinned_chip_view {
-        app.state.get_pinned_chip().unwrap()
-    } else {
-        app.state.get_selected_chip()
-    };
+    let chip = props.chip;
+    let chip_name = &chip.name;
     let feature_spans = chip
-        .feature_iter()
+        .features
+        .iter()
+        .filter(|feature| feature.kind == Some(chip.charted_kind()))
         .filter_map(|feature| {
-            if feature.kind() == Some(feature::Kind::Temperature) {
-                let temperatures: String = feature
-                    .sub_feature_iter()
-                    .filter_map(|sub_feature| {
-                        if let (Some(Ok(name)), Ok(value)) =
-                            (sub_feature.name(), sub_feature.value())
-                        {
-                            Some(format!("\n [{} {}]", name, value))
+            let label = &feature.label;
+            if !props.is_feature_shown(app, label) {
+                return None;
+            }
+            let is_highlighted = !props.is_pinned_chip_view
+                && app.state.get_selected_feature() == Some(label.as_str());
+            let label_style = if is_highlighted {
+                Style::default().fg(Color::Black).bg(Color::Yellow)
+            } else {
+                Style::default().add_modifier(Modifier::BOLD)
+            };
+            let mark = if app.state.is_marked(&SensorId::new(chip_name, label)) {
+                "● "
+            } else {
+                ""
+            };
+            let mut lines = vec![Spans::from(Span::styled(format!(" {}{} ", mark, label), label_style))];
+            let input_color = feature
+                .reading(chip_name)
+                .map(|reading| status_color(reading.status()))
+                .unwrap_or(Color::Gray);
+            let unit = feature.kind.map_or("", |kind| kind.unit());
+            lines.extend(feature.sub_features.iter().map(|sub_feature| {
+                let name = sub_feature.short_name();
+                let value = match &sub_feature.value {
+                    Err(e) => Span::styled(format!("N/A ({})", e), Style::default().fg(Color::Red)),
+                    Ok(_) if sub_feature.is_alarm() => {
+                        if sub_feature.is_active_alarm() {
+                            Span::styled("ALARM", Style::default().fg(Color::White).bg(Color::Red))
                         } else {
-                            None
+                            Span::from("ok")
                         }
-                    })
-                    .collect();
-                Some(format!(" {} {}\n", feature.label().unwrap(), temperatures))
-            } else {
-                None
+                    }
+                    Ok(value) => {
+                        let unit = if sub_feature.has_unit() { unit } else { "" };
+                        Span::styled(
+                            format!("{}{}", value, unit),
+                            Style::default().fg(threshold_color(name, input_color)),
+                        )
+                    }
+                };
+                Spans::from(vec![Span::from(format!("   {:<10} ", name)), value])
+            }));
+            let read_failures = app
+                .state
+                .get_collector()
+                .get_read_failures(&SensorId::new(chip_name, label));
+            if let Some(failures) = read_failures {
+                lines.push(Spans::from(Span::styled(
+                    format!("   {} failed reads, last: {}", failures.count, failures.last_error),
+                    Style::default().fg(Color::Red),
+                )));
             }
+            lines.push(Spans::default());
+            Some(lines)
         })
-        .collect::<String>();
+        .flatten()
+        .collect::<Vec<Spans>>();
+    let feature_spans = if feature_spans.is_empty() {
+        vec![Spans::from(format!(" No {} sensors", chip.charted_kind().name()))]
+    } else {
+        feature_spans
+    };
+    let feature_spans = if is_cpu_chip(chip) {
+        let mut spans = throttling_spans(app);
+        spans.extend(feature_spans);
+        spans
+    } else {
+        feature_spans
+    };
 
     let paragraph = Paragraph::new(Text::from(feature_spans)).block(Block::default().borders(Borders::ALL).title("Sensor Details"));
 
     f.render_widget(paragraph, area)
 }
+
+/// Whether a hot package actually lost performance, only known for local CPUs
+fn throttling_spans(app: &App) -> Vec<Spans<'static>> {
+    let throttling = app.state.get_collector().get_throttling();
+    if !throttling.is_available() {
+        return vec![];
+    }
+    let mut lines = vec![Spans::from(Span::styled(" Throttling ", Style::default().add_modifier(Modifier::BOLD)))];
+    let count = match throttling.since_start() {
+        Some(counters) if counters.total() > 0 => Span::styled(
+            format!("{} (core {}, package {})", counters.total(), counters.core, counters.package),
+            Style::default().fg(Color::White).bg(Color::Red),
+        ),
+        Some(_) => Span::from("0"),
+        None => Span::styled("not reported", Style::default().fg(Color::Gray)),
+    };
+    lines.push(Spans::from(vec![Span::from(format!("   {:<10} ", "count")), count]));
+    if let Some(time) = throttling.last_event() {
+        let time = DateTime::<Local>::from(time).format("%H:%M:%S").to_string();
+        lines.push(Spans::from(format!("   {:<10} {}", "last", time)));
+    }
+    if let Some(frequency) = throttling.frequency() {
+        lines.push(Spans::from(format!(
+            "   {:<10} {:.0} / {:.0} MHz",
+            "frequency", frequency.current, frequency.max
+        )));
+    }
+    lines.push(Spans::default());
+    lines
+}
+
+/// Same colors as the reference lines drawn on the charts
+fn threshold_color(name: &str, input_color: Color) -> Color {
+    match name {
+        "input" => input_color,
+        "max" => Color::LightYellow,
+        "crit" => Color::LightRed,
+        "emergency" => Color::LightMagenta,
+        _ => Color::Gray,
+    }
+}
@@ -1,41 +1,149 @@
-use lm_sensors::{prelude::SharedChip, ChipRef};
-use ratatui::{Frame, layout::Rect, backend::Backend, widgets::{List, ListItem, Block, Borders}, text::Text, style::Style};
+use ratatui::{Frame, layout::Rect, backend::Backend, widgets::{List, ListItem, Block, Borders}, text::{Span, Spans, Text}, style::{Color, Modifier, Style}};
 
-use crate::app::App;
+use crate::{app::App, snapshot::ChipSnapshot, source::{HostStatus, SourceStatus}};
 
-pub struct ChipListProps {
+use super::overview::status_color;
+
+pub struct ChipListProps<'a> {
+    pub chip: &'a ChipSnapshot,
+    /// Restricts the panel to a single feature, for pinned sensors
+    pub feature: Option<&'a str>,
     pub is_pinned_chip_view: bool,
+    /// Overlay all features on one chart instead of one chart per feature
+    pub is_combined: bool,
+    /// Fit the y axis to the visible values instead of 0 to the highest limit
+    pub is_auto_scaled: bool,
+}
+
+impl<'a> ChipListProps<'a> {
+    /// Pinned views ignore the search filter so pins never disappear
+    pub fn is_feature_shown(&self, app: &App, label: &str) -> bool {
+        match self.feature {
+            Some(feature) => feature == label,
+            None if self.is_pinned_chip_view => true,
+            None => app.state.is_feature_visible(self.chip, label),
+        }
+    }
 }
 
 pub fn chip_list<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect, props: &ChipListProps) {
-    let sensors = app.state.get_sensors();
     let lower_block = Block::default().title("Sensors List").borders(Borders::ALL);
-    let selected_chip = if props.is_pinned_chip_view {
-        app.state.get_pinned_chip().unwrap()
+    let hosts = app.state.get_hosts();
+    let chip_list_items = if hosts.is_empty() {
+        chip_list_items(app, props)
     } else {
-        app.state.get_selected_chip()
+        host_list_items(app, props, &hosts)
     };
-    let chip_list_items: Vec<ListItem> = sensors
-        .chip_iter(None)
-        .map(|chip| {
-            chip_list_item(
-                chip,
-                chip.address() == selected_chip.address(),
-            )
-        })
-        .collect();
 
-    let list = List::new(chip_list_items).block(lower_block);
+    let list = if chip_list_items.is_empty() {
+        List::new(vec![ListItem::new(" No matches")])
+    } else {
+        List::new(chip_list_items)
+    }
+    .block(lower_block);
     f.render_widget(list, area);
 }
 
-pub fn chip_list_item(chip: ChipRef, is_highlighted: bool) -> ListItem {
-    let formatted_string = format!(
-        "{}/{}",
-        chip.prefix().unwrap().ok().unwrap(),
-        chip.name().unwrap(),
-    );
-    let text = Text::from(formatted_string);
+fn chip_list_items<'a>(app: &'a App, props: &ChipListProps) -> Vec<ListItem<'a>> {
+    let mut items: Vec<ListItem> = app
+        .state
+        .get_visible_chips()
+        .into_iter()
+        .map(|chip| chip_list_item(chip, chip.name == props.chip.name, false))
+        .collect();
+    items.extend(app.state.get_visible_offline_chips().into_iter().map(offline_chip_item));
+    items
+}
+
+/// Fleets list the hosts with their hottest sensor, only the selected host is
+/// expanded into its chips
+fn host_list_items<'a>(
+    app: &'a App,
+    props: &ChipListProps,
+    hosts: &[HostStatus],
+) -> Vec<ListItem<'a>> {
+    let visible_chips = app.state.get_visible_chips();
+    let is_searching = app.state.get_search().is_some();
+    let mut items = vec![];
+    for host in hosts {
+        let chips: Vec<&ChipSnapshot> = visible_chips
+            .iter()
+            .filter(|chip| chip.host.as_ref() == Some(&host.name))
+            .copied()
+            .collect();
+        if is_searching && chips.is_empty() {
+            continue;
+        }
+        let is_selected = props.chip.host.as_ref() == Some(&host.name);
+        items.push(host_list_item(app, host, &chips, is_selected));
+        if is_selected {
+            items.extend(
+                chips
+                    .iter()
+                    .map(|chip| chip_list_item(chip, chip.name == props.chip.name, true)),
+            );
+            let prefix = format!("{}/", host.name);
+            items.extend(
+                app.state
+                    .get_visible_offline_chips()
+                    .into_iter()
+                    .filter_map(|name| name.strip_prefix(&prefix))
+                    .map(|name| offline_chip_item(&format!("  {}", name))),
+            );
+        }
+    }
+    items
+}
+
+fn host_list_item<'a>(
+    app: &App,
+    host: &HostStatus,
+    chips: &[&ChipSnapshot],
+    is_selected: bool,
+) -> ListItem<'a> {
+    let marker = if is_selected { "▾ " } else { "▸ " };
+    let mut spans = vec![Span::styled(
+        format!("{}{}", marker, host.name),
+        Style::default().add_modifier(Modifier::BOLD),
+    )];
+    match &host.status {
+        SourceStatus::Live => {
+            if let Some(reading) = app.state.get_hottest_reading(&host.name) {
+                if let Ok(value) = reading.value {
+                    spans.push(Span::from(" "));
+                    spans.push(Span::styled(
+                        format!("{:.1}{} {}", value, reading.kind.unit(), reading.id.label),
+                        Style::default().fg(status_color(reading.status())),
+                    ));
+                }
+            }
+        }
+        SourceStatus::Stale { .. } => {
+            spans.push(Span::from(" "));
+            let style = Style::default().fg(Color::Black).bg(Color::Yellow);
+            spans.push(Span::styled("unreachable", style));
+        }
+    }
+    if chips.iter().any(|chip| chip.has_active_alarm()) {
+        spans.push(Span::from(" "));
+        spans.push(Span::styled("ALARM", Style::default().fg(Color::White).bg(Color::Red)));
+    }
+    ListItem::new(Text::from(Spans::from(spans)))
+}
+
+fn offline_chip_item<'a>(name: &str) -> ListItem<'a> {
+    ListItem::new(format!("{} (offline)", name)).style(Style::default().fg(Color::DarkGray))
+}
+
+/// `is_nested` indents chips listed under their host
+pub fn chip_list_item(chip: &ChipSnapshot, is_highlighted: bool, is_nested: bool) -> ListItem {
+    let indent = if is_nested { "  " } else { "" };
+    let mut spans = vec![Span::from(format!("{}{}", indent, chip.display_name()))];
+    if chip.has_active_alarm() {
+        spans.push(Span::from(" "));
+        spans.push(Span::styled("ALARM", Style::default().fg(Color::White).bg(Color::Red)));
+    }
+    let text = Text::from(Spans::from(spans));
     let style = if is_highlighted {
         Some(Style {
             bg: Some(ratatui::style::Color::White),
@@ -0,0 +1,201 @@
+use std::{collections::HashMap, time::SystemTime};
+
+use ratatui::{
+    backend::Backend,
+    layout::{Constraint, Direction, Layout, Rect},
+    style::{Color, Style},
+    symbols,
+    widgets::{Axis, Block, Chart, Dataset, GraphType, Paragraph},
+    Frame,
+};
+
+use crate::{
+    app::App,
+    cpu::{chip_package, CoreHistory},
+    history::Sample,
+    ring_buffer::RingBuf,
+    sensors::SensorKind,
+};
+
+use super::{
+    chip_list::ChipListProps,
+    temperature_graphs::{y_bounds, y_labels, SERIES_COLORS},
+};
+
+/// Load and frequency of the cores, on the same x axis as the temperature charts
+pub fn cpu_panel<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect, props: &ChipListProps) {
+    let collector = app.state.get_collector();
+    let chip = props.chip;
+    // Core labels repeat on every package, only the chip's own cores belong here
+    let package = chip_package(chip, collector.get_chips());
+    let package_cores: Vec<&CoreHistory> = collector
+        .get_core_activity()
+        .cores()
+        .filter(|core| match package {
+            Some(package) => core.package_id == package,
+            None => true,
+        })
+        .collect();
+    // Where coretemp labels the cores, follow the features shown next to it
+    let has_core_labels = package_cores.iter().any(|core| {
+        chip.features
+            .iter()
+            .any(|feature| feature.label == core.label)
+    });
+    let cores: Vec<&CoreHistory> = package_cores
+        .into_iter()
+        .filter(|core| !has_core_labels || props.is_feature_shown(app, &core.label))
+        .collect();
+    if cores.is_empty() {
+        f.render_widget(
+            Paragraph::new(" No CPU load, it is only sampled for local CPUs"),
+            area,
+        );
+        return;
+    }
+
+    // Samples are taken on the same ticks, so they line up by time with the
+    // temperatures charted for this chip
+    let reference = chip
+        .readings()
+        .into_iter()
+        .find(|reading| {
+            reading.kind == SensorKind::Temperature
+                && props.is_feature_shown(app, &reading.id.label)
+        })
+        .map(|reading| {
+            app.state
+                .get_visible_samples(&reading.id, reading.value.ok())
+        })
+        .filter(|samples| !samples.is_empty());
+    let x_positions: HashMap<SystemTime, usize> = reference
+        .iter()
+        .flatten()
+        .enumerate()
+        .map(|(x, sample)| (sample.time, x))
+        .collect();
+    let points = |history: &RingBuf<Sample>| -> Vec<(f64, f64)> {
+        match reference.as_ref().and_then(|samples| samples.first()) {
+            Some(start) => {
+                let mut points: Vec<(f64, f64)> = history
+                    .buf
+                    .iter()
+                    .rev()
+                    .take_while(|sample| sample.time >= start.time)
+                    .filter_map(|sample| {
+                        let x = x_positions.get(&sample.time)?;
+                        Some((*x as f64, sample.value))
+                    })
+                    .collect();
+                points.reverse();
+                points
+            }
+            None => app
+                .state
+                .get_viewport()
+                .window(&history.buf)
+                .iter()
+                .enumerate()
+                .map(|(x, sample)| (x as f64, sample.value))
+                .collect(),
+        }
+    };
+    let loads: Vec<(&str, Vec<(f64, f64)>)> = cores
+        .iter()
+        .map(|core| (core.label.as_str(), points(&core.load)))
+        .collect();
+    let frequencies: Vec<(&str, Vec<(f64, f64)>)> = cores
+        .iter()
+        .map(|core| (core.label.as_str(), points(&core.frequency)))
+        .collect();
+
+    let width = app.state.get_viewport().width() as f64;
+    let layout = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Ratio(1, 2), Constraint::Ratio(1, 2)])
+        .split(area);
+    let latest = |series: &[(&str, Vec<(f64, f64)>)]| {
+        let values: Vec<f64> = series
+            .iter()
+            .flat_map(|(_, points)| points.last())
+            .map(|(_, value)| *value)
+            .collect();
+        match values.len() {
+            0 => String::from("N/A"),
+            len => format!("{:.0}", values.iter().sum::<f64>() / len as f64),
+        }
+    };
+    core_chart(
+        f,
+        layout[0],
+        format!("Load {}%", latest(&loads)),
+        &loads,
+        [0.0, 100.0],
+        "%",
+        width,
+    );
+    let max_frequency = collector
+        .get_throttling()
+        .frequency()
+        .map(|frequency| frequency.max)
+        .filter(|max| *max > 0.0)
+        .unwrap_or_else(|| {
+            frequencies
+                .iter()
+                .flat_map(|(_, points)| points)
+                .map(|(_, value)| *value)
+                .fold(1000.0, f64::max)
+        });
+    let bounds = y_bounds(
+        frequencies
+            .iter()
+            .flat_map(|(_, points)| points)
+            .map(|(_, value)| *value),
+        max_frequency,
+        props.is_auto_scaled,
+    );
+    core_chart(
+        f,
+        layout[1],
+        format!("Frequency {} MHz", latest(&frequencies)),
+        &frequencies,
+        bounds,
+        " MHz",
+        width,
+    );
+}
+
+fn core_chart<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    title: String,
+    series: &[(&str, Vec<(f64, f64)>)],
+    bounds: [f64; 2],
+    unit: &str,
+    width: f64,
+) {
+    let datasets = series
+        .iter()
+        .enumerate()
+        .map(|(i, (label, points))| {
+            Dataset::default()
+                .name(*label)
+                .marker(symbols::Marker::Braille)
+                .graph_type(GraphType::Line)
+                .style(Style::default().fg(SERIES_COLORS[i % SERIES_COLORS.len()]))
+                .data(points)
+        })
+        .collect::<Vec<Dataset>>();
+    let chart = Chart::new(datasets)
+        .block(Block::default().title(title))
+        .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 1)))
+        .x_axis(Axis::default().bounds([0.0, width]))
+        .y_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::White))
+                .labels(y_labels(bounds, unit))
+                .bounds(bounds),
+        );
+
+    f.render_widget(chart, area);
+}
@@ -0,0 +1,41 @@
+use log::Level;
+use ratatui::{
+    backend::Backend,
+    layout::Rect,
+    style::{Color, Style},
+    text::{Span, Spans},
+    widgets::{Block, Borders, Paragraph},
+    Frame,
+};
+
+use crate::logger::recent_entries;
+
+fn level_color(level: Level) -> Color {
+    match level {
+        Level::Error => Color::Red,
+        Level::Warn => Color::Yellow,
+        Level::Info => Color::Blue,
+        Level::Debug | Level::Trace => Color::Gray,
+    }
+}
+
+/// The newest log records that fit into `area`
+pub fn log_pane<B: Backend>(f: &mut Frame<B>, area: Rect) {
+    let entries = recent_entries();
+    let visible_lines = area.height.saturating_sub(2) as usize;
+    let lines = entries[entries.len().saturating_sub(visible_lines)..]
+        .iter()
+        .map(|entry| {
+            Spans::from(vec![
+                Span::styled(entry.time.clone(), Style::default().fg(Color::DarkGray)),
+                Span::from(" "),
+                Span::styled(format!("{:<5}", entry.level), Style::default().fg(level_color(entry.level))),
+                Span::styled(format!(" {}: ", entry.target), Style::default().fg(Color::DarkGray)),
+                Span::from(entry.message.clone()),
+            ])
+        })
+        .collect::<Vec<Spans>>();
+
+    let paragraph = Paragraph::new(lines).block(Block::default().borders(Borders::ALL).title("Log"));
+    f.render_widget(paragraph, area);
+}
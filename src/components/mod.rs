@@ -1,3 +1,6 @@
 pub mod temperature_graphs;
 pub mod chip_info;
 pub mod chip_list;
+pub mod cpu_panel;
+pub mod log_pane;
+pub mod overview;
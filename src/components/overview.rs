@@ -0,0 +1,123 @@
+use ratatui::{
+    backend::Backend,
+    layout::{Constraint, Rect},
+    style::{Color, Modifier, Style},
+    widgets::{Block, Borders, Cell, Row, Table, TableState},
+    Frame,
+};
+
+use crate::{app::App, sensors::Status};
+
+const SPARKLINE_BARS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+const SPARKLINE_WIDTH: usize = 30;
+
+pub fn status_color(status: Status) -> Color {
+    match status {
+        Status::Normal => Color::Blue,
+        Status::High => Color::Yellow,
+        Status::Critical => Color::Red,
+        Status::Emergency => Color::Magenta,
+        Status::Unknown => Color::Gray,
+    }
+}
+
+/// Renders the last `width` values as unicode bars, scaled to their own min/max
+pub fn sparkline(values: &[f64], width: usize) -> String {
+    let values = &values[values.len().saturating_sub(width)..];
+    let min = values.iter().cloned().fold(f64::INFINITY, f64::min);
+    let max = values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+    values
+        .iter()
+        .map(|value| {
+            let index = if max > min {
+                ((value - min) / (max - min) * (SPARKLINE_BARS.len() - 1) as f64).round() as usize
+            } else {
+                0
+            };
+            SPARKLINE_BARS[index.min(SPARKLINE_BARS.len() - 1)]
+        })
+        .collect()
+}
+
+pub fn overview<B: Backend>(app: &App, f: &mut Frame<B>, area: Rect) {
+    let readings = app.state.get_overview_readings();
+    let header = Row::new(vec![
+        "Chip", "Sensor", "Value", "Min", "Max", "Headroom", "Status", "History",
+    ])
+    .style(Style::default().add_modifier(Modifier::BOLD));
+
+    let rows = readings.iter().map(|reading| {
+        let unit = reading.kind.unit();
+        let collector = app.state.get_collector();
+        let stats = collector.get_sensor_stats(&reading.id);
+        let history = collector
+            .get_historical_data(&reading.id)
+            .map(|history| {
+                let values: Vec<f64> = history.buf.iter().map(|sample| sample.value).collect();
+                sparkline(&values, SPARKLINE_WIDTH)
+            })
+            .unwrap_or_default();
+        let status = reading.status();
+        let color = status_color(status);
+        Row::new(vec![
+            Cell::from(reading.id.chip.clone()),
+            Cell::from(reading.id.label.clone()),
+            Cell::from(match &reading.value {
+                Ok(value) => format!("{:.1}{}", value, unit),
+                Err(_) => String::from("N/A"),
+            })
+            .style(Style::default().fg(color)),
+            Cell::from(stats.map(|s| format!("{:.1}{}", s.min, unit)).unwrap_or_default()),
+            Cell::from(stats.map(|s| format!("{:.1}{}", s.max, unit)).unwrap_or_default()),
+            Cell::from(
+                reading
+                    .headroom()
+                    .map(|headroom| format!("{:.1}{}", headroom, unit))
+                    .unwrap_or_else(|| String::from("-")),
+            ),
+            if reading.is_alarm {
+                Cell::from("ALARM").style(Style::default().fg(Color::White).bg(Color::Red))
+            } else {
+                Cell::from(format!("{:?}", status)).style(Style::default().fg(color))
+            },
+            Cell::from(history).style(Style::default().fg(color)),
+        ])
+    });
+
+    let widths = [
+        Constraint::Percentage(18),
+        Constraint::Percentage(14),
+        Constraint::Percentage(9),
+        Constraint::Percentage(9),
+        Constraint::Percentage(9),
+        Constraint::Percentage(9),
+        Constraint::Percentage(8),
+        Constraint::Percentage(24),
+    ];
+    let title = format!("Overview (sorted by {})", app.state.get_overview_sort().name());
+    let table = Table::new(rows)
+        .header(header)
+        .widths(&widths)
+        .block(Block::default().borders(Borders::ALL).title(title))
+        .highlight_style(Style::default().bg(Color::White).fg(Color::Black));
+
+    let mut table_state = TableState::default();
+    table_state.select(Some(app.state.get_overview_selected()));
+    f.render_stateful_widget(table, area, &mut table_state);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::sparkline;
+
+    #[test]
+    fn test_sparkline_scales_to_range() {
+        assert!(sparkline(&[40.0, 45.0, 50.0], 10) == "▁▅█");
+    }
+
+    #[test]
+    fn test_sparkline_truncates_to_width() {
+        assert!(sparkline(&[1.0, 2.0, 3.0, 3.0], 2) == "▁▁");
+        assert!(sparkline(&[], 2).is_empty());
+    }
+}
@@ -1,116 +1,473 @@
 use std::iter::zip;
 
 use itertools::Itertools;
-use lm_sensors::{feature, prelude::SharedChip, value::Kind, ChipRef};
 use ratatui::{
     backend::Backend,
     layout::{Constraint, Layout, Rect},
     style::{Color, Style},
     symbols,
-    widgets::{Axis, Block, Chart, Dataset},
+    widgets::{Axis, Block, Chart, Dataset, Paragraph},
     Frame, text::Span,
 };
 
-use crate::{app::App, utils::get_sub_feature};
-
-use super::chip_list::ChipListProps;
-
-pub fn get_temperature(chip: &ChipRef) -> Vec<(String, f64)> {
-    chip.feature_iter()
-        .filter(|feature| matches!(feature.kind(), Some(feature::Kind::Temperature)))
-        .map(|feature| {
-            let temperature: f64 = get_sub_feature(&feature, Kind::TemperatureInput).unwrap_or(0.0);
+use crate::{
+    app::App,
+    cpu::is_cpu_chip,
+    history::Sample,
+    sensors::{Reading, SensorKind, DEFAULT_TEMPERATURE_CRITICAL},
+};
 
-            (feature.label().unwrap(), temperature)
-        })
-        .collect()
-}
+use super::{chip_list::ChipListProps, overview::status_color};
 
-pub fn temperature_graphs<'a, B: Backend>(
+pub fn temperature_graphs<B: Backend>(
     app: &App,
     f: &mut Frame<B>,
     area: Rect,
     props: &ChipListProps,
 ) {
-    let chip = if props.is_pinned_chip_view {
-        app.state.get_pinned_chip().unwrap()
-    } else {
-        app.state.get_selected_chip()
-    };
-    let data = get_temperature(&chip);
+    let chip = props.chip;
+    let readings: Vec<Reading> = chip
+        .readings()
+        .into_iter()
+        .filter(|reading| reading.kind == chip.charted_kind())
+        .filter(|reading| props.is_feature_shown(app, &reading.id.label))
+        .collect();
+    if readings.is_empty() {
+        let message = format!(" No {} sensors", chip.charted_kind().name());
+        f.render_widget(Paragraph::new(message), area);
+        return;
+    }
+    if props.is_combined {
+        // Overlay the chip's marked features, or all of them if none are marked
+        let has_marks = app
+            .state
+            .get_marked_features()
+            .iter()
+            .any(|sensor_id| sensor_id.chip == chip.name);
+        let readings: Vec<Reading> = readings
+            .into_iter()
+            .filter(|reading| !has_marks || app.state.is_marked(&reading.id))
+            .collect();
+        combined_chart(app, f, area, &readings, "Combined", props.is_auto_scaled);
+        return;
+    }
 
     let layout = Layout::default()
         .direction(ratatui::layout::Direction::Vertical)
         .constraints(
-            data.iter()
-                .map(|_| Constraint::Ratio(1, data.len() as u32))
+            readings
+                .iter()
+                .map(|_| Constraint::Ratio(1, readings.len() as u32))
                 .collect::<Vec<Constraint>>(),
         )
         .split(area);
 
-    charts(&app, f, &layout, props);
+    charts(app, f, &readings, &layout, props);
 }
 
-fn charts<B: Backend>(app: &App, f: &mut Frame<B>, layout: &[Rect], props: &ChipListProps) {
-    let chip = if props.is_pinned_chip_view {
-        app.state.get_pinned_chip().unwrap()
-    } else {
-        app.state.get_selected_chip()
-    };
-    for ((label, current_t), area) in zip(get_temperature(&chip).iter(), layout) {
-        let feature = chip.feature_iter().find(|feature| {
-            feature.label().unwrap() == *label
-        }).unwrap();
-        let max_t = get_sub_feature(&feature, Kind::TemperatureCritical).unwrap_or(100.0);
-        let max_t_label = format!("{}C", &max_t);
-        let half_max_t_label = format!("{}C", (max_t / 2.0).round());
-        let current_t_pct_from_max = if max_t != 0.0 {
-            100.min(((current_t / max_t) * 100.0) as u16)
-        } else {
-            *current_t as u16
-        };
-        // get historical data and combine with current_t
-        let existing_temps = if let Some(existing_temps) = app.state.get_historical_data(label) {
-            let mut v = Vec::from(existing_temps.buf.clone());
-            v.push(*current_t);
-            v
-        } else {
-            vec![*current_t]
-        };
-        let existing_temps: Vec<(f64, f64)> = existing_temps
+fn charts<B: Backend>(
+    app: &App,
+    f: &mut Frame<B>,
+    readings: &[Reading],
+    layout: &[Rect],
+    props: &ChipListProps,
+) {
+    for (reading, area) in zip(readings.iter(), layout) {
+        let label = &reading.id.label;
+        let current_t = reading.value.as_ref().ok().copied();
+        let unit = reading.kind.unit();
+        let max_t = chart_max(app, reading);
+        let samples = app.state.get_visible_samples(&reading.id, current_t);
+        let existing_temps: Vec<(f64, f64)> = samples
             .iter()
             .enumerate()
-            .map(|(x, y)| (x as f64, *y))
+            .map(|(x, sample)| (x as f64, sample.value))
             .collect();
         // x is index
         // y is temp
-        let color = if current_t_pct_from_max < 50 {
-            Color::Blue
-        } else if current_t_pct_from_max < 80 {
-            Color::Yellow
-        } else {
-            Color::Red
-        };
+        let color = status_color(reading.status());
         let dataset = Dataset::default()
-            .name(label)
+            .name(label.as_str())
             .marker(symbols::Marker::Dot)
             .graph_type(ratatui::widgets::GraphType::Line)
             .style(Style::default().fg(color))
             .data(&existing_temps);
-        let chart = Chart::new(vec![dataset])
-            .block(Block::default().title(label.clone()))
+        let is_auto_scaled = props.is_auto_scaled || app.state.is_auto_scaled_sensor(&reading.id);
+        let bounds = y_bounds(samples.iter().map(|sample| sample.value), max_t, is_auto_scaled);
+        let width = app.state.get_viewport().width() as f64;
+        let reference_lines = reference_lines(app, std::slice::from_ref(reading), bounds, width);
+        let cursor_points = cursor_line(app, samples.len(), bounds);
+        let throttle_points = throttle_marks(app, &reading.id.chip, &samples, bounds);
+        let mut datasets = vec![dataset];
+        datasets.extend(reference_lines.iter().map(reference_dataset));
+        if !throttle_points.is_empty() {
+            datasets.push(throttle_dataset(&throttle_points));
+        }
+        if let Some(cursor_points) = &cursor_points {
+            datasets.push(cursor_dataset(cursor_points));
+        }
+        let title = match app.state.get_viewport().sample_at_cursor(&samples) {
+            Some(sample) => format!("{} {}{} @ {}", label, sample.value, unit, sample.format_time()),
+            None if current_t.is_none() => format!("{} N/A", label),
+            None => label.clone(),
+        };
+        let title_style = if !props.is_pinned_chip_view
+            && app.state.get_selected_feature() == Some(label.as_str())
+        {
+            Style::default().fg(Color::Black).bg(Color::Yellow)
+        } else {
+            Style::default()
+        };
+        let chart = Chart::new(datasets)
+            .block(Block::default().title(Span::styled(title, title_style)))
             .x_axis(
                 Axis::default()
-                    .bounds([0.0, 100.0]),
+                    .bounds([0.0, width]),
             )
             .y_axis(
                 Axis::default()
-                    .title(format!("{}C", current_t))
+                    .title(format_value(current_t, unit))
                     .style(Style::default().fg(Color::White))
-                    .labels(vec!["0C", &half_max_t_label, &max_t_label].into_iter().map(Span::from).collect_vec())
-                    .bounds([0.0, if max_t == 0.0 { 100.0 } else { max_t }]),
+                    .labels(y_labels(bounds, unit))
+                    .bounds(bounds),
             );
 
         f.render_widget(chart, *area);
     }
 }
+
+/// Leaves out yellow, red and magenta, which mark the threshold lines
+pub const SERIES_COLORS: [Color; 6] = [
+    Color::Cyan,
+    Color::Green,
+    Color::Blue,
+    Color::LightCyan,
+    Color::LightGreen,
+    Color::LightBlue,
+];
+
+/// Overlays several readings on a single chart with a shared y axis
+pub fn combined_chart<B: Backend>(
+    app: &App,
+    f: &mut Frame<B>,
+    area: Rect,
+    readings: &[Reading],
+    title: &str,
+    is_auto_scaled: bool,
+) {
+    let max_t = readings
+        .iter()
+        .map(|reading| chart_max(app, reading))
+        .fold(0.0, f64::max);
+    let max_t = if max_t == 0.0 { DEFAULT_TEMPERATURE_CRITICAL } else { max_t };
+    let unit = readings.first().map_or("C", |reading| reading.kind.unit());
+
+    // Datasets borrow their points, so collect them all before building the chart
+    let samples: Vec<Vec<Sample>> = readings
+        .iter()
+        .map(|reading| app.state.get_visible_samples(&reading.id, reading.value.clone().ok()))
+        .collect();
+    let series: Vec<Vec<(f64, f64)>> = samples
+        .iter()
+        .map(|samples| {
+            samples
+                .iter()
+                .enumerate()
+                .map(|(x, sample)| (x as f64, sample.value))
+                .collect()
+        })
+        .collect();
+    let viewport = app.state.get_viewport();
+    let width = viewport.width() as f64;
+    let bounds = y_bounds(
+        samples.iter().flatten().map(|sample| sample.value),
+        max_t,
+        is_auto_scaled,
+    );
+    // Labels only stay unique within a chip, qualify them when overlaying across chips
+    let is_multi_chip = readings
+        .iter()
+        .any(|reading| readings.first().map(|first| &first.id.chip) != Some(&reading.id.chip));
+    // With the cursor active the legend shows the values under it instead of the latest
+    let cursor_samples: Vec<Option<Sample>> = samples
+        .iter()
+        .map(|samples| viewport.sample_at_cursor(samples))
+        .collect();
+    let mut datasets = zip(readings.iter(), zip(series.iter(), cursor_samples.iter()))
+        .enumerate()
+        .map(|(i, (reading, (points, cursor_sample)))| {
+            let value = match cursor_sample {
+                Some(sample) => Some(sample.value),
+                None => reading.value.clone().ok(),
+            };
+            let name = if is_multi_chip {
+                format!("{}/{} {}", reading.id.chip, reading.id.label, format_value(value, unit))
+            } else {
+                format!("{} {}", reading.id.label, format_value(value, unit))
+            };
+            Dataset::default()
+                .name(name)
+                .marker(symbols::Marker::Braille)
+                .graph_type(ratatui::widgets::GraphType::Line)
+                .style(Style::default().fg(SERIES_COLORS[i % SERIES_COLORS.len()]))
+                .data(points)
+        })
+        .collect::<Vec<Dataset>>();
+    let reference_lines = reference_lines(app, readings, bounds, width);
+    datasets.extend(reference_lines.iter().map(reference_dataset));
+    let samples_len = samples.iter().map(|samples| samples.len()).max().unwrap_or(0);
+    let cursor_points = cursor_line(app, samples_len, bounds);
+    // All series of a chip share the sample times, take the marks off the longest
+    let throttle_points = match (readings.first(), samples.iter().max_by_key(|samples| samples.len())) {
+        (Some(reading), Some(samples)) if !is_multi_chip => {
+            throttle_marks(app, &reading.id.chip, samples, bounds)
+        }
+        _ => vec![],
+    };
+    if !throttle_points.is_empty() {
+        datasets.push(throttle_dataset(&throttle_points));
+    }
+    if let Some(cursor_points) = &cursor_points {
+        datasets.push(cursor_dataset(cursor_points));
+    }
+    let title = match cursor_samples.iter().flatten().next() {
+        Some(sample) => format!("{} @ {}", title, sample.format_time()),
+        None => String::from(title),
+    };
+
+    let chart = Chart::new(datasets)
+        .block(Block::default().title(title))
+        .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 1)))
+        .x_axis(Axis::default().bounds([0.0, width]))
+        .y_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::White))
+                .labels(y_labels(bounds, unit))
+                .bounds(bounds),
+        );
+
+    f.render_widget(chart, area);
+}
+
+/// Absolute charts go up to the highest limit, emergency, crit or max.
+/// Temperatures without one go up to a default, other kinds like power to a
+/// bit above the highest value seen
+fn chart_max(app: &App, reading: &Reading) -> f64 {
+    let limit = reading
+        .emergency
+        .or(reading.critical)
+        .or(reading.maximum)
+        .filter(|limit| *limit != 0.0);
+    match (limit, reading.kind) {
+        (Some(limit), _) => limit,
+        (None, SensorKind::Temperature) => DEFAULT_TEMPERATURE_CRITICAL,
+        (None, _) => app
+            .state
+            .get_collector()
+            .get_sensor_stats(&reading.id)
+            .map(|stats| (stats.max * 1.25).ceil())
+            .filter(|max| *max > 0.0)
+            .unwrap_or(DEFAULT_TEMPERATURE_CRITICAL),
+    }
+}
+
+/// Overlays whole series, e.g. the same sensor in two recordings, with x in
+/// seconds instead of samples. Always auto scaled, the units vary
+pub fn overlay_chart<B: Backend>(
+    f: &mut Frame<B>,
+    area: Rect,
+    title: &str,
+    series: &[(String, Vec<(f64, f64)>)],
+    critical: Option<f64>,
+    unit: &str,
+) {
+    let values = series.iter().flat_map(|(_, points)| points.iter().map(|(_, value)| *value));
+    let bounds = y_bounds(values, critical.unwrap_or(DEFAULT_TEMPERATURE_CRITICAL), true);
+    let duration = series
+        .iter()
+        .flat_map(|(_, points)| points.last())
+        .map(|(time, _)| *time)
+        .fold(1.0, f64::max);
+    let mut datasets = zip(series, SERIES_COLORS)
+        .map(|((name, points), color)| {
+            Dataset::default()
+                .name(name.as_str())
+                .marker(symbols::Marker::Braille)
+                .graph_type(ratatui::widgets::GraphType::Line)
+                .style(Style::default().fg(color))
+                .data(points)
+        })
+        .collect::<Vec<Dataset>>();
+    let reference_line = critical
+        .filter(|crit| *crit >= bounds[0] && *crit <= bounds[1])
+        .map(|crit| (format!("crit {}{}", crit, unit), Color::LightRed, vec![(0.0, crit), (duration, crit)]));
+    if let Some(reference_line) = &reference_line {
+        datasets.push(reference_dataset(reference_line));
+    }
+    let x_labels = [0.0, (duration / 2.0).round(), duration.ceil()]
+        .into_iter()
+        .map(|seconds| Span::from(format!("{}s", seconds)))
+        .collect_vec();
+
+    let chart = Chart::new(datasets)
+        .block(Block::default().title(String::from(title)))
+        .hidden_legend_constraints((Constraint::Ratio(1, 2), Constraint::Ratio(1, 1)))
+        .x_axis(Axis::default().labels(x_labels).bounds([0.0, duration.ceil()]))
+        .y_axis(
+            Axis::default()
+                .style(Style::default().fg(Color::White))
+                .labels(y_labels(bounds, unit))
+                .bounds(bounds),
+        );
+
+    f.render_widget(chart, area);
+}
+
+/// Absolute charts go from 0 to `max_t`, auto scaled charts fit the values they
+/// show with some padding so small variations remain visible
+pub fn y_bounds(values: impl Iterator<Item = f64>, max_t: f64, is_auto_scaled: bool) -> [f64; 2] {
+    if !is_auto_scaled {
+        return [0.0, max_t];
+    }
+    let (min, max) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), value| {
+        (min.min(value), max.max(value))
+    });
+    if min > max {
+        return [0.0, max_t];
+    }
+    let padding = ((max - min) * 0.1).max(1.0);
+    [(min - padding).floor(), (max + padding).ceil()]
+}
+
+fn format_value(value: Option<f64>, unit: &str) -> String {
+    match value {
+        Some(value) => format!("{}{}", value, unit),
+        None => String::from("N/A"),
+    }
+}
+
+pub fn y_labels(bounds: [f64; 2], unit: &str) -> Vec<Span<'static>> {
+    let [min, max] = bounds;
+    vec![
+        format!("{}{}", min, unit),
+        format!("{}{}", ((min + max) / 2.0).round(), unit),
+        format!("{}{}", max, unit),
+    ]
+    .into_iter()
+    .map(Span::from)
+    .collect_vec()
+}
+
+/// Legend name, color and endpoints of a horizontal threshold line
+type ReferenceLine = (String, Color, Vec<(f64, f64)>);
+
+/// Horizontal max/crit/emergency lines for the thresholds that fall inside
+/// `bounds`, with dimmer lines where the alarms clear again
+fn reference_lines(
+    app: &App,
+    readings: &[Reading],
+    bounds: [f64; 2],
+    width: f64,
+) -> Vec<ReferenceLine> {
+    let hysteresis = |reading: &Reading, short_name: &str| {
+        app.state
+            .get_chip_by_name(&reading.id.chip)?
+            .features
+            .iter()
+            .find(|feature| feature.label == reading.id.label)?
+            .sub_feature(short_name)?
+            .value
+            .clone()
+            .ok()
+    };
+    let thresholds = readings
+        .iter()
+        .flat_map(|reading| {
+            [
+                reading.maximum.map(|max| ("max", Color::LightYellow, max)),
+                hysteresis(reading, "max_hyst").map(|hyst| ("max hyst", Color::Yellow, hyst)),
+                reading.critical.map(|crit| ("crit", Color::LightRed, crit)),
+                hysteresis(reading, "crit_hyst").map(|hyst| ("crit hyst", Color::Red, hyst)),
+                reading.emergency.map(|emerg| ("emerg", Color::LightMagenta, emerg)),
+                hysteresis(reading, "emergency_hyst")
+                    .map(|hyst| ("emerg hyst", Color::Magenta, hyst)),
+            ]
+        })
+        .flatten()
+        .filter(|(_, _, value)| *value >= bounds[0] && *value <= bounds[1])
+        .unique_by(|(name, _, value)| (*name, value.to_bits()));
+    thresholds
+        .map(|(name, color, value)| {
+            let unit = readings.first().map_or("C", |reading| reading.kind.unit());
+            (format!("{} {}{}", name, value, unit), color, vec![(0.0, value), (width, value)])
+        })
+        .collect()
+}
+
+fn reference_dataset((name, color, points): &ReferenceLine) -> Dataset {
+    Dataset::default()
+        .name(name.as_str())
+        .marker(symbols::Marker::Braille)
+        .graph_type(ratatui::widgets::GraphType::Line)
+        .style(Style::default().fg(*color))
+        .data(points)
+}
+
+/// A row of dots along the bottom of CPU charts where the CPU throttled
+fn throttle_marks(app: &App, chip: &str, samples: &[Sample], bounds: [f64; 2]) -> Vec<(f64, f64)> {
+    if !app.state.get_chip_by_name(chip).is_some_and(is_cpu_chip) {
+        return vec![];
+    }
+    app.state
+        .get_collector()
+        .get_throttling()
+        .marks(samples)
+        .into_iter()
+        .map(|x| (x as f64, bounds[0]))
+        .collect()
+}
+
+fn throttle_dataset(points: &[(f64, f64)]) -> Dataset {
+    Dataset::default()
+        .name("throttled")
+        .marker(symbols::Marker::Block)
+        .graph_type(ratatui::widgets::GraphType::Scatter)
+        .style(Style::default().fg(Color::Red))
+        .data(points)
+}
+
+/// Endpoints of a vertical line at the cursor, if the cursor is active
+fn cursor_line(app: &App, samples_len: usize, bounds: [f64; 2]) -> Option<Vec<(f64, f64)>> {
+    let cursor = app.state.get_viewport().cursor()?;
+    let x = cursor.min(samples_len.checked_sub(1)?) as f64;
+    Some(vec![(x, bounds[0]), (x, bounds[1])])
+}
+
+fn cursor_dataset(points: &[(f64, f64)]) -> Dataset {
+    Dataset::default()
+        .marker(symbols::Marker::Braille)
+        .graph_type(ratatui::widgets::GraphType::Line)
+        .style(Style::default().fg(Color::Gray))
+        .data(points)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::y_bounds;
+
+    #[test]
+    fn test_absolute_bounds() {
+        assert!(y_bounds([42.0, 45.0].into_iter(), 100.0, false) == [0.0, 100.0]);
+    }
+
+    #[test]
+    fn test_auto_bounds_fit_values_with_padding() {
+        assert!(y_bounds([42.0, 45.0].into_iter(), 100.0, true) == [41.0, 46.0]);
+        assert!(y_bounds([20.0, 80.0].into_iter(), 100.0, true) == [14.0, 86.0]);
+    }
+
+    #[test]
+    fn test_auto_bounds_without_values() {
+        assert!(y_bounds(std::iter::empty(), 90.0, true) == [0.0, 90.0]);
+    }
+}
@@ -0,0 +1,137 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    io::ErrorKind,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{export::ExporterConfig, mqtt::MqttConfig};
+
+/// A `senso serve` instance shown by `senso fleet`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HostConfig {
+    /// Shown in the host list and used to tell the hosts' chips apart
+    pub name: String,
+    /// `host` or `host:port`
+    pub address: String,
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Settings edited by hand, stored as JSON in the XDG config dir
+#[derive(Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Config {
+    #[serde(default)]
+    pub hosts: Vec<HostConfig>,
+    /// Run by `senso daemon` and `senso serve`
+    #[serde(default)]
+    pub exporters: Vec<ExporterConfig>,
+    /// Also run by `senso daemon` and `senso serve`
+    #[serde(default)]
+    pub mqtt: Option<MqttConfig>,
+}
+
+/// `$XDG_CONFIG_HOME/senso/config.json`, falling back to `~/.config/senso/config.json`
+pub fn default_config_path() -> Option<PathBuf> {
+    env::var_os("XDG_CONFIG_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+        .map(|dir| dir.join("senso").join("config.json"))
+}
+
+impl Config {
+    /// A missing default config is fine, a missing `path` or an invalid file is not
+    pub fn load(path: Option<&Path>) -> Result<Self, Box<dyn Error>> {
+        let (path, is_default) = match path {
+            Some(path) => (path.to_path_buf(), false),
+            None => match default_config_path() {
+                Some(path) => (path, true),
+                None => return Ok(Self::default()),
+            },
+        };
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if is_default && e.kind() == ErrorKind::NotFound => return Ok(Self::default()),
+            Err(e) => return Err(format!("can't read {}: {}", path.display(), e).into()),
+        };
+        let config: Self = serde_json::from_str(&contents)
+            .map_err(|e| format!("invalid config {}: {}", path.display(), e))?;
+        config.validate()?;
+
+        Ok(config)
+    }
+
+    fn validate(&self) -> Result<(), Box<dyn Error>> {
+        for (i, host) in self.hosts.iter().enumerate() {
+            if host.name.is_empty() || host.name.contains('/') {
+                return Err(format!(
+                    "invalid host name {:?}, it can't be empty or contain /",
+                    host.name
+                )
+                .into());
+            }
+            if self.hosts[..i].iter().any(|other| other.name == host.name) {
+                return Err(format!("host {} is listed twice", host.name).into());
+            }
+        }
+        for exporter in &self.exporters {
+            exporter.validate()?;
+        }
+        if let Some(mqtt) = &self.mqtt {
+            mqtt.validate()?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Config, HostConfig};
+
+    #[test]
+    fn test_parse_hosts() {
+        let config: Config = serde_json::from_str(
+            r#"{"hosts": [{"name": "lab1", "address": "lab1:7878", "token": "secret"}, {"name": "lab2", "address": "lab2"}]}"#,
+        )
+        .unwrap();
+
+        assert!(config.hosts.len() == 2);
+        assert!(config.hosts[0].token.as_deref() == Some("secret"));
+        assert!(config.hosts[1].token.is_none());
+        assert!(config.validate().is_ok());
+    }
+
+    #[test]
+    fn test_parse_exporters() {
+        let config: Config = serde_json::from_str(
+            r#"{"exporters": [{"type": "statsd", "address": "127.0.0.1:8125", "tags": {"rack": "r2"}}, {"type": "influx_http", "url": "https://influx", "interval": 60}]}"#,
+        )
+        .unwrap();
+
+        assert!(config.exporters[0].interval == 10);
+        assert!(config.exporters[0].tags["rack"] == "r2");
+        assert!(config.exporters[1].interval == 60);
+        // https needs a TLS client senso doesn't have
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_host_names_must_be_unique() {
+        let host = HostConfig {
+            name: String::from("lab1"),
+            address: String::from("lab1"),
+            token: None,
+        };
+        let config = Config {
+            hosts: vec![host.clone(), host],
+            exporters: vec![],
+            mqtt: None,
+        };
+
+        assert!(config.validate().is_err());
+    }
+}
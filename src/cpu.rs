@@ -0,0 +1,525 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    fs,
+    path::Path,
+    time::SystemTime,
+};
+
+use crate::{
+    history::{Sample, HISTORY_CAPACITY},
+    ring_buffer::RingBuf,
+    snapshot::ChipSnapshot,
+};
+
+pub const CPU_ROOT: &str = "/sys/devices/system/cpu";
+pub const PROC_STAT: &str = "/proc/stat";
+
+/// Chips carrying the CPU temperatures, throttling is shown next to them
+const CPU_CHIP_PREFIXES: [&str; 3] = ["coretemp", "k10temp", "zenpower"];
+
+pub fn is_cpu_chip(chip: &ChipSnapshot) -> bool {
+    CPU_CHIP_PREFIXES.contains(&chip.prefix.as_str())
+}
+
+/// The package a CPU chip measures. coretemp labels it with a `Package id N`
+/// feature, other drivers have a chip per package in package order
+pub fn chip_package(chip: &ChipSnapshot, chips: &[ChipSnapshot]) -> Option<u32> {
+    let labelled = chip
+        .features
+        .iter()
+        .find_map(|feature| feature.label.strip_prefix("Package id ")?.parse().ok());
+    if labelled.is_some() {
+        return labelled;
+    }
+    let mut siblings: Vec<&str> = chips
+        .iter()
+        .filter(|other| other.prefix == chip.prefix && other.host == chip.host)
+        .map(|other| other.name.as_str())
+        .collect();
+    siblings.sort();
+    let position = siblings.iter().position(|name| *name == chip.name)?;
+    Some(position as u32)
+}
+
+/// What the kernel reports for a logical CPU. Counters and frequencies are
+/// missing where the driver doesn't expose them, e.g. throttle counts on AMD
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CpuState {
+    pub cpu: usize,
+    pub package_id: u32,
+    pub core_id: u32,
+    pub core_throttle_count: Option<u64>,
+    pub package_throttle_count: Option<u64>,
+    /// kHz, like cpufreq
+    pub current_frequency: Option<u64>,
+    pub max_frequency: Option<u64>,
+}
+
+fn read_number(path: &Path) -> Option<u64> {
+    fs::read_to_string(path).ok()?.trim().parse().ok()
+}
+
+impl CpuState {
+    fn read(cpu: usize, dir: &Path) -> Self {
+        let read = |file: &str| read_number(&dir.join(file));
+        Self {
+            cpu,
+            package_id: read("topology/physical_package_id").unwrap_or(0) as u32,
+            core_id: read("topology/core_id").unwrap_or(cpu as u64) as u32,
+            core_throttle_count: read("thermal_throttle/core_throttle_count"),
+            package_throttle_count: read("thermal_throttle/package_throttle_count"),
+            current_frequency: read("cpufreq/scaling_cur_freq"),
+            max_frequency: read("cpufreq/cpuinfo_max_freq"),
+        }
+    }
+}
+
+/// Reads `cpu0`, `cpu1`, ... under `root`, ordered by number
+pub fn read_cpus(root: &Path) -> Vec<CpuState> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return vec![];
+    };
+    let mut cpus: Vec<CpuState> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            let cpu = name.to_str()?.strip_prefix("cpu")?.parse().ok()?;
+            Some(CpuState::read(cpu, &entry.path()))
+        })
+        .collect();
+    cpus.sort_by_key(|cpu| cpu.cpu);
+    cpus
+}
+
+/// Jiffies a CPU spent idle and in total, from its `cpuN` line in /proc/stat
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct CpuTimes {
+    pub idle: u64,
+    pub total: u64,
+}
+
+impl CpuTimes {
+    /// Percent of the time spent busy since `earlier`
+    fn load_since(&self, earlier: &Self) -> Option<f64> {
+        let total = self
+            .total
+            .checked_sub(earlier.total)
+            .filter(|total| *total > 0)?;
+        let idle = self.idle.saturating_sub(earlier.idle).min(total);
+        Some((total - idle) as f64 / total as f64 * 100.0)
+    }
+}
+
+/// The per CPU lines of /proc/stat, without the `cpu` line summing them up
+pub fn parse_proc_stat(text: &str) -> HashMap<usize, CpuTimes> {
+    text.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let cpu = fields.next()?.strip_prefix("cpu")?.parse().ok()?;
+            let values: Vec<u64> = fields.map(str::parse).collect::<Result<_, _>>().ok()?;
+            // user nice system idle iowait irq softirq steal, guest time is
+            // already part of user
+            Some((
+                cpu,
+                CpuTimes {
+                    idle: values.get(3)? + values.get(4).unwrap_or(&0),
+                    total: values.iter().take(8).sum(),
+                },
+            ))
+        })
+        .collect()
+}
+
+pub fn read_cpu_times() -> HashMap<usize, CpuTimes> {
+    fs::read_to_string(PROC_STAT)
+        .map(|text| parse_proc_stat(&text))
+        .unwrap_or_default()
+}
+
+/// Load and frequency history of a physical core, averaged over its threads
+#[derive(Debug)]
+pub struct CoreHistory {
+    pub package_id: u32,
+    /// Like coretemp labels the core's temperature, e.g. "Core 3". Only unique
+    /// within the package, as are coretemp's labels
+    pub label: String,
+    /// Percent busy since the previous sample
+    pub load: RingBuf<Sample>,
+    /// MHz
+    pub frequency: RingBuf<Sample>,
+}
+
+fn mean(values: &[f64]) -> Option<f64> {
+    match values.len() {
+        0 => None,
+        len => Some(values.iter().sum::<f64>() / len as f64),
+    }
+}
+
+/// What the local cores were doing while the temperatures were sampled
+#[derive(Debug, Default)]
+pub struct CoreActivity {
+    last_times: HashMap<usize, CpuTimes>,
+    cores: BTreeMap<(u32, u32), CoreHistory>,
+}
+
+impl CoreActivity {
+    pub fn update(
+        &mut self,
+        cpus: &[CpuState],
+        times: &HashMap<usize, CpuTimes>,
+        time: SystemTime,
+    ) {
+        let mut threads: BTreeMap<(u32, u32), (Vec<f64>, Vec<f64>)> = BTreeMap::new();
+        for cpu in cpus {
+            let (loads, frequencies) = threads.entry((cpu.package_id, cpu.core_id)).or_default();
+            let last_times = self.last_times.get(&cpu.cpu);
+            if let Some(load) = times
+                .get(&cpu.cpu)
+                .zip(last_times)
+                .and_then(|(times, last_times)| times.load_since(last_times))
+            {
+                loads.push(load);
+            }
+            if let Some(frequency) = cpu.current_frequency {
+                frequencies.push(frequency as f64 / 1000.0);
+            }
+        }
+        self.last_times = times.clone();
+        for ((package_id, core_id), (loads, frequencies)) in threads {
+            let history = self
+                .cores
+                .entry((package_id, core_id))
+                .or_insert_with(|| CoreHistory {
+                    package_id,
+                    label: format!("Core {}", core_id),
+                    load: RingBuf::new(HISTORY_CAPACITY),
+                    frequency: RingBuf::new(HISTORY_CAPACITY),
+                });
+            if let Some(value) = mean(&loads) {
+                history.load.put(Sample { time, value });
+            }
+            if let Some(value) = mean(&frequencies) {
+                history.frequency.put(Sample { time, value });
+            }
+        }
+    }
+
+    /// Ordered by package and core
+    pub fn cores(&self) -> impl Iterator<Item = &CoreHistory> {
+        self.cores.values()
+    }
+}
+
+/// Throttle events counted by the kernel, summed over cores and packages
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ThrottleCounters {
+    pub core: u64,
+    pub package: u64,
+}
+
+impl ThrottleCounters {
+    /// Sibling threads repeat their core's counter and every CPU of a package
+    /// repeats the package counter, so each is only counted once
+    pub fn from_cpus(cpus: &[CpuState]) -> Option<Self> {
+        let mut cores = HashMap::new();
+        let mut packages = HashMap::new();
+        for cpu in cpus {
+            if let Some(count) = cpu.core_throttle_count {
+                cores.insert((cpu.package_id, cpu.core_id), count);
+            }
+            if let Some(count) = cpu.package_throttle_count {
+                packages.insert(cpu.package_id, count);
+            }
+        }
+        if cores.is_empty() && packages.is_empty() {
+            return None;
+        }
+        Some(Self {
+            core: cores.values().sum(),
+            package: packages.values().sum(),
+        })
+    }
+
+    pub fn total(&self) -> u64 {
+        self.core + self.package
+    }
+
+    fn since(&self, start: &Self) -> Self {
+        Self {
+            core: self.core.saturating_sub(start.core),
+            package: self.package.saturating_sub(start.package),
+        }
+    }
+}
+
+/// Average current and highest frequency over all CPUs, in MHz
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Frequency {
+    pub current: f64,
+    pub max: f64,
+}
+
+impl Frequency {
+    pub fn from_cpus(cpus: &[CpuState]) -> Option<Self> {
+        let current: Vec<u64> = cpus.iter().flat_map(|cpu| cpu.current_frequency).collect();
+        if current.is_empty() {
+            return None;
+        }
+        Some(Self {
+            current: current.iter().sum::<u64>() as f64 / current.len() as f64 / 1000.0,
+            max: cpus
+                .iter()
+                .flat_map(|cpu| cpu.max_frequency)
+                .max()
+                .unwrap_or(0) as f64
+                / 1000.0,
+        })
+    }
+}
+
+/// Throttling of the local CPUs since senso started
+#[derive(Debug)]
+pub struct Throttling {
+    /// The counters on the first and the latest update
+    counters: Option<(ThrottleCounters, ThrottleCounters)>,
+    frequency: Option<Frequency>,
+    /// Updates on which a counter went up, oldest first
+    events: RingBuf<SystemTime>,
+}
+
+impl Default for Throttling {
+    fn default() -> Self {
+        Self {
+            counters: None,
+            frequency: None,
+            events: RingBuf::new(HISTORY_CAPACITY),
+        }
+    }
+}
+
+impl Throttling {
+    pub fn update(&mut self, cpus: &[CpuState], time: SystemTime) {
+        self.frequency = Frequency::from_cpus(cpus);
+        let Some(counters) = ThrottleCounters::from_cpus(cpus) else {
+            return;
+        };
+        self.counters = match self.counters {
+            Some((first, last)) => {
+                if counters.total() > last.total() {
+                    self.events.put(time);
+                }
+                Some((first, counters))
+            }
+            None => Some((counters, counters)),
+        };
+    }
+
+    /// Whether there is anything to show, remote sources don't update this
+    pub fn is_available(&self) -> bool {
+        self.counters.is_some() || self.frequency.is_some()
+    }
+
+    pub fn since_start(&self) -> Option<ThrottleCounters> {
+        self.counters.map(|(first, last)| last.since(&first))
+    }
+
+    pub fn last_event(&self) -> Option<SystemTime> {
+        self.events.buf.back().copied()
+    }
+
+    pub fn frequency(&self) -> Option<Frequency> {
+        self.frequency
+    }
+
+    /// Indices of the samples taken when a counter went up since the
+    /// previous sample
+    pub fn marks(&self, samples: &[Sample]) -> Vec<usize> {
+        samples
+            .iter()
+            .enumerate()
+            .filter(|(i, sample)| {
+                let previous = i.checked_sub(1).map(|i| samples[i].time);
+                self.events.buf.iter().any(|event| match previous {
+                    Some(previous) => *event > previous && *event <= sample.time,
+                    None => *event == sample.time,
+                })
+            })
+            .map(|(i, _)| i)
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::HashMap,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{
+        chip_package, parse_proc_stat, CoreActivity, CpuState, CpuTimes, Frequency,
+        ThrottleCounters, Throttling,
+    };
+    use crate::{
+        history::Sample,
+        sensors::SensorKind,
+        snapshot::fixtures::{chip, input},
+    };
+
+    /// Two cores with two threads each on one package
+    fn cpus(core_counts: [u64; 2], package_count: u64) -> Vec<CpuState> {
+        (0..4)
+            .map(|cpu| CpuState {
+                cpu,
+                package_id: 0,
+                core_id: (cpu % 2) as u32,
+                core_throttle_count: Some(core_counts[cpu % 2]),
+                package_throttle_count: Some(package_count),
+                current_frequency: Some(1_000_000 + cpu as u64 * 1_000_000),
+                max_frequency: Some(4_500_000),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_counters_count_shared_counters_once() {
+        let counters = ThrottleCounters::from_cpus(&cpus([3, 4], 5));
+
+        assert!(
+            counters
+                == Some(ThrottleCounters {
+                    core: 7,
+                    package: 5
+                })
+        );
+        assert!(ThrottleCounters::from_cpus(&[CpuState::default()]).is_none());
+    }
+
+    #[test]
+    fn test_frequency() {
+        let frequency = Frequency::from_cpus(&cpus([0, 0], 0));
+
+        assert!(
+            frequency
+                == Some(Frequency {
+                    current: 2500.0,
+                    max: 4500.0
+                })
+        );
+    }
+
+    #[test]
+    fn test_throttle_events_mark_samples() {
+        let start = SystemTime::now();
+        let times: Vec<SystemTime> = (0..4).map(|i| start + Duration::from_secs(i)).collect();
+        let mut throttling = Throttling::default();
+        throttling.update(&cpus([3, 4], 5), times[0]);
+        throttling.update(&cpus([3, 4], 5), times[1]);
+        throttling.update(&cpus([3, 6], 5), times[2]);
+        throttling.update(&cpus([3, 6], 5), times[3]);
+        let samples: Vec<Sample> = times
+            .iter()
+            .map(|time| Sample {
+                time: *time,
+                value: 50.0,
+            })
+            .collect();
+
+        assert!(
+            throttling.since_start()
+                == Some(ThrottleCounters {
+                    core: 2,
+                    package: 0
+                })
+        );
+        assert!(throttling.last_event() == Some(times[2]));
+        assert!(throttling.marks(&samples) == [2]);
+        assert!(throttling.marks(&samples[2..]) == [0]);
+    }
+
+    #[test]
+    fn test_parse_proc_stat() {
+        let times = parse_proc_stat(
+            "cpu  10 0 10 70 10 0 0 0 5 0\ncpu0 4 0 6 30 5 0 0 0 5 0\nintr 1234 0 0\n",
+        );
+
+        assert!(times.len() == 1);
+        assert!(
+            times.get(&0)
+                == Some(&CpuTimes {
+                    idle: 35,
+                    total: 45
+                })
+        );
+    }
+
+    #[test]
+    fn test_core_load_averages_threads() {
+        let times = |busy: [u64; 4]| -> HashMap<usize, CpuTimes> {
+            (0..4)
+                .map(|cpu| {
+                    (
+                        cpu,
+                        CpuTimes {
+                            idle: 100 - busy[cpu],
+                            total: 100,
+                        },
+                    )
+                })
+                .collect()
+        };
+        let mut activity = CoreActivity::default();
+        let now = SystemTime::now();
+        activity.update(&cpus([0, 0], 0), &times([0; 4]), now);
+        activity.update(&cpus([0, 0], 0), &HashMap::new(), now);
+        activity.update(&cpus([0, 0], 0), &times([0; 4]), now);
+        // Per 100 jiffies, threads 0 and 2 share core 0
+        let later = times([0; 4])
+            .into_iter()
+            .map(|(cpu, times)| {
+                let busy = [50, 100, 30, 0][cpu];
+                (
+                    cpu,
+                    CpuTimes {
+                        idle: times.idle + 100 - busy,
+                        total: times.total + 100,
+                    },
+                )
+            })
+            .collect();
+        activity.update(&cpus([0, 0], 0), &later, now);
+        let cores: Vec<(&str, Vec<f64>, Option<f64>)> = activity
+            .cores()
+            .map(|core| {
+                (
+                    core.label.as_str(),
+                    core.load.buf.iter().map(|sample| sample.value).collect(),
+                    core.frequency.buf.back().map(|sample| sample.value),
+                )
+            })
+            .collect();
+
+        assert!(
+            cores
+                == [
+                    ("Core 0", vec![40.0], Some(2000.0)),
+                    ("Core 1", vec![50.0], Some(3000.0)),
+                ]
+        );
+    }
+
+    #[test]
+    fn test_chip_package() {
+        let temperature = |label: &str| input("temp1", label, SensorKind::Temperature, 40.0);
+        let chips = [
+            chip("coretemp-isa-0001", vec![temperature("Package id 1")]),
+            chip("k10temp-pci-00cb", vec![temperature("Tctl")]),
+            chip("k10temp-pci-00c3", vec![temperature("Tctl")]),
+        ];
+
+        assert!(chip_package(&chips[0], &chips) == Some(1));
+        assert!(chip_package(&chips[1], &chips) == Some(1));
+        assert!(chip_package(&chips[2], &chips) == Some(0));
+    }
+}
@@ -0,0 +1,483 @@
+use std::{
+    env,
+    error::Error,
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind, Read, Write},
+    net::TcpListener,
+    os::unix::{
+        fs::PermissionsExt,
+        net::{UnixListener, UnixStream},
+    },
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Condvar, Mutex, MutexGuard, PoisonError,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use log::{debug, info};
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+
+use crate::{
+    collector::Collector,
+    config::Config,
+    export::{Batch, Exporter, ExporterConfig},
+    mqtt::{MqttConfig, MqttPublisher},
+    protocol::{read_message_with_limit, write_message, Request, Response, MAX_REQUEST_LEN},
+    selector::{select_readings, Selector},
+    source::SensorSource,
+    state_file::state_dir,
+};
+
+/// `$XDG_RUNTIME_DIR/senso.sock`, falling back to the state dir
+pub fn default_socket_path() -> Option<PathBuf> {
+    env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(state_dir)
+        .map(|dir| dir.join("senso.sock"))
+}
+
+/// Port used by `senso serve` and `senso connect` unless one is given
+pub const DEFAULT_PORT: u16 = 7878;
+
+/// TCP clients that don't authenticate within this are dropped
+const AUTH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Where the daemon serves
+#[derive(Debug, Clone)]
+pub enum Endpoint {
+    /// Only reachable by the user running the daemon
+    Unix(PathBuf),
+    /// Anyone who can reach the address can read the sensors, unless a token is set
+    Tcp {
+        address: String,
+        token: Option<String>,
+    },
+}
+
+/// What the sampling loop shares with the connections. `generation` is bumped
+/// after every sample so subscribers know when there is something new to send
+#[derive(Default)]
+pub struct Shared {
+    collector: Collector,
+    generation: u64,
+    time: Option<SystemTime>,
+}
+
+pub type SharedState = Arc<(Mutex<Shared>, Condvar)>;
+
+// A panicking connection must not take the daemon down with it
+fn lock(shared: &SharedState) -> MutexGuard<Shared> {
+    shared.0.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+fn snapshot(shared: &Shared) -> Response {
+    Response::Snapshot {
+        time: shared.time.unwrap_or_else(SystemTime::now),
+        chips: shared.collector.get_chips().to_vec(),
+    }
+}
+
+fn respond(shared: &SharedState, request: Request) -> Response {
+    let shared = lock(shared);
+    match request {
+        // Only checked at the start of a connection, and only if a token is set
+        Request::Auth { .. } => Response::Authenticated,
+        Request::ListChips => Response::Chips {
+            chips: shared
+                .collector
+                .get_chips()
+                .iter()
+                .map(|chip| chip.name.clone())
+                .collect(),
+            offline_chips: shared.collector.get_offline_chips().to_vec(),
+        },
+        Request::Snapshot => snapshot(&shared),
+        Request::History { sensor } => match shared.collector.get_historical_data(&sensor) {
+            Some(history) => Response::History {
+                sensor,
+                samples: history.buf.iter().copied().collect(),
+            },
+            None => Response::Error {
+                message: format!("unknown sensor {}/{}", sensor.chip, sensor.label),
+            },
+        },
+        Request::Subscribe => unreachable!("subscriptions are handled by the connection"),
+    }
+}
+
+fn stream_snapshots<W: Write>(writer: &mut W, shared: &SharedState) -> io::Result<()> {
+    let mut generation = lock(shared).generation;
+    loop {
+        let response = {
+            let mut state = lock(shared);
+            while state.generation == generation {
+                state = shared.1.wait(state).unwrap_or_else(PoisonError::into_inner);
+            }
+            generation = state.generation;
+            snapshot(&state)
+        };
+        // Fails once the client goes away, which ends the subscription
+        write_message(writer, &response)?;
+    }
+}
+
+// Takes as long for every wrong token of the right length
+fn tokens_match(a: &str, b: &str) -> bool {
+    a.len() == b.len()
+        && a.bytes()
+            .zip(b.bytes())
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+/// Answers requests until the client disconnects or subscribes. With a
+/// `token`, the first request has to be a matching `Request::Auth`.
+/// `on_authenticated` runs once the client may send requests
+pub fn handle_client<R: BufRead, W: Write>(
+    mut reader: R,
+    mut writer: W,
+    shared: &SharedState,
+    token: Option<&str>,
+    on_authenticated: impl FnOnce(),
+) -> io::Result<()> {
+    if let Some(token) = token {
+        match read_message_with_limit::<_, Request>(&mut reader, MAX_REQUEST_LEN) {
+            Ok(None) => return Ok(()),
+            Ok(Some(Request::Auth { token: given })) if tokens_match(&given, token) => {
+                write_message(&mut writer, &Response::Authenticated)?;
+            }
+            _ => {
+                let message = String::from("authentication failed");
+                write_message(&mut writer, &Response::Error { message })?;
+                return Err(io::Error::new(
+                    ErrorKind::PermissionDenied,
+                    "client failed to authenticate",
+                ));
+            }
+        }
+    }
+    on_authenticated();
+    loop {
+        let response = match read_message_with_limit::<_, Request>(&mut reader, MAX_REQUEST_LEN) {
+            Ok(None) => return Ok(()),
+            Ok(Some(Request::Subscribe)) => return stream_snapshots(&mut writer, shared),
+            Ok(Some(request)) => respond(shared, request),
+            Err(e) if e.kind() == ErrorKind::InvalidData => Response::Error {
+                message: format!("invalid request: {}", e),
+            },
+            Err(e) => return Err(e),
+        };
+        write_message(&mut writer, &response)?;
+    }
+}
+
+/// Handles a connection on its own thread, `streams` are its read and write halves
+fn spawn_client<S: Read + Write + Send + 'static>(
+    streams: io::Result<(S, S)>,
+    peer: String,
+    shared: &SharedState,
+    token: Option<String>,
+    on_authenticated: impl FnOnce() + Send + 'static,
+) {
+    let shared = Arc::clone(shared);
+    thread::spawn(move || {
+        let result = streams.and_then(|(reader, writer)| {
+            let reader = BufReader::new(reader);
+            handle_client(reader, writer, &shared, token.as_deref(), on_authenticated)
+        });
+        if let Err(e) = result {
+            debug!("connection from {} closed: {}", peer, e);
+        }
+    });
+}
+
+fn serve_unix(listener: UnixListener, shared: SharedState) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => spawn_client(
+                stream.try_clone().map(|reader| (reader, stream)),
+                String::from("local client"),
+                &shared,
+                None,
+                || {},
+            ),
+            Err(e) => debug!("failed to accept connection: {}", e),
+        }
+    }
+}
+
+pub fn serve_tcp(listener: TcpListener, shared: SharedState, token: Option<String>) {
+    for stream in listener.incoming() {
+        match stream {
+            Ok(stream) => {
+                let peer = stream
+                    .peer_addr()
+                    .map_or_else(|_| String::from("unknown peer"), |addr| addr.to_string());
+                info!("connection from {}", peer);
+                // Snapshots are small and sent once per tick, don't hold them
+                // back. Idle subscribers are fine, idle strangers are not
+                let streams = stream
+                    .set_nodelay(true)
+                    .and_then(|_| stream.set_read_timeout(Some(AUTH_TIMEOUT)))
+                    .and_then(|_| stream.try_clone())
+                    .map(|reader| (reader, stream));
+                let timeout_stream = streams.as_ref().ok().map(|(reader, _)| reader.try_clone());
+                let on_authenticated = move || {
+                    if let Some(Ok(stream)) = timeout_stream {
+                        let _ = stream.set_read_timeout(None);
+                    }
+                };
+                spawn_client(streams, peer, &shared, token.clone(), on_authenticated);
+            }
+            Err(e) => debug!("failed to accept connection: {}", e),
+        }
+    }
+}
+
+/// Binds the socket, replacing a stale one left behind by a daemon that didn't
+/// shut down cleanly
+fn bind(socket_path: &Path) -> Result<UnixListener, Box<dyn Error>> {
+    if socket_path.exists() {
+        if UnixStream::connect(socket_path).is_ok() {
+            return Err(
+                format!("a daemon is already listening on {}", socket_path.display()).into(),
+            );
+        }
+        fs::remove_file(socket_path)?;
+    }
+    if let Some(dir) = socket_path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let listener = UnixListener::bind(socket_path)?;
+    fs::set_permissions(socket_path, fs::Permissions::from_mode(0o600))?;
+
+    Ok(listener)
+}
+
+/// Takes a sample and wakes up the subscribers
+pub fn publish(shared: &SharedState, source: &mut dyn SensorSource) {
+    {
+        let mut state = lock(shared);
+        source.poll(&mut state.collector);
+        state.generation += 1;
+        state.time = Some(SystemTime::now());
+    }
+    shared.1.notify_all();
+}
+
+fn latest_batch(shared: &SharedState, sensors: Option<&Selector>) -> Option<Batch> {
+    let state = lock(shared);
+    let readings = select_readings(sensors, state.collector.get_chips());
+    state.time.map(|time| Batch::new(time, readings))
+}
+
+/// Pushes the latest readings every `config.interval` on its own thread
+fn spawn_exporter(config: ExporterConfig, shared: &SharedState) {
+    let shared = Arc::clone(shared);
+    let sensors = config.sensors.clone();
+    let mut exporter = Exporter::new(config);
+    thread::spawn(move || loop {
+        thread::sleep(exporter.interval());
+        if let Some(batch) = latest_batch(&shared, sensors.as_ref()) {
+            exporter.push(batch);
+        }
+    });
+}
+
+fn spawn_mqtt_publisher(config: MqttConfig, shared: &SharedState) {
+    let shared = Arc::clone(shared);
+    let sensors = config.sensors.clone();
+    let mut publisher = MqttPublisher::new(config);
+    thread::spawn(move || loop {
+        thread::sleep(publisher.interval());
+        if let Some(batch) = latest_batch(&shared, sensors.as_ref()) {
+            publisher.publish(&batch);
+        }
+    });
+}
+
+/// Samples `source` every `tick_rate` and serves the collected data on
+/// `endpoint` until terminated
+pub fn run_daemon(
+    endpoint: &Endpoint,
+    tick_rate: Duration,
+    mut source: Box<dyn SensorSource>,
+    config: Config,
+) -> Result<(), Box<dyn Error>> {
+    let shared: SharedState = Arc::default();
+    let server_shared = Arc::clone(&shared);
+    match endpoint {
+        Endpoint::Unix(socket_path) => {
+            let listener = bind(socket_path)?;
+            info!("listening on {}", socket_path.display());
+            thread::spawn(move || serve_unix(listener, server_shared));
+        }
+        Endpoint::Tcp { address, token } => {
+            let listener = TcpListener::bind(address)?;
+            let local_address = listener.local_addr()?;
+            if token.is_none() && !local_address.ip().is_loopback() {
+                return Err(format!(
+                    "refusing to serve on {} without a token, set --token or $SENSO_TOKEN",
+                    local_address
+                )
+                .into());
+            }
+            info!("listening on {}", local_address);
+            let token = token.clone();
+            thread::spawn(move || serve_tcp(listener, server_shared, token));
+        }
+    }
+
+    for exporter in config.exporters {
+        info!("exporting to {}", exporter.target);
+        spawn_exporter(exporter, &shared);
+    }
+    if let Some(mqtt) = config.mqtt {
+        info!("publishing to mqtt broker {}", mqtt.broker);
+        spawn_mqtt_publisher(mqtt, &shared);
+    }
+
+    let should_quit = Arc::new(AtomicBool::new(false));
+    for signal in [SIGTERM, SIGHUP, SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(&should_quit))?;
+    }
+
+    // libsensors handles can't leave this thread, so sampling stays here
+    while !should_quit.load(Ordering::Relaxed) {
+        publish(&shared, source.as_mut());
+        thread::sleep(tick_rate);
+    }
+
+    info!("shutting down");
+    if let Endpoint::Unix(socket_path) = endpoint {
+        fs::remove_file(socket_path)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::BufReader,
+        net::{TcpListener, TcpStream},
+        os::unix::net::UnixStream,
+        sync::Arc,
+        thread,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{handle_client, lock, serve_tcp, SharedState};
+    use crate::{
+        protocol::{read_message, write_message, Request, Response},
+        sensors::{SensorId, SensorKind},
+        snapshot::fixtures::{chip, input},
+    };
+
+    fn shared_with_chip() -> SharedState {
+        let shared: SharedState = Arc::default();
+        let chip = chip(
+            "coretemp-isa-0000",
+            vec![input("temp1", "Core 0", SensorKind::Temperature, 42.0)],
+        );
+        lock(&shared)
+            .collector
+            .ingest(vec![chip], SystemTime::now());
+        shared
+    }
+
+    #[test]
+    fn test_requests_over_a_socket() {
+        let shared = shared_with_chip();
+        let (client, server) = UnixStream::pair().unwrap();
+        let server_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            let reader = BufReader::new(server.try_clone().unwrap());
+            handle_client(reader, server, &server_shared, None, || {})
+        });
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        let mut writer = client;
+
+        write_message(&mut writer, &Request::ListChips).unwrap();
+        assert!(
+            read_message::<_, Response>(&mut reader).unwrap()
+                == Some(Response::Chips {
+                    chips: vec![String::from("coretemp-isa-0000")],
+                    offline_chips: vec![],
+                })
+        );
+
+        let sensor = SensorId::new("coretemp-isa-0000", "Core 0");
+        write_message(&mut writer, &Request::History { sensor }).unwrap();
+        let history = read_message::<_, Response>(&mut reader).unwrap();
+        assert!(matches!(history, Some(Response::History { samples, .. }) if samples.len() == 1));
+
+        write_message(
+            &mut writer,
+            &Request::History {
+                sensor: SensorId::new("x", "y"),
+            },
+        )
+        .unwrap();
+        let error = read_message::<_, Response>(&mut reader).unwrap();
+        assert!(matches!(error, Some(Response::Error { .. })));
+    }
+
+    #[test]
+    fn test_subscribers_get_every_new_sample() {
+        let shared = shared_with_chip();
+        let (client, server) = UnixStream::pair().unwrap();
+        let server_shared = Arc::clone(&shared);
+        thread::spawn(move || {
+            let reader = BufReader::new(server.try_clone().unwrap());
+            handle_client(reader, server, &server_shared, None, || {})
+        });
+        let mut reader = BufReader::new(client.try_clone().unwrap());
+        let mut writer = client;
+        write_message(&mut writer, &Request::Subscribe).unwrap();
+
+        // Keep publishing, the subscription may not be registered yet
+        thread::spawn(move || loop {
+            lock(&shared).generation += 1;
+            shared.1.notify_all();
+            thread::sleep(Duration::from_millis(10));
+        });
+        let snapshot = read_message::<_, Response>(&mut reader).unwrap();
+        assert!(matches!(snapshot, Some(Response::Snapshot { chips, .. }) if chips.len() == 1));
+    }
+
+    #[test]
+    fn test_tcp_clients_need_the_token() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap();
+        let shared = shared_with_chip();
+        thread::spawn(move || serve_tcp(listener, shared, Some(String::from("secret"))));
+
+        let request = |token: &str| {
+            let stream = TcpStream::connect(address).unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut writer = stream;
+            let token = String::from(token);
+            write_message(&mut writer, &Request::Auth { token }).unwrap();
+            let auth = read_message::<_, Response>(&mut reader).unwrap();
+            // The server hangs up on a wrong token, so this may fail
+            let _ = write_message(&mut writer, &Request::ListChips);
+            (
+                auth,
+                read_message::<_, Response>(&mut reader).ok().flatten(),
+            )
+        };
+
+        let (auth, chips) = request("secret");
+        assert!(auth == Some(Response::Authenticated));
+        assert!(matches!(chips, Some(Response::Chips { .. })));
+
+        let (auth, chips) = request("guess");
+        assert!(matches!(auth, Some(Response::Error { .. })));
+        assert!(chips.is_none());
+    }
+}
@@ -0,0 +1,517 @@
+use std::{
+    collections::{BTreeMap, VecDeque},
+    env,
+    fmt::{self, Display, Formatter},
+    fs,
+    io::{self, BufRead, BufReader, ErrorKind, Write},
+    net::{TcpStream, ToSocketAddrs, UdpSocket},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+
+use crate::{remote, selector::Selector, sensors::Reading};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+/// Stays below the usual MTU so datagrams aren't fragmented
+const MAX_DATAGRAM_SIZE: usize = 1400;
+
+/// Where an exporter sends readings to
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ExportTarget {
+    /// InfluxDB line protocol POSTed to a write endpoint, e.g.
+    /// `http://localhost:8086/api/v2/write?org=lab&bucket=senso`
+    InfluxHttp {
+        url: String,
+        #[serde(default)]
+        token: Option<String>,
+    },
+    /// InfluxDB line protocol over UDP
+    InfluxUdp { address: String },
+    /// StatsD gauges over UDP
+    Statsd {
+        address: String,
+        #[serde(default = "default_prefix")]
+        prefix: String,
+    },
+    /// Graphite plaintext protocol over TCP
+    Graphite {
+        address: String,
+        #[serde(default = "default_prefix")]
+        prefix: String,
+    },
+}
+
+fn default_prefix() -> String {
+    String::from("senso")
+}
+
+fn default_interval() -> u64 {
+    10
+}
+
+fn default_buffer() -> usize {
+    360
+}
+
+impl Display for ExportTarget {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::InfluxHttp { url, .. } => write!(f, "influx {}", url),
+            Self::InfluxUdp { address } => write!(f, "influx udp://{}", address),
+            Self::Statsd { address, .. } => write!(f, "statsd {}", address),
+            Self::Graphite { address, .. } => write!(f, "graphite {}", address),
+        }
+    }
+}
+
+/// A push exporter as configured in the config file
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExporterConfig {
+    #[serde(flatten)]
+    pub target: ExportTarget,
+    /// Seconds between pushes
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// Added to every metric, `host` defaults to the hostname
+    #[serde(default)]
+    pub tags: BTreeMap<String, String>,
+    /// Pushes kept while the target is unreachable, the oldest are dropped first
+    #[serde(default = "default_buffer")]
+    pub buffer: usize,
+    /// A selector like `kind=temperature`, all sensors are exported without one
+    #[serde(default)]
+    pub sensors: Option<Selector>,
+}
+
+impl ExporterConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.interval == 0 {
+            return Err(format!(
+                "{}: interval has to be at least 1 second",
+                self.target
+            ));
+        }
+        if let ExportTarget::InfluxHttp { url, .. } = &self.target {
+            if !url.starts_with("http://") {
+                return Err(format!("{}: only http:// urls are supported", self.target));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// `/proc/sys/kernel/hostname`, falling back to `$HOSTNAME`
+pub fn hostname() -> String {
+    fs::read_to_string("/proc/sys/kernel/hostname")
+        .ok()
+        .or_else(|| env::var("HOSTNAME").ok())
+        .map(|name| String::from(name.trim()))
+        .filter(|name| !name.is_empty())
+        .unwrap_or_else(|| String::from("localhost"))
+}
+
+/// The readable values of one sample
+#[derive(Debug, Clone)]
+pub struct Batch {
+    time: SystemTime,
+    readings: Vec<(Reading, f64)>,
+}
+
+impl Batch {
+    pub fn new(time: SystemTime, readings: Vec<Reading>) -> Self {
+        Self {
+            time,
+            readings: readings
+                .into_iter()
+                .filter_map(|reading| {
+                    let value = *reading.value.as_ref().ok()?;
+                    Some((reading, value))
+                })
+                .collect(),
+        }
+    }
+
+    pub fn readings(&self) -> &[(Reading, f64)] {
+        &self.readings
+    }
+
+    fn unix_time(&self) -> Duration {
+        self.time.duration_since(UNIX_EPOCH).unwrap_or_default()
+    }
+}
+
+fn escape_influx(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+}
+
+pub fn influx_lines(batch: &Batch, tags: &BTreeMap<String, String>) -> Vec<String> {
+    let tags: String = tags
+        .iter()
+        .map(|(key, value)| format!(",{}={}", escape_influx(key), escape_influx(value)))
+        .collect();
+    batch
+        .readings
+        .iter()
+        .map(|(reading, value)| {
+            format!(
+                "senso{},chip={},sensor={},kind={} value={} {}",
+                tags,
+                escape_influx(&reading.id.chip),
+                escape_influx(&reading.id.label),
+                reading.kind.name(),
+                value,
+                batch.unix_time().as_nanos()
+            )
+        })
+        .collect()
+}
+
+/// Metric paths can't contain dots or spaces, `Core 0` becomes `Core_0`
+fn path_segment(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            'a'..='z' | 'A'..='Z' | '0'..='9' | '-' | '_' => c,
+            _ => '_',
+        })
+        .collect()
+}
+
+/// `prefix.<tag values>.chip.sensor`, tag values sorted by tag name since
+/// neither StatsD nor Graphite plaintext have tags
+fn metric_path(prefix: &str, tags: &BTreeMap<String, String>, reading: &Reading) -> String {
+    let mut segments = vec![String::from(prefix)];
+    segments.extend(tags.values().map(|value| path_segment(value)));
+    segments.push(path_segment(&reading.id.chip));
+    segments.push(path_segment(&reading.id.label));
+    segments.join(".")
+}
+
+pub fn statsd_lines(batch: &Batch, prefix: &str, tags: &BTreeMap<String, String>) -> Vec<String> {
+    batch
+        .readings
+        .iter()
+        .map(|(reading, value)| format!("{}:{}|g", metric_path(prefix, tags, reading), value))
+        .collect()
+}
+
+pub fn graphite_lines(batch: &Batch, prefix: &str, tags: &BTreeMap<String, String>) -> Vec<String> {
+    batch
+        .readings
+        .iter()
+        .map(|(reading, value)| {
+            format!(
+                "{} {} {}",
+                metric_path(prefix, tags, reading),
+                value,
+                batch.unix_time().as_secs()
+            )
+        })
+        .collect()
+}
+
+fn connect_tcp(address: &str) -> io::Result<TcpStream> {
+    let address = address
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "address didn't resolve"))?;
+    let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+    stream.set_read_timeout(Some(TIMEOUT))?;
+    stream.set_write_timeout(Some(TIMEOUT))?;
+    Ok(stream)
+}
+
+/// Packs as many lines into each datagram as fit
+fn send_udp(address: &str, lines: &[String]) -> io::Result<()> {
+    let socket = UdpSocket::bind("0.0.0.0:0")?;
+    socket.connect(address)?;
+    let mut datagram = String::new();
+    for line in lines {
+        if !datagram.is_empty() && datagram.len() + line.len() + 1 > MAX_DATAGRAM_SIZE {
+            socket.send(datagram.as_bytes())?;
+            datagram.clear();
+        }
+        datagram.push_str(line);
+        datagram.push('\n');
+    }
+    if !datagram.is_empty() {
+        socket.send(datagram.as_bytes())?;
+    }
+    Ok(())
+}
+
+fn send_tcp(address: &str, lines: &[String]) -> io::Result<()> {
+    let mut stream = connect_tcp(address)?;
+    for line in lines {
+        writeln!(stream, "{}", line)?;
+    }
+    stream.flush()
+}
+
+/// A minimal HTTP/1.1 POST, enough for InfluxDB's write endpoint
+fn post(url: &str, token: Option<&str>, lines: &[String]) -> io::Result<()> {
+    let invalid = |message: &str| io::Error::new(ErrorKind::InvalidInput, message);
+    let rest = url
+        .strip_prefix("http://")
+        .ok_or_else(|| invalid("only http:// urls are supported"))?;
+    let (host, path) = match rest.find('/') {
+        Some(i) => rest.split_at(i),
+        None => (rest, "/"),
+    };
+    let address = remote::with_default_port(host, 80);
+    let body = lines.join("\n");
+
+    let mut stream = connect_tcp(&address)?;
+    write!(
+        stream,
+        "POST {} HTTP/1.1\r\nHost: {}\r\nContent-Type: text/plain; charset=utf-8\r\nContent-Length: {}\r\nConnection: close\r\n",
+        path,
+        host,
+        body.len()
+    )?;
+    if let Some(token) = token {
+        write!(stream, "Authorization: Token {}\r\n", token)?;
+    }
+    write!(stream, "\r\n{}", body)?;
+    stream.flush()?;
+
+    let mut status_line = String::new();
+    BufReader::new(stream).read_line(&mut status_line)?;
+    match status_line.split_whitespace().nth(1) {
+        Some(status) if status.starts_with('2') => Ok(()),
+        _ => Err(io::Error::other(format!(
+            "server answered {:?}",
+            status_line.trim()
+        ))),
+    }
+}
+
+/// Pushes batches to one target, buffering them while it is unreachable
+pub struct Exporter {
+    config: ExporterConfig,
+    tags: BTreeMap<String, String>,
+    pending: VecDeque<Batch>,
+    is_failing: bool,
+}
+
+impl Exporter {
+    pub fn new(config: ExporterConfig) -> Self {
+        let mut tags = config.tags.clone();
+        tags.entry(String::from("host")).or_insert_with(hostname);
+        Self {
+            config,
+            tags,
+            pending: VecDeque::new(),
+            is_failing: false,
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.config.interval)
+    }
+
+    /// Sends `batch` along with whatever is still buffered, oldest first
+    pub fn push(&mut self, batch: Batch) {
+        if self.pending.len() >= self.config.buffer.max(1) {
+            self.pending.pop_front();
+        }
+        self.pending.push_back(batch);
+        while let Some(batch) = self.pending.front() {
+            match self.send(batch) {
+                Ok(()) => {
+                    self.pending.pop_front();
+                    if self.is_failing {
+                        info!("{}: delivering again", self.config.target);
+                        self.is_failing = false;
+                    }
+                }
+                Err(e) => {
+                    // Log once per outage, retried on every push
+                    if !self.is_failing {
+                        warn!("{}: {}, buffering until it's back", self.config.target, e);
+                        self.is_failing = true;
+                    }
+                    break;
+                }
+            }
+        }
+    }
+
+    fn send(&self, batch: &Batch) -> io::Result<()> {
+        if batch.readings.is_empty() {
+            return Ok(());
+        }
+        match &self.config.target {
+            ExportTarget::InfluxHttp { url, token } => {
+                post(url, token.as_deref(), &influx_lines(batch, &self.tags))
+            }
+            ExportTarget::InfluxUdp { address } => {
+                send_udp(address, &influx_lines(batch, &self.tags))
+            }
+            ExportTarget::Statsd { address, prefix } => {
+                send_udp(address, &statsd_lines(batch, prefix, &self.tags))
+            }
+            ExportTarget::Graphite { address, prefix } => {
+                send_tcp(address, &graphite_lines(batch, prefix, &self.tags))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        collections::BTreeMap,
+        io::{BufRead, BufReader, Read, Write},
+        net::{TcpListener, UdpSocket},
+        thread,
+        time::{Duration, UNIX_EPOCH},
+    };
+
+    use super::{
+        graphite_lines, influx_lines, statsd_lines, Batch, ExportTarget, Exporter, ExporterConfig,
+    };
+    use crate::{sensors::SensorKind, snapshot::fixtures::reading};
+
+    fn batch() -> Batch {
+        let reading = reading("coretemp-isa-0000", "Core 0", SensorKind::Temperature, 42.5);
+        Batch::new(
+            UNIX_EPOCH + Duration::from_secs(1_700_000_000),
+            vec![reading],
+        )
+    }
+
+    fn tags() -> BTreeMap<String, String> {
+        BTreeMap::from([
+            (String::from("host"), String::from("lab1")),
+            (String::from("rack"), String::from("r 2")),
+        ])
+    }
+
+    fn config(target: ExportTarget) -> ExporterConfig {
+        ExporterConfig {
+            target,
+            interval: 1,
+            tags: tags(),
+            buffer: 2,
+            sensors: None,
+        }
+    }
+
+    #[test]
+    fn test_line_formats() {
+        assert!(
+            influx_lines(&batch(), &tags())
+                == vec![String::from(
+                    "senso,host=lab1,rack=r\\ 2,chip=coretemp-isa-0000,sensor=Core\\ 0,kind=temperature value=42.5 1700000000000000000"
+                )]
+        );
+        assert!(
+            statsd_lines(&batch(), "senso", &tags())
+                == vec![String::from(
+                    "senso.lab1.r_2.coretemp-isa-0000.Core_0:42.5|g"
+                )]
+        );
+        assert!(
+            graphite_lines(&batch(), "senso", &tags())
+                == vec![String::from(
+                    "senso.lab1.r_2.coretemp-isa-0000.Core_0 42.5 1700000000"
+                )]
+        );
+    }
+
+    #[test]
+    fn test_statsd_over_udp() {
+        let listener = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let prefix = String::from("senso");
+        let mut exporter = Exporter::new(config(ExportTarget::Statsd { address, prefix }));
+        exporter.push(batch());
+
+        let mut buf = [0; 1500];
+        let len = listener.recv(&mut buf).unwrap();
+        assert!(&buf[..len] == b"senso.lab1.r_2.coretemp-isa-0000.Core_0:42.5|g\n");
+    }
+
+    #[test]
+    fn test_influx_over_http() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/api/v2/write?bucket=senso",
+            listener.local_addr().unwrap()
+        );
+        let server = thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut reader = BufReader::new(stream.try_clone().unwrap());
+            let mut request = String::new();
+            let mut content_length = 0;
+            loop {
+                let mut line = String::new();
+                reader.read_line(&mut line).unwrap();
+                if let Some(length) = line.strip_prefix("Content-Length: ") {
+                    content_length = length.trim().parse().unwrap();
+                }
+                request.push_str(&line);
+                if line == "\r\n" {
+                    break;
+                }
+            }
+            let mut body = vec![0; content_length];
+            reader.read_exact(&mut body).unwrap();
+            let mut stream = stream;
+            stream
+                .write_all(b"HTTP/1.1 204 No Content\r\n\r\n")
+                .unwrap();
+            (request, String::from_utf8(body).unwrap())
+        });
+
+        let token = Some(String::from("secret"));
+        let mut exporter = Exporter::new(config(ExportTarget::InfluxHttp { url, token }));
+        exporter.push(batch());
+        let (request, body) = server.join().unwrap();
+
+        assert!(request.starts_with("POST /api/v2/write?bucket=senso HTTP/1.1\r\n"));
+        assert!(request.contains("Authorization: Token secret\r\n"));
+        assert!(body.starts_with("senso,host=lab1"));
+        assert!(exporter.pending.is_empty());
+    }
+
+    #[test]
+    fn test_graphite_buffers_while_unreachable() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let target = ExportTarget::Graphite {
+            address: address.clone(),
+            prefix: String::from("senso"),
+        };
+        let mut exporter = Exporter::new(config(target));
+        exporter.push(batch());
+        exporter.push(batch());
+        exporter.push(batch());
+        // Only `buffer` pushes are kept, the next push drops the oldest again
+        assert!(exporter.pending.len() == 2);
+
+        let listener = TcpListener::bind(&address).unwrap();
+        let server = thread::spawn(move || {
+            let mut lines = 0;
+            for _ in 0..2 {
+                let (stream, _) = listener.accept().unwrap();
+                lines += BufReader::new(stream).lines().count();
+            }
+            lines
+        });
+        exporter.push(batch());
+
+        assert!(exporter.pending.is_empty());
+        assert!(server.join().unwrap() == 2);
+    }
+}
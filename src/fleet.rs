@@ -0,0 +1,214 @@
+use crate::{
+    collector::Collector,
+    config::HostConfig,
+    remote::{Address, RemoteSource},
+    source::{HostStatus, SensorSource, SourceStatus},
+};
+
+struct Host {
+    name: String,
+    source: RemoteSource,
+}
+
+/// Shows the chips of several `senso serve` instances side by side
+pub struct FleetSource {
+    hosts: Vec<Host>,
+}
+
+impl FleetSource {
+    /// Connects to every host in the background, unreachable hosts are retried
+    pub fn new(hosts: &[HostConfig]) -> Self {
+        Self {
+            hosts: hosts
+                .iter()
+                .map(|host| Host {
+                    name: host.name.clone(),
+                    source: RemoteSource::new(Address::tcp(&host.address), host.token.clone())
+                        .on_host(&host.name),
+                })
+                .collect(),
+        }
+    }
+}
+
+impl SensorSource for FleetSource {
+    fn poll(&mut self, collector: &mut Collector) {
+        for host in &mut self.hosts {
+            host.source.poll(collector);
+        }
+    }
+
+    /// Every server rescans on its own
+    fn rescan(&mut self) {}
+
+    /// Stale only when no host is live, single hosts are marked in the host list
+    fn status(&self) -> SourceStatus {
+        if self
+            .hosts
+            .iter()
+            .any(|host| host.source.status() == SourceStatus::Live)
+        {
+            return SourceStatus::Live;
+        }
+        SourceStatus::Stale {
+            age: self
+                .hosts
+                .iter()
+                .filter_map(|host| host.source.get_last_update())
+                .max()
+                .map(|time| time.elapsed()),
+            reason: String::from("no host is reachable"),
+        }
+    }
+
+    fn hosts(&self) -> Vec<HostStatus> {
+        self.hosts
+            .iter()
+            .map(|host| HostStatus {
+                name: host.name.clone(),
+                status: host.source.status(),
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::Arc,
+        thread,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use super::FleetSource;
+    use crate::{
+        collector::Collector,
+        config::HostConfig,
+        daemon::{publish, serve_tcp, SharedState},
+        sensors::{SensorId, SensorKind},
+        snapshot::fixtures::{chip, input},
+        source::{SensorSource, SourceStatus},
+    };
+
+    /// An NVMe drive at a fixed temperature
+    struct FakeSource(f64);
+
+    impl SensorSource for FakeSource {
+        fn poll(&mut self, collector: &mut Collector) {
+            let chip = chip(
+                "nvme-pci-0100",
+                vec![input("temp1", "Composite", SensorKind::Temperature, self.0)],
+            );
+            collector.ingest(vec![chip], SystemTime::now());
+        }
+
+        fn rescan(&mut self) {}
+    }
+
+    /// Serves `temperature` on a loopback port and returns the host entry for it
+    fn daemon(name: &str, temperature: f64) -> HostConfig {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let shared: SharedState = Arc::default();
+        let server_shared = Arc::clone(&shared);
+        thread::spawn(move || serve_tcp(listener, server_shared, None));
+        thread::spawn(move || loop {
+            publish(&shared, &mut FakeSource(temperature));
+            thread::sleep(Duration::from_millis(10));
+        });
+        HostConfig {
+            name: String::from(name),
+            address,
+            token: None,
+        }
+    }
+
+    /// A host entry nothing listens on
+    fn unreachable(name: &str) -> HostConfig {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+        HostConfig {
+            name: String::from(name),
+            address,
+            token: None,
+        }
+    }
+
+    /// Polls `source` until `condition` holds or a few seconds passed
+    fn poll_until(
+        source: &mut FleetSource,
+        collector: &mut Collector,
+        condition: impl Fn(&FleetSource, &Collector) -> bool,
+    ) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(5) {
+            source.poll(collector);
+            if condition(source, collector) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    fn composite(collector: &Collector, chip: &str) -> Option<f64> {
+        let id = SensorId::new(chip, "Composite");
+        collector
+            .get_readings()
+            .into_iter()
+            .find(|reading| reading.id == id)
+            .and_then(|reading| reading.value.ok())
+    }
+
+    #[test]
+    fn test_merges_the_chips_of_every_host() {
+        let mut source = FleetSource::new(&[daemon("lab1", 38.0), daemon("lab2", 45.0)]);
+        let mut collector = Collector::default();
+
+        assert!(poll_until(&mut source, &mut collector, |_, collector| {
+            collector.get_chips().len() == 2
+        }));
+        assert!(composite(&collector, "lab1/nvme-pci-0100") == Some(38.0));
+        assert!(composite(&collector, "lab2/nvme-pci-0100") == Some(45.0));
+        assert!(poll_until(&mut source, &mut collector, |source, _| {
+            source
+                .hosts()
+                .iter()
+                .all(|host| host.status == SourceStatus::Live)
+        }));
+        assert!(source.status() == SourceStatus::Live);
+    }
+
+    #[test]
+    fn test_unreachable_host_is_stale() {
+        let mut source = FleetSource::new(&[daemon("lab1", 38.0), unreachable("lab2")]);
+        let mut collector = Collector::default();
+
+        assert!(poll_until(&mut source, &mut collector, |source, _| {
+            source.status() == SourceStatus::Live
+        }));
+        let hosts = source.hosts();
+        assert!(hosts[0].name == "lab1" && hosts[0].status == SourceStatus::Live);
+        assert!(hosts[1].name == "lab2");
+        assert!(matches!(
+            &hosts[1].status,
+            SourceStatus::Stale { age: None, reason } if reason.contains("connect")
+        ));
+        assert!(collector
+            .get_chips()
+            .iter()
+            .all(|chip| chip.host.as_deref() == Some("lab1")));
+    }
+
+    #[test]
+    fn test_stale_when_no_host_is_reachable() {
+        let source = FleetSource::new(&[unreachable("lab1"), unreachable("lab2")]);
+
+        assert!(matches!(
+            source.status(),
+            SourceStatus::Stale { age: None, reason } if reason == "no host is reachable"
+        ));
+    }
+}
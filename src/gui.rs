@@ -1,62 +1,66 @@
-use crossterm::{
-    event::{self, DisableMouseCapture, EnableMouseCapture},
-    execute,
-    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
-};
+use crossterm::event;
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
 
-use std::{cell::RefCell, error::Error};
+use std::{
+    cell::RefCell,
+    error::Error,
+    iter::zip,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
 use std::{thread, time::Duration};
 
 use ratatui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
-    style::Style,
+    style::{Color, Style},
     text::Span,
-    widgets::{Block, Borders},
+    widgets::{Block, Borders, Paragraph},
     Frame,
 };
 
 use crate::{
-    app::App,
+    app::{App, ChartPanel, InputMode, Screen},
+    cpu::is_cpu_chip,
     components::{
         chip_info::chip_info_panel,
         chip_list::{chip_list, ChipListProps},
+        cpu_panel::cpu_panel,
+        log_pane::log_pane,
+        temperature_graphs::combined_chart,
+        overview::overview,
         temperature_graphs::temperature_graphs,
     },
     input::handle_input,
-    terminal,
+    pins::Pin,
+    source::{SensorSource, SourceStatus},
+    terminal::TerminalGuard,
 };
 
-pub fn run_gui(tick_rate: Duration) -> Result<(), Box<dyn Error>> {
-    let mut terminal = terminal::get_terminal().unwrap();
-    enable_raw_mode()?;
-    let app = App::new();
-
-    terminal.autoresize()?;
+pub fn run_gui(tick_rate: Duration, source: Box<dyn SensorSource>) -> Result<(), Box<dyn Error>> {
+    let app = App::new(source);
 
-    execute!(
-        terminal.backend_mut(),
-        EnterAlternateScreen,
-        EnableMouseCapture
-    )?;
+    // Raw mode turns Ctrl-C into a key event, handled as quit by `handle_input`.
+    // SIGTERM and SIGHUP still need a clean exit
+    let should_quit = Arc::new(AtomicBool::new(false));
+    for signal in [SIGTERM, SIGHUP, SIGINT] {
+        signal_hook::flag::register(signal, Arc::clone(&should_quit))?;
+    }
 
+    let mut terminal = TerminalGuard::new()?;
     let app = RefCell::new(app);
 
     // Render Loop
-    loop {
-        terminal
-            .draw(|f| {
-                draw_ui(f, &app.borrow());
-            })
-            .unwrap();
+    while !should_quit.load(Ordering::Relaxed) {
+        terminal.draw(|f| {
+            draw_ui(f, &app.borrow());
+        })?;
         let mut should_break = false;
-        while let Ok(event_available) = event::poll(Duration::from_millis(0)) {
-            if event_available {
-                if handle_input(&event::read().unwrap(), &app).is_err() {
-                    should_break = true;
-                    break;
-                }
-            } else {
+        while event::poll(Duration::from_millis(0))? {
+            if handle_input(&event::read()?, &app).is_err() {
+                should_break = true;
                 break;
             }
         }
@@ -67,71 +71,224 @@ pub fn run_gui(tick_rate: Duration) -> Result<(), Box<dyn Error>> {
         thread::sleep(tick_rate);
     }
 
-    disable_raw_mode().unwrap();
-    execute!(
-        terminal.backend_mut(),
-        LeaveAlternateScreen,
-        DisableMouseCapture
-    )
-    .unwrap();
-    terminal.show_cursor().unwrap();
-
     Ok(())
 }
 
 fn draw_ui<B: Backend>(f: &mut Frame<B>, app: &App) {
-    let constraints = if let Some(_pinned_chip) = app.state.get_pinned_chip() {
+    let area = if app.state.is_log_visible() {
+        let chunks = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(75), Constraint::Percentage(25)])
+            .split(f.size());
+        log_pane(f, chunks[1]);
+        chunks[0]
+    } else {
+        f.size()
+    };
+    let pins = app.state.get_pins();
+    let constraints = if pins.is_empty() || app.state.get_screen() == Screen::Overview {
+        [Constraint::Percentage(6), Constraint::Percentage(94)].as_ref()
+    } else {
         [
             Constraint::Percentage(6),
             Constraint::Percentage(47),
             Constraint::Percentage(47),
         ]
         .as_ref()
-    } else {
-        [Constraint::Percentage(6), Constraint::Percentage(94)].as_ref()
     };
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .margin(1)
         .constraints(constraints)
-        .split(f.size());
-    let key_binds_status_line = " | Pin (P/Enter) | Down (J/🠋) | Up (K/🠉)";
-    let title_block = Block::default()
-        .title(vec![
-            Span::styled("♨️", Style::default().fg(ratatui::style::Color::Red)),
-            Span::from(" senso "),
-            Span::styled("♨️", Style::default().fg(ratatui::style::Color::Red)),
-            key_binds_status_line.into(),
-        ])
-        .borders(Borders::NONE);
+        .split(area);
+    let key_binds_status_line = match app.state.get_screen() {
+        Screen::Main => " | Overview (Tab) | Down (J/🠋) | Up (K/🠉) | Sensor ([/]) | Pin chip (p/Enter) | Pin sensor (P) | Pins (,/. focus, </> move, x unpin) | Combine (c/C) | Mark (m) | Pin marked (O) | Auto-scale (a/A) | Pause (Space) | Zoom (+/-) | Pan (H/L) | Cursor (v, h/l) | Search (/) | Next/Prev match (n/N) | Rescan (r) | CPU load (u) | Log (~)",
+        Screen::Overview => " | Chips (Tab) | Down (J/🠋) | Up (K/🠉) | Sort (s) | Open (Enter) | Search (/) | Rescan (r) | Log (~)",
+    };
+    let mut title = vec![
+        Span::styled("♨️", Style::default().fg(Color::Red)),
+        Span::from(" senso "),
+        Span::styled("♨️", Style::default().fg(Color::Red)),
+        key_binds_status_line.into(),
+    ];
+    title.extend(search_indicator(app));
+    title.extend(viewport_indicator(app));
+    title.extend(read_failures_indicator(app));
+    title.extend(source_status_indicator(app));
+    let title_block = Block::default().title(title).borders(Borders::NONE);
     f.render_widget(title_block, chunks[0]);
 
-    if let Some(_) = app.state.get_pinned_chip() {
-        draw_lower_block(
-            f,
-            app,
-            chunks[1],
-            ChipListProps {
-                is_pinned_chip_view: false,
-            },
-        );
-        draw_lower_block(
-            f,
-            app,
-            chunks[2],
-            ChipListProps {
-                is_pinned_chip_view: true,
-            },
-        );
+    if app.state.get_screen() == Screen::Overview {
+        overview(app, f, chunks[1]);
+        return;
+    }
+
+    let Some(selected_chip) = app.state.get_selected_chip() else {
+        let message = Paragraph::new(
+            " No sensor chips found. Make sure lm-sensors is set up, e.g. by running `sensors-detect`.",
+        )
+        .block(Block::default().borders(Borders::ALL));
+        f.render_widget(message, chunks[1]);
+        return;
+    };
+    draw_lower_block(
+        f,
+        app,
+        chunks[1],
+        ChipListProps {
+            chip: selected_chip,
+            feature: None,
+            is_pinned_chip_view: false,
+            is_combined: app.state.is_combined_chart(&ChartPanel::Main),
+            is_auto_scaled: app.state.is_auto_scaled_panel(&ChartPanel::Main),
+        },
+    );
+
+    if !pins.is_empty() {
+        draw_pins(f, app, chunks[2]);
+    }
+}
+
+/// Lays out pins as a stack, or as a two column grid once there are more than two
+fn draw_pins<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect) {
+    let pins = app.state.get_pins();
+    let columns = if pins.get().len() > 2 { 2 } else { 1 };
+    let rows = pins.get().chunks(columns).collect::<Vec<&[Pin]>>();
+    let row_areas = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints(
+            rows.iter()
+                .map(|_| Constraint::Ratio(1, rows.len() as u32))
+                .collect::<Vec<Constraint>>(),
+        )
+        .split(area);
+
+    for (row_index, (row, row_area)) in zip(rows.iter(), row_areas.iter()).enumerate() {
+        let cell_areas = Layout::default()
+            .direction(Direction::Horizontal)
+            .constraints(
+                (0..columns)
+                    .map(|_| Constraint::Ratio(1, columns as u32))
+                    .collect::<Vec<Constraint>>(),
+            )
+            .split(*row_area);
+        for (column_index, (pin, cell_area)) in zip(row.iter(), cell_areas.iter()).enumerate() {
+            let is_focused = row_index * columns + column_index == pins.focused();
+            draw_pin(f, app, pin, *cell_area, is_focused);
+        }
+    }
+}
+
+fn draw_pin<B: Backend>(f: &mut Frame<B>, app: &App, pin: &Pin, area: Rect, is_focused: bool) {
+    let title = match pin {
+        Pin::Chip(chip_name) => format!(" 📌 {} ", chip_name),
+        Pin::Feature(sensor_id) => format!(" 📌 {}/{} ", sensor_id.chip, sensor_id.label),
+        Pin::Group(sensor_ids) => format!(" 📌 Overlay of {} sensors ", sensor_ids.len()),
+    };
+    let border_style = if is_focused {
+        Style::default().fg(Color::Yellow)
+    } else {
+        Style::default()
+    };
+    let block = Block::default()
+        .title(title)
+        .borders(Borders::ALL)
+        .border_style(border_style);
+    let inner_area = block.inner(area);
+    f.render_widget(block, area);
+
+    let (chip_name, feature) = match pin {
+        Pin::Chip(chip_name) => (chip_name, None),
+        Pin::Feature(sensor_id) => (&sensor_id.chip, Some(sensor_id.label.as_str())),
+        Pin::Group(sensor_ids) => {
+            let readings = app.state.get_readings(sensor_ids);
+            let is_auto_scaled = app.state.is_auto_scaled_panel(&ChartPanel::Pin(pin.clone()));
+            combined_chart(app, f, inner_area, &readings, "", is_auto_scaled);
+            return;
+        }
+    };
+    let Some(chip) = app.state.get_chip_by_name(chip_name) else {
+        let message = if app.state.is_chip_offline(chip_name) {
+            " Offline, history is kept until it comes back"
+        } else {
+            " Not available"
+        };
+        f.render_widget(Paragraph::new(message), inner_area);
+        return;
+    };
+    let props = ChipListProps {
+        chip,
+        feature,
+        is_pinned_chip_view: true,
+        is_combined: app.state.is_combined_chart(&ChartPanel::Pin(pin.clone())),
+        is_auto_scaled: app.state.is_auto_scaled_panel(&ChartPanel::Pin(pin.clone())),
+    };
+    if feature.is_some() {
+        draw_feature_block(f, app, inner_area, props);
     } else {
-        draw_lower_block(
-            f,
-            app,
-            chunks[1],
-            ChipListProps {
-                is_pinned_chip_view: false,
-            },
-        );
+        draw_lower_block(f, app, inner_area, props);
+    }
+}
+
+fn viewport_indicator(app: &App) -> Vec<Span> {
+    let viewport = app.state.get_viewport();
+    let mut spans = vec![Span::from(format!(" | Zoom: {} samples", viewport.width()))];
+    if viewport.is_frozen() {
+        spans.push(Span::from(" | "));
+        spans.push(Span::styled("⏸ Paused", Style::default().fg(Color::Cyan)));
+    } else if !viewport.is_live() {
+        spans.push(Span::from(" | "));
+        spans.push(Span::styled("Panned", Style::default().fg(Color::Cyan)));
+    }
+    spans
+}
+
+fn read_failures_indicator(app: &App) -> Vec<Span> {
+    match app.state.get_collector().get_total_read_failures() {
+        0 => vec![],
+        failures => vec![
+            Span::from(" | "),
+            Span::styled(format!("⚠ {} failed reads", failures), Style::default().fg(Color::Red)),
+        ],
+    }
+}
+
+fn source_status_indicator(app: &App) -> Vec<Span> {
+    let text = match app.state.get_source_status() {
+        SourceStatus::Live => return vec![],
+        SourceStatus::Stale {
+            age: Some(age),
+            reason,
+        } => format!("⚠ Stale, last data {}s ago, {}", age.as_secs(), reason),
+        SourceStatus::Stale { age: None, reason } => format!("⚠ No data yet, {}", reason),
+    };
+    vec![
+        Span::from(" | "),
+        Span::styled(text, Style::default().fg(Color::Black).bg(Color::Yellow)),
+    ]
+}
+
+fn search_indicator(app: &App) -> Vec<Span> {
+    let is_typing = app.state.get_input_mode() == InputMode::Search;
+    match app.state.get_search() {
+        Some(search) => {
+            let cursor = if is_typing { "_" } else { "" };
+            let mut spans = vec![
+                Span::from(" | "),
+                Span::styled(
+                    format!("Filter: /{}{}", search.query(), cursor),
+                    Style::default().fg(Color::Yellow),
+                ),
+            ];
+            if let Some(error) = search.error() {
+                spans.push(Span::styled(
+                    format!(" ({})", error.lines().last().unwrap_or(error)),
+                    Style::default().fg(Color::Red),
+                ));
+            }
+            spans
+        }
+        None => vec![],
     }
 }
 
@@ -147,11 +304,31 @@ fn draw_lower_block<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect, props:
         .split(area);
 
     // Chip List
-    chip_list(&app, f, nested_layout[0], &props);
+    chip_list(app, f, nested_layout[0], &props);
 
     // Right side details panel
-    chip_info_panel(&app, f, nested_layout[1], &props);
+    chip_info_panel(app, f, nested_layout[1], &props);
+
+    // Charts, with the CPU's load and frequency below on CPU chips
+    if app.state.is_cpu_panel_visible() && is_cpu_chip(props.chip) {
+        let chart_layout = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Percentage(60), Constraint::Percentage(40)])
+            .split(nested_layout[2]);
+        temperature_graphs(app, f, chart_layout[0], &props);
+        cpu_panel(app, f, chart_layout[1], &props);
+    } else {
+        temperature_graphs(app, f, nested_layout[2], &props);
+    }
+}
+
+fn draw_feature_block<B: Backend>(f: &mut Frame<B>, app: &App, area: Rect, props: ChipListProps) {
+    let nested_layout = Layout::default()
+        .direction(Direction::Horizontal)
+        .constraints([Constraint::Ratio(1, 3), Constraint::Ratio(2, 3)])
+        .split(area);
+
+    chip_info_panel(app, f, nested_layout[0], &props);
 
-    // Charts
-    temperature_graphs(&app, f, nested_layout[2], &props);
+    temperature_graphs(app, f, nested_layout[1], &props);
 }
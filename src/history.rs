@@ -0,0 +1,216 @@
+use std::{collections::VecDeque, time::SystemTime};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+/// How many samples are kept per sensor, the viewport can pan over all of them
+pub const HISTORY_CAPACITY: usize = 3000;
+const DEFAULT_VIEWPORT_WIDTH: usize = 100;
+const MIN_VIEWPORT_WIDTH: usize = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Sample {
+    pub time: SystemTime,
+    pub value: f64,
+}
+
+impl Sample {
+    pub fn now(value: f64) -> Self {
+        Self {
+            time: SystemTime::now(),
+            value,
+        }
+    }
+
+    pub fn format_time(&self) -> String {
+        DateTime::<Local>::from(self.time)
+            .format("%H:%M:%S%.3f")
+            .to_string()
+    }
+}
+
+/// The part of the history shown by the graphs. Sampling keeps going while the
+/// viewport is frozen, it just stops following the newest sample
+#[derive(Debug)]
+pub struct Viewport {
+    width: usize,
+    /// Samples between the newest (or frozen) sample and the right edge
+    offset: usize,
+    frozen_at: Option<SystemTime>,
+    /// Position of the value cursor, relative to the left edge
+    cursor: Option<usize>,
+}
+
+impl Default for Viewport {
+    fn default() -> Self {
+        Self {
+            width: DEFAULT_VIEWPORT_WIDTH,
+            offset: 0,
+            frozen_at: None,
+            cursor: None,
+        }
+    }
+}
+
+impl Viewport {
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn is_frozen(&self) -> bool {
+        self.frozen_at.is_some()
+    }
+
+    /// Whether the right edge follows the newest sample
+    pub fn is_live(&self) -> bool {
+        self.frozen_at.is_none() && self.offset == 0
+    }
+
+    pub fn cursor(&self) -> Option<usize> {
+        self.cursor
+    }
+
+    pub fn toggle_freeze(&mut self) {
+        self.frozen_at = match self.frozen_at {
+            Some(_) => {
+                self.offset = 0;
+                None
+            }
+            None => Some(SystemTime::now()),
+        };
+    }
+
+    pub fn zoom_in(&mut self) {
+        self.width = (self.width / 2).max(MIN_VIEWPORT_WIDTH);
+        self.clamp_cursor();
+    }
+
+    pub fn zoom_out(&mut self) {
+        self.width = (self.width * 2).min(HISTORY_CAPACITY);
+    }
+
+    /// Freezes the viewport first, an offset from the newest sample would keep
+    /// scrolling as samples come in
+    pub fn pan_left(&mut self) {
+        if self.frozen_at.is_none() {
+            self.frozen_at = Some(SystemTime::now());
+        }
+        self.offset = (self.offset + self.width / 4).min(HISTORY_CAPACITY - MIN_VIEWPORT_WIDTH);
+    }
+
+    pub fn pan_right(&mut self) {
+        self.offset = self.offset.saturating_sub(self.width / 4);
+    }
+
+    pub fn toggle_cursor(&mut self) {
+        self.cursor = match self.cursor {
+            Some(_) => None,
+            None => Some(self.width - 1),
+        };
+    }
+
+    pub fn move_cursor_left(&mut self) {
+        let cursor = self.cursor.unwrap_or(self.width - 1);
+        self.cursor = Some(cursor.saturating_sub(1));
+    }
+
+    pub fn move_cursor_right(&mut self) {
+        let cursor = self.cursor.unwrap_or(self.width - 1);
+        self.cursor = Some((cursor + 1).min(self.width - 1));
+    }
+
+    fn clamp_cursor(&mut self) {
+        self.cursor = self.cursor.map(|cursor| cursor.min(self.width - 1));
+    }
+
+    /// The samples that fall into the viewport, oldest first
+    pub fn window(&self, samples: &VecDeque<Sample>) -> Vec<Sample> {
+        let end = match self.frozen_at {
+            Some(frozen_at) => samples.iter().take_while(|s| s.time <= frozen_at).count(),
+            None => samples.len(),
+        };
+        let end = end.saturating_sub(self.offset);
+        let start = end.saturating_sub(self.width);
+        samples.range(start..end).copied().collect()
+    }
+
+    /// The sample under the cursor, the window may be shorter than the viewport
+    /// while the history is still filling up
+    pub fn sample_at_cursor(&self, window: &[Sample]) -> Option<Sample> {
+        let cursor = self.cursor?;
+        window.get(cursor.min(window.len().checked_sub(1)?)).copied()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{collections::VecDeque, time::SystemTime};
+
+    use super::{Sample, Viewport};
+
+    fn samples(n: usize) -> VecDeque<Sample> {
+        (0..n).map(|i| Sample::now(i as f64)).collect()
+    }
+
+    #[test]
+    fn test_window_follows_newest_sample() {
+        let viewport = Viewport::default();
+        let window = viewport.window(&samples(150));
+
+        assert!(window.len() == 100);
+        assert!(window.first().map(|s| s.value) == Some(50.0));
+        assert!(window.last().map(|s| s.value) == Some(149.0));
+    }
+
+    #[test]
+    fn test_zoom_and_pan() {
+        let history = samples(150);
+        let mut viewport = Viewport::default();
+        viewport.zoom_in();
+        viewport.pan_left();
+        let window = viewport.window(&history);
+
+        assert!(viewport.width() == 50);
+        assert!(!viewport.is_live());
+        assert!(window.len() == 50);
+        assert!(window.last().map(|s| s.value) == Some(137.0));
+    }
+
+    #[test]
+    fn test_panned_window_ignores_new_samples() {
+        let mut viewport = Viewport::default();
+        let mut history = samples(150);
+        viewport.pan_left();
+        let window = viewport.window(&history);
+        history.pop_front();
+        history.push_back(Sample {
+            time: SystemTime::now() + std::time::Duration::from_secs(1),
+            value: 99.0,
+        });
+
+        assert!(viewport.is_frozen());
+        assert!(viewport.window(&history) == window);
+    }
+
+    #[test]
+    fn test_frozen_window_ignores_new_samples() {
+        let mut viewport = Viewport::default();
+        let mut history = samples(10);
+        viewport.toggle_freeze();
+        history.push_back(Sample {
+            time: SystemTime::now() + std::time::Duration::from_secs(1),
+            value: 99.0,
+        });
+
+        assert!(viewport.window(&history).len() == 10);
+    }
+
+    #[test]
+    fn test_cursor_is_clamped_to_window() {
+        let mut viewport = Viewport::default();
+        viewport.toggle_cursor();
+        let window = viewport.window(&samples(5));
+
+        assert!(viewport.sample_at_cursor(&window).map(|s| s.value) == Some(4.0));
+    }
+}
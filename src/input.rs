@@ -1,31 +1,236 @@
 use std::{error::Error, io::{self, ErrorKind}, cell::RefCell};
 
-use crossterm::event::{KeyCode, Event};
+use crossterm::event::{KeyCode, Event, KeyEvent, KeyModifiers};
 
-use crate::app::App;
+use crate::app::{App, InputMode, Screen};
 
 pub fn handle_input(event: &Event, app: &RefCell<App>) -> Result<(), Box<dyn Error>> {
     match event {
+        // Raw mode turns Ctrl-C into a key event instead of SIGINT
+        Event::Key(key_event) if key_event.code == KeyCode::Char('c') && key_event.modifiers.contains(KeyModifiers::CONTROL) => {
+            Err(Box::new(io::Error::from(ErrorKind::Interrupted)))
+        },
+        // No chords are bound, Ctrl-x must not act like x
+        Event::Key(key_event) if key_event.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => Ok(()),
         Event::Key(key_event) => {
-            match key_event.code {
-                KeyCode::Esc | KeyCode::Char('q') => {
-                    Err(Box::new(io::Error::from(ErrorKind::Interrupted)))
-                },
-                KeyCode::Down | KeyCode::Char('j') => {
-                    app.borrow_mut().state.select_next_chip();
-                    Ok(())
-                },
-                KeyCode::Up | KeyCode::Char('k') => {
-                    app.borrow_mut().state.select_previous_chip();
+            let (input_mode, screen) = {
+                let state = &app.borrow().state;
+                (state.get_input_mode(), state.get_screen())
+            };
+            match (input_mode, screen) {
+                (InputMode::Normal, Screen::Main) => handle_normal_input(key_event, app),
+                (InputMode::Normal, Screen::Overview) => handle_overview_input(key_event, app),
+                (InputMode::Search, _) => {
+                    handle_search_input(key_event, app);
                     Ok(())
-                },
-                KeyCode::Enter | KeyCode::Char('p') => {
-                    app.borrow_mut().state.set_pinned_chip();
-                    Ok(())
-                },
-                _ => Ok(())
+                }
             }
         },
         _ => Ok(())
     }
 }
+
+fn handle_normal_input(key_event: &KeyEvent, app: &RefCell<App>) -> Result<(), Box<dyn Error>> {
+    match key_event.code {
+        KeyCode::Esc if app.borrow().state.get_search().is_some() => {
+            app.borrow_mut().state.cancel_search();
+            Ok(())
+        },
+        KeyCode::Esc | KeyCode::Char('q') => {
+            Err(Box::new(io::Error::from(ErrorKind::Interrupted)))
+        },
+        KeyCode::Tab => {
+            app.borrow_mut().state.toggle_screen();
+            Ok(())
+        },
+        KeyCode::Down | KeyCode::Char('j') => {
+            app.borrow_mut().state.select_next_chip();
+            Ok(())
+        },
+        KeyCode::Up | KeyCode::Char('k') => {
+            app.borrow_mut().state.select_previous_chip();
+            Ok(())
+        },
+        KeyCode::Char('}') => {
+            app.borrow_mut().state.select_next_host();
+            Ok(())
+        },
+        KeyCode::Char('{') => {
+            app.borrow_mut().state.select_previous_host();
+            Ok(())
+        },
+        KeyCode::Char(']') => {
+            app.borrow_mut().state.select_next_feature();
+            Ok(())
+        },
+        KeyCode::Char('[') => {
+            app.borrow_mut().state.select_previous_feature();
+            Ok(())
+        },
+        KeyCode::Enter | KeyCode::Char('p') => {
+            app.borrow_mut().state.toggle_pinned_chip();
+            Ok(())
+        },
+        KeyCode::Char('P') => {
+            app.borrow_mut().state.toggle_pinned_feature();
+            Ok(())
+        },
+        KeyCode::Char('.') => {
+            app.borrow_mut().state.focus_next_pin();
+            Ok(())
+        },
+        KeyCode::Char(',') => {
+            app.borrow_mut().state.focus_previous_pin();
+            Ok(())
+        },
+        KeyCode::Char('>') => {
+            app.borrow_mut().state.move_focused_pin_down();
+            Ok(())
+        },
+        KeyCode::Char('<') => {
+            app.borrow_mut().state.move_focused_pin_up();
+            Ok(())
+        },
+        KeyCode::Char('x') => {
+            app.borrow_mut().state.remove_focused_pin();
+            Ok(())
+        },
+        KeyCode::Char(' ') => {
+            app.borrow_mut().state.get_viewport_mut().toggle_freeze();
+            Ok(())
+        },
+        KeyCode::Char('+') | KeyCode::Char('=') => {
+            app.borrow_mut().state.get_viewport_mut().zoom_in();
+            Ok(())
+        },
+        KeyCode::Char('-') => {
+            app.borrow_mut().state.get_viewport_mut().zoom_out();
+            Ok(())
+        },
+        KeyCode::Char('H') => {
+            app.borrow_mut().state.get_viewport_mut().pan_left();
+            Ok(())
+        },
+        KeyCode::Char('L') => {
+            app.borrow_mut().state.get_viewport_mut().pan_right();
+            Ok(())
+        },
+        KeyCode::Char('v') => {
+            app.borrow_mut().state.get_viewport_mut().toggle_cursor();
+            Ok(())
+        },
+        KeyCode::Left | KeyCode::Char('h') => {
+            app.borrow_mut().state.get_viewport_mut().move_cursor_left();
+            Ok(())
+        },
+        KeyCode::Right | KeyCode::Char('l') => {
+            app.borrow_mut().state.get_viewport_mut().move_cursor_right();
+            Ok(())
+        },
+        KeyCode::Char('c') => {
+            app.borrow_mut().state.toggle_combined_main_chart();
+            Ok(())
+        },
+        KeyCode::Char('C') => {
+            app.borrow_mut().state.toggle_combined_pin_chart();
+            Ok(())
+        },
+        KeyCode::Char('a') => {
+            app.borrow_mut().state.toggle_auto_scaled_main_chart();
+            Ok(())
+        },
+        KeyCode::Char('A') => {
+            app.borrow_mut().state.toggle_auto_scaled_pin_chart();
+            Ok(())
+        },
+        KeyCode::Char('m') => {
+            app.borrow_mut().state.toggle_marked_feature();
+            Ok(())
+        },
+        KeyCode::Char('O') => {
+            app.borrow_mut().state.pin_marked_features();
+            Ok(())
+        },
+        KeyCode::Char('/') => {
+            app.borrow_mut().state.start_search();
+            Ok(())
+        },
+        KeyCode::Char('r') => {
+            app.borrow_mut().state.rescan_chips();
+            Ok(())
+        },
+        KeyCode::Char('~') => {
+            app.borrow_mut().state.toggle_log();
+            Ok(())
+        },
+        KeyCode::Char('u') => {
+            app.borrow_mut().state.toggle_cpu_panel();
+            Ok(())
+        },
+        KeyCode::Char('n') => {
+            app.borrow_mut().state.select_next_match();
+            Ok(())
+        },
+        KeyCode::Char('N') => {
+            app.borrow_mut().state.select_previous_match();
+            Ok(())
+        },
+        _ => Ok(())
+    }
+}
+
+fn handle_overview_input(key_event: &KeyEvent, app: &RefCell<App>) -> Result<(), Box<dyn Error>> {
+    match key_event.code {
+        KeyCode::Esc if app.borrow().state.get_search().is_some() => {
+            app.borrow_mut().state.cancel_search();
+            Ok(())
+        },
+        KeyCode::Esc | KeyCode::Char('q') => {
+            Err(Box::new(io::Error::from(ErrorKind::Interrupted)))
+        },
+        KeyCode::Tab => {
+            app.borrow_mut().state.toggle_screen();
+            Ok(())
+        },
+        KeyCode::Down | KeyCode::Char('j') => {
+            app.borrow_mut().state.select_next_overview_row();
+            Ok(())
+        },
+        KeyCode::Up | KeyCode::Char('k') => {
+            app.borrow_mut().state.select_previous_overview_row();
+            Ok(())
+        },
+        KeyCode::Char('s') => {
+            app.borrow_mut().state.cycle_overview_sort();
+            Ok(())
+        },
+        KeyCode::Enter => {
+            app.borrow_mut().state.open_overview_row();
+            Ok(())
+        },
+        KeyCode::Char('/') => {
+            app.borrow_mut().state.start_search();
+            Ok(())
+        },
+        KeyCode::Char('r') => {
+            app.borrow_mut().state.rescan_chips();
+            Ok(())
+        },
+        KeyCode::Char('~') => {
+            app.borrow_mut().state.toggle_log();
+            Ok(())
+        },
+        _ => Ok(())
+    }
+}
+
+fn handle_search_input(key_event: &KeyEvent, app: &RefCell<App>) {
+    let state = &mut app.borrow_mut().state;
+    match key_event.code {
+        KeyCode::Esc => state.cancel_search(),
+        KeyCode::Enter => state.confirm_search(),
+        KeyCode::Backspace => state.pop_search_char(),
+        KeyCode::Char(c) => state.push_search_char(c),
+        _ => {}
+    }
+}
@@ -1,61 +1,128 @@
 use std::{
-    fmt::Display,
-    fs::OpenOptions,
-    io::Write,
-    sync::{mpsc, Mutex},
-    thread,
+    collections::VecDeque,
+    error::Error,
+    fmt,
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Mutex,
 };
 
+use chrono::Local;
 use lazy_static::lazy_static;
+use log::{Level, LevelFilter, Log, Metadata, Record};
 
-struct Logger<T> {
-    rx: Mutex<mpsc::Receiver<T>>,
-    #[allow(unused)]
-    tx: Mutex<mpsc::Sender<T>>,
+use crate::state_file::state_dir;
+
+/// How many records the log pane keeps
+const RECENT_CAPACITY: usize = 500;
+/// Log files above this size are moved aside on startup
+const MAX_LOG_FILE_SIZE: u64 = 1024 * 1024;
+
+#[derive(Debug, Clone)]
+pub struct LogEntry {
+    pub time: String,
+    pub level: Level,
+    pub target: String,
+    pub message: String,
 }
 
-impl<T: Display> Logger<T> {
-    fn start(&self) {
-        let out = OpenOptions::new()
-            .write(true)
-            .append(true)
-            .open("msg.log");
-        if let Ok(mut out) = out {
-            out.set_len(0).unwrap();
+impl fmt::Display for LogEntry {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} {:<5} {}: {}", self.time, self.level, self.target, self.message)
+    }
+}
 
-            while let Ok(msg) = self.rx.lock().unwrap().recv() {
-                writeln!(&mut out, "{}", msg).unwrap();
+/// Writes records to the log file and keeps the most recent ones for the log pane
+struct Logger {
+    file: Mutex<Option<File>>,
+    recent: Mutex<VecDeque<LogEntry>>,
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= log::max_level()
+    }
+
+    fn log(&self, record: &Record) {
+        if !self.enabled(record.metadata()) {
+            return;
+        }
+        let entry = LogEntry {
+            time: Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string(),
+            level: record.level(),
+            target: String::from(record.target()),
+            message: record.args().to_string(),
+        };
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(file) = file.as_mut() {
+                let _ = writeln!(file, "{}", entry);
+            }
+        }
+        if let Ok(mut recent) = self.recent.lock() {
+            if recent.len() == RECENT_CAPACITY {
+                recent.pop_front();
             }
+            recent.push_back(entry);
         }
     }
-}
 
-impl<T> Default for Logger<T> {
-    fn default() -> Self {
-        let (tx, rx) = mpsc::channel();
-        Self {
-            tx: Mutex::new(tx),
-            rx: Mutex::new(rx),
+    fn flush(&self) {
+        if let Ok(mut file) = self.file.lock() {
+            if let Some(file) = file.as_mut() {
+                let _ = file.flush();
+            }
         }
     }
 }
 
 lazy_static! {
-    static ref LOGGER: Logger<String> = Logger::default();
+    static ref LOGGER: Logger = Logger {
+        file: Mutex::new(None),
+        recent: Mutex::new(VecDeque::with_capacity(RECENT_CAPACITY)),
+    };
 }
 
-#[allow(unused)]
-pub fn log_message(message: &str) {
-    LOGGER
-        .tx
-        .lock()
-        .unwrap()
-        .send(String::from(message))
-        .unwrap();
+/// `senso.log` in the state dir
+pub fn default_log_file() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("senso.log"))
 }
 
-pub fn start_logger() {
-    thread::spawn(|| {
-        LOGGER.start();
-    });
+fn open_log_file(path: &Path) -> io::Result<File> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    if fs::metadata(path).is_ok_and(|metadata| metadata.len() > MAX_LOG_FILE_SIZE) {
+        let mut old_path = path.as_os_str().to_owned();
+        old_path.push(".old");
+        fs::rename(path, old_path)?;
+    }
+    OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Installs the logger. If the log file can't be opened the error is returned,
+/// but records still reach the log pane
+pub fn init_logger(path: Option<&Path>, level: LevelFilter) -> Result<(), Box<dyn Error>> {
+    log::set_logger(&*LOGGER)?;
+    log::set_max_level(level);
+
+    let path = path
+        .map(PathBuf::from)
+        .or_else(default_log_file)
+        .ok_or("could not determine log file path")?;
+    let file = open_log_file(&path).map_err(|e| format!("{}: {}", path.display(), e))?;
+    if let Ok(mut log_file) = LOGGER.file.lock() {
+        *log_file = Some(file);
+    }
+
+    Ok(())
+}
+
+/// The most recent records, oldest first
+pub fn recent_entries() -> Vec<LogEntry> {
+    LOGGER
+        .recent
+        .lock()
+        .map(|recent| recent.iter().cloned().collect())
+        .unwrap_or_default()
 }
@@ -1,43 +1,351 @@
-use std::{error::Error, io::stdout, panic, time::Duration};
-
-use clap::{arg, command, Parser};
-use crossterm::{
-    event::DisableMouseCapture,
-    execute,
-    terminal::{disable_raw_mode, LeaveAlternateScreen},
-};
+use std::{env, error::Error, fs, path::PathBuf, process, time::Duration};
+
+use bar::{run_bar, BarFormat};
+use clap::{arg, command, Parser, Subcommand};
+use compare::{run_compare_gui, Comparison, WatchRecording};
+use config::{default_config_path, Config};
+use daemon::{default_socket_path, run_daemon, Endpoint, DEFAULT_PORT};
+use fleet::FleetSource;
 use gui::run_gui;
-use logger::{start_logger, log_message};
+use log::{debug, error, warn, LevelFilter};
+use logger::init_logger;
+use remote::{Address, RemoteSource};
+use selector::{run_list, Selector};
+use session::{run_session, ReportFormat, SessionOptions};
+use source::{parse_duration, LocalSource, SensorSource};
+use template::{run_template, Scope, Template, DEFAULT_TEMPLATE};
+use terminal::install_panic_hook;
+use watch::{run_watch, WatchFormat, Watcher};
 
 mod app;
+mod bar;
+mod collector;
+mod compare;
 mod components;
+mod config;
+mod cpu;
+mod daemon;
+mod export;
+mod fleet;
 mod gui;
+mod history;
 mod input;
 mod logger;
+mod mqtt;
+mod pins;
+mod powercap;
+mod protocol;
+mod remote;
 mod ring_buffer;
+mod search;
+mod selector;
 mod sensors;
+mod session;
+mod snapshot;
+mod source;
+mod state_file;
+mod stats;
+mod template;
 mod terminal;
-mod utils;
+mod watch;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
     #[arg(short, long, default_value_t = 100)]
     tick_rate: u16,
+    /// Defaults to senso.log in $XDG_STATE_HOME/senso
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+    /// One of off, error, warn, info, debug or trace
+    #[arg(long, default_value_t = LevelFilter::Info)]
+    log_level: LevelFilter,
+    /// Show the readings of a running daemon, on its default socket unless given
+    #[arg(long, value_name = "SOCKET")]
+    attach: Option<Option<PathBuf>>,
+    /// Defaults to config.json in $XDG_CONFIG_HOME/senso
+    #[arg(long)]
+    config: Option<PathBuf>,
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
-    start_logger();
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Keep sampling in the background and serve the readings on a Unix socket
+    Daemon {
+        /// Defaults to senso.sock in $XDG_RUNTIME_DIR
+        #[arg(long)]
+        socket: Option<PathBuf>,
+    },
+    /// Like daemon, but serve the readings over TCP for `senso connect`
+    Serve {
+        /// Address and port to listen on. Other addresses than loopback need a
+        /// token, e.g. 0.0.0.0:7878 with --token
+        #[arg(long, default_value_t = format!("127.0.0.1:{}", DEFAULT_PORT))]
+        listen: String,
+        /// Clients have to send this token, defaults to $SENSO_TOKEN
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Show the readings of `senso serve` running on another host
+    Connect {
+        /// host or host:port
+        address: String,
+        /// The token the server was started with, defaults to $SENSO_TOKEN
+        #[arg(long)]
+        token: Option<String>,
+    },
+    /// Show every host listed in the config file, each running `senso serve`
+    Fleet,
+    /// Keep printing sensor values for a status bar
+    Bar {
+        #[arg(long, value_enum, default_value_t = BarFormat::Waybar)]
+        format: BarFormat,
+        /// Sensor labels or selectors in braces, e.g. "{Package id 0}°C
+        /// {fan1}rpm" or "{nvme-*/Composite}". {hottest} is the hottest CPU
+        /// temperature. Takes everything print --scope system does, like
+        /// {value("kind=fan"):.1} or {if high}...{end}
+        #[arg(long, default_value = "{hottest}°C")]
+        template: String,
+        /// Only look at the sensors picked by a selector
+        #[arg(long = "match", value_parser = Selector::parse)]
+        sensors: Option<Selector>,
+        /// Time between updates, e.g. 500ms, 2s or 1m
+        #[arg(long, default_value = "2s", value_parser = parse_duration)]
+        interval: Duration,
+    },
+    /// Print sensor values filled into a template, once or every interval
+    Print {
+        /// e.g. "{chip}:{label}={value:.1}{unit}". Also takes sub-features like
+        /// {sub.crit_hyst}, aggregates like {avg(temperature, "Core *")} and
+        /// conditionals like {if value >= crit}...{else}...{end}
+        #[arg(long, conflicts_with = "template_file")]
+        template: Option<String>,
+        /// Read the template from a file
+        #[arg(long)]
+        template_file: Option<PathBuf>,
+        /// Fill the template in for every feature, every chip or once
+        #[arg(long, value_enum, default_value_t = Scope::Feature)]
+        scope: Scope,
+        /// Only fill in the sensors picked by a selector
+        #[arg(long = "match", value_parser = Selector::parse)]
+        sensors: Option<Selector>,
+        /// Keep printing every interval, e.g. 500ms, 2s or 1m
+        #[arg(long, value_parser = parse_duration)]
+        interval: Option<Duration>,
+    },
+    /// List the sensors, to try out selectors
+    List {
+        /// e.g. "coretemp-*/Core *", "nvme-pci-*/Composite", "kind=fan" or
+        /// "chip.bus=pci". Terms separated by spaces all have to match, commas
+        /// separate alternatives
+        #[arg(long = "match", value_parser = Selector::parse)]
+        sensors: Option<Selector>,
+    },
+    /// Record the sensors while a command runs, e.g. a stress test, then
+    /// report how they coped
+    Run {
+        #[arg(long, value_enum, default_value_t = ReportFormat::Markdown)]
+        format: ReportFormat,
+        /// Write the report to a file instead of stdout
+        #[arg(long)]
+        output: Option<PathBuf>,
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        interval: Duration,
+        /// Idle sampling before the command starts
+        #[arg(long, default_value = "5s", value_parser = parse_duration)]
+        baseline: Duration,
+        /// The longest wait for temperatures to settle after the command exits
+        #[arg(long, default_value = "60s", value_parser = parse_duration)]
+        cool_down: Duration,
+        /// Only record the sensors picked by a selector
+        #[arg(long = "match", value_parser = Selector::parse)]
+        sensors: Option<Selector>,
+        /// The command after --, e.g. `senso run -- stress-ng --cpu 8 --timeout 60`
+        #[arg(last = true, required = true)]
+        command: Vec<String>,
+    },
+    /// Compare two recordings of `senso watch --format jsonl`, lined up by
+    /// the time since each started
+    Compare {
+        a: PathBuf,
+        b: PathBuf,
+        /// Print a table of the differences instead of showing the graphs
+        #[arg(long)]
+        text: bool,
+    },
+    /// Keep printing readings to stdout, one record per sensor and interval
+    Watch {
+        /// e.g. 500ms, 1s or 2m
+        #[arg(long, default_value = "1s", value_parser = parse_duration)]
+        interval: Duration,
+        #[arg(long, value_enum, default_value_t = WatchFormat::Jsonl)]
+        format: WatchFormat,
+        /// Stop after this many intervals
+        #[arg(long, value_parser = clap::value_parser!(u64).range(1..))]
+        count: Option<u64>,
+        /// Only print readings whose value changed since the last interval
+        #[arg(long)]
+        changes_only: bool,
+        /// Only print the sensors picked by a selector, e.g. "coretemp-*/Core *"
+        /// or "kind=fan"
+        #[arg(long = "match", value_parser = Selector::parse)]
+        sensors: Option<Selector>,
+    },
+}
 
-    let args = Args::parse();
-    log_message(&format!("tick_rate = {}", args.tick_rate));
+/// Tokens on the command line show up in `ps`, the environment is safer
+fn token_or_env(token: Option<String>) -> Option<String> {
+    token.or_else(|| {
+        env::var("SENSO_TOKEN")
+            .ok()
+            .filter(|token| !token.is_empty())
+    })
+}
 
-    let _ = panic::catch_unwind(|| {
-        run_gui(Duration::from_millis(args.tick_rate as u64)).unwrap();
-    });
+fn socket_path(path: Option<PathBuf>) -> Result<PathBuf, Box<dyn Error>> {
+    path.or_else(default_socket_path)
+        .ok_or_else(|| "no socket path given and no runtime or state dir to put one in".into())
+}
+
+fn run(args: Args) -> Result<(), Box<dyn Error>> {
+    let tick_rate = Duration::from_millis(args.tick_rate as u64);
+    let source: Box<dyn SensorSource> = match (args.command, args.attach) {
+        (Some(Command::Daemon { socket }), _) => {
+            let endpoint = Endpoint::Unix(socket_path(socket)?);
+            let config = Config::load(args.config.as_deref())?;
+            return run_daemon(&endpoint, tick_rate, Box::new(LocalSource::new()?), config);
+        }
+        (Some(Command::Serve { listen, token }), _) => {
+            let endpoint = Endpoint::Tcp {
+                address: listen,
+                token: token_or_env(token),
+            };
+            let config = Config::load(args.config.as_deref())?;
+            return run_daemon(&endpoint, tick_rate, Box::new(LocalSource::new()?), config);
+        }
+        (Some(Command::Connect { address, token }), _) => Box::new(RemoteSource::attach(
+            Address::tcp(&address),
+            token_or_env(token),
+        )?),
+        (
+            Some(Command::Bar {
+                format,
+                template,
+                sensors,
+                interval,
+            }),
+            _,
+        ) => {
+            let template = Template::parse(&template, Scope::System)?;
+            return run_bar(
+                format,
+                &template,
+                sensors.as_ref(),
+                interval,
+                Box::new(LocalSource::new()?),
+            );
+        }
+        (
+            Some(Command::Print {
+                template,
+                template_file,
+                scope,
+                sensors,
+                interval,
+            }),
+            _,
+        ) => {
+            let template = match (template, template_file) {
+                (Some(template), _) => template,
+                (None, Some(path)) => fs::read_to_string(&path)
+                    .map_err(|e| format!("can't read {}: {}", path.display(), e))?,
+                (None, None) => String::from(DEFAULT_TEMPLATE),
+            };
+            let template = Template::parse(&template, scope)?;
+            let source = Box::new(LocalSource::new()?);
+            return run_template(&template, sensors.as_ref(), interval, source);
+        }
+        (
+            Some(Command::Watch {
+                interval,
+                format,
+                count,
+                changes_only,
+                sensors,
+            }),
+            _,
+        ) => {
+            let watcher = Watcher::new(sensors, changes_only);
+            let source = Box::new(LocalSource::new()?);
+            return run_watch(format, interval, count, watcher, source);
+        }
+        (Some(Command::List { sensors }), _) => {
+            return run_list(sensors.as_ref(), Box::new(LocalSource::new()?));
+        }
+        (
+            Some(Command::Run {
+                format,
+                output,
+                interval,
+                baseline,
+                cool_down,
+                sensors,
+                command,
+            }),
+            _,
+        ) => {
+            let options = SessionOptions {
+                interval,
+                baseline,
+                cool_down,
+                format,
+                output,
+                sensors,
+            };
+            return run_session(&command, options, Box::new(LocalSource::new()?));
+        }
+        (Some(Command::Compare { a, b, text }), _) => {
+            let comparison =
+                Comparison::new(WatchRecording::load(&a)?, WatchRecording::load(&b)?);
+            if text {
+                print!("{}", comparison.to_text());
+                return Ok(());
+            }
+            install_panic_hook();
+            return run_compare_gui(&comparison);
+        }
+        (Some(Command::Fleet), _) => {
+            let config = Config::load(args.config.as_deref())?;
+            if config.hosts.is_empty() {
+                let path = args.config.or_else(default_config_path).unwrap_or_default();
+                return Err(format!("no hosts configured, add them to {}", path.display()).into());
+            }
+            Box::new(FleetSource::new(&config.hosts))
+        }
+        (None, Some(socket)) => {
+            let address = Address::Unix(socket_path(socket)?);
+            Box::new(RemoteSource::attach(address, None)?)
+        }
+        (None, None) => Box::new(LocalSource::new()?),
+    };
+    install_panic_hook();
+    run_gui(tick_rate, source)
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    let args = Args::parse();
+    if let Err(e) = init_logger(args.log_file.as_deref(), args.log_level) {
+        warn!("not logging to a file: {}", e);
+    }
+    debug!("tick_rate = {}", args.tick_rate);
 
-    disable_raw_mode()?;
-    execute!(stdout(), LeaveAlternateScreen, DisableMouseCapture).unwrap();
+    if let Err(e) = run(args) {
+        error!("{}", e);
+        eprintln!("senso: {}", e);
+        process::exit(1);
+    }
 
     Ok(())
 }
@@ -0,0 +1,571 @@
+use std::{
+    collections::HashSet,
+    io::{self, ErrorKind, Read, Write},
+    net::{TcpStream, ToSocketAddrs},
+    time::{Duration, Instant},
+};
+
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::{
+    export::{hostname, Batch},
+    remote,
+    selector::Selector,
+    sensors::{Reading, SensorId, SensorKind},
+};
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(60);
+
+fn default_topic_prefix() -> String {
+    String::from("senso")
+}
+
+fn default_discovery_prefix() -> String {
+    String::from("homeassistant")
+}
+
+fn default_interval() -> u64 {
+    10
+}
+
+/// Publishes readings to an MQTT broker, run by `senso daemon` and `senso serve`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MqttConfig {
+    /// `host` or `host:port`, the port defaults to 1883
+    pub broker: String,
+    /// Defaults to `senso-<host>`
+    #[serde(default)]
+    pub client_id: Option<String>,
+    #[serde(default)]
+    pub username: Option<String>,
+    #[serde(default)]
+    pub password: Option<String>,
+    /// 0 or 1
+    #[serde(default)]
+    pub qos: u8,
+    #[serde(default)]
+    pub retain: bool,
+    /// Seconds between publishes
+    #[serde(default = "default_interval")]
+    pub interval: u64,
+    /// The `<host>` in `senso/<host>/<chip>/<feature>`, defaults to the hostname
+    #[serde(default)]
+    pub host: Option<String>,
+    #[serde(default = "default_topic_prefix")]
+    pub topic_prefix: String,
+    /// Announce the sensors to Home Assistant
+    #[serde(default)]
+    pub discovery: bool,
+    #[serde(default = "default_discovery_prefix")]
+    pub discovery_prefix: String,
+    /// A selector like `kind=temperature`, all sensors are published without one
+    #[serde(default)]
+    pub sensors: Option<Selector>,
+}
+
+impl MqttConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.qos > 1 {
+            return Err(format!(
+                "mqtt: QoS {} is not supported, use 0 or 1",
+                self.qos
+            ));
+        }
+        if self.interval == 0 {
+            return Err(String::from("mqtt: interval has to be at least 1 second"));
+        }
+        // MQTT 3.1.1 only allows a password together with a user name
+        if self.password.is_some() && self.username.is_none() {
+            return Err(String::from("mqtt: password is set without a username"));
+        }
+        Ok(())
+    }
+}
+
+/// Topic levels can't contain `/`, and `+` and `#` are wildcards
+fn topic_segment(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| match c {
+            '/' | '+' | '#' => '_',
+            c if c.is_whitespace() => '_',
+            c => c,
+        })
+        .collect()
+}
+
+/// Home Assistant object ids only allow `[a-zA-Z0-9_-]`
+fn object_id(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+fn device_class(kind: SensorKind) -> Option<&'static str> {
+    match kind {
+        SensorKind::Temperature => Some("temperature"),
+        SensorKind::Voltage => Some("voltage"),
+        SensorKind::Power => Some("power"),
+        SensorKind::Current => Some("current"),
+        SensorKind::Humidity => Some("humidity"),
+        SensorKind::Fan => None,
+    }
+}
+
+fn unit_of_measurement(kind: SensorKind) -> &'static str {
+    match kind {
+        SensorKind::Temperature => "°C",
+        _ => kind.unit(),
+    }
+}
+
+// Packet types, shifted into the upper nibble of the first byte
+const CONNECT: u8 = 0x10;
+const CONNACK: u8 = 0x20;
+const PUBLISH: u8 = 0x30;
+const PUBACK: u8 = 0x40;
+const PINGREQ: u8 = 0xc0;
+const PINGRESP: u8 = 0xd0;
+const DISCONNECT: u8 = 0xe0;
+
+fn protocol_error(message: String) -> io::Error {
+    io::Error::new(ErrorKind::InvalidData, message)
+}
+
+fn put_string(buf: &mut Vec<u8>, value: &[u8]) {
+    buf.extend_from_slice(&(value.len() as u16).to_be_bytes());
+    buf.extend_from_slice(value);
+}
+
+/// Fixed header plus `body`, the length is a base-128 varint
+fn packet(header: u8, body: &[u8]) -> Vec<u8> {
+    let mut buf = vec![header];
+    let mut len = body.len();
+    loop {
+        let mut byte = (len % 128) as u8;
+        len /= 128;
+        if len > 0 {
+            byte |= 0x80;
+        }
+        buf.push(byte);
+        if len == 0 {
+            break;
+        }
+    }
+    buf.extend_from_slice(body);
+    buf
+}
+
+/// Reads a whole packet, returns its first byte and body
+fn read_packet<R: Read>(reader: &mut R) -> io::Result<(u8, Vec<u8>)> {
+    let mut byte = [0];
+    reader.read_exact(&mut byte)?;
+    let header = byte[0];
+    let mut len = 0;
+    for shift in (0..28).step_by(7) {
+        reader.read_exact(&mut byte)?;
+        len |= ((byte[0] & 0x7f) as usize) << shift;
+        if byte[0] & 0x80 == 0 {
+            let mut body = vec![0; len];
+            reader.read_exact(&mut body)?;
+            return Ok((header, body));
+        }
+    }
+    Err(protocol_error(String::from("malformed packet length")))
+}
+
+/// An MQTT 3.1.1 session, only what publishing needs
+struct Connection {
+    stream: TcpStream,
+    next_packet_id: u16,
+}
+
+impl Connection {
+    fn open(config: &MqttConfig, client_id: &str) -> io::Result<Self> {
+        let address = remote::with_default_port(&config.broker, 1883)
+            .to_socket_addrs()?
+            .next()
+            .ok_or_else(|| io::Error::new(ErrorKind::NotFound, "broker didn't resolve"))?;
+        let stream = TcpStream::connect_timeout(&address, TIMEOUT)?;
+        stream.set_read_timeout(Some(TIMEOUT))?;
+        stream.set_write_timeout(Some(TIMEOUT))?;
+        let mut connection = Self {
+            stream,
+            next_packet_id: 1,
+        };
+
+        // Something is published every interval, which keeps the session alive
+        let keep_alive = (config.interval * 3).clamp(10, u16::MAX as u64) as u16;
+        let mut flags = 0x02; // clean session
+        if config.username.is_some() {
+            flags |= 0x80;
+        }
+        if config.password.is_some() {
+            flags |= 0x40;
+        }
+        let mut body = vec![];
+        put_string(&mut body, b"MQTT");
+        body.push(4); // protocol level 3.1.1
+        body.push(flags);
+        body.extend_from_slice(&keep_alive.to_be_bytes());
+        put_string(&mut body, client_id.as_bytes());
+        for value in [&config.username, &config.password].into_iter().flatten() {
+            put_string(&mut body, value.as_bytes());
+        }
+        connection.stream.write_all(&packet(CONNECT, &body))?;
+
+        match read_packet(&mut connection.stream)? {
+            (CONNACK, body) if body.len() == 2 && body[1] == 0 => Ok(connection),
+            (CONNACK, body) => Err(io::Error::new(
+                ErrorKind::PermissionDenied,
+                format!(
+                    "broker refused the connection, code {}",
+                    body.get(1).unwrap_or(&0)
+                ),
+            )),
+            (header, _) => Err(protocol_error(format!(
+                "expected CONNACK, got {:#x}",
+                header
+            ))),
+        }
+    }
+
+    fn publish(&mut self, topic: &str, payload: &[u8], qos: u8, retain: bool) -> io::Result<()> {
+        let mut body = vec![];
+        put_string(&mut body, topic.as_bytes());
+        let packet_id = self.next_packet_id;
+        if qos > 0 {
+            body.extend_from_slice(&packet_id.to_be_bytes());
+            // Packet ids must not be 0
+            self.next_packet_id = self.next_packet_id.checked_add(1).unwrap_or(1);
+        }
+        body.extend_from_slice(payload);
+        let header = PUBLISH | (qos << 1) | retain as u8;
+        self.stream.write_all(&packet(header, &body))?;
+
+        if qos == 0 {
+            return Ok(());
+        }
+        match read_packet(&mut self.stream)? {
+            (PUBACK, body) if body == packet_id.to_be_bytes() => Ok(()),
+            (header, _) => Err(protocol_error(format!(
+                "expected PUBACK, got {:#x}",
+                header
+            ))),
+        }
+    }
+
+    fn ping(&mut self) -> io::Result<()> {
+        self.stream.write_all(&packet(PINGREQ, &[]))?;
+        match read_packet(&mut self.stream)? {
+            (PINGRESP, _) => Ok(()),
+            (header, _) => Err(protocol_error(format!(
+                "expected PINGRESP, got {:#x}",
+                header
+            ))),
+        }
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        let _ = self.stream.write_all(&packet(DISCONNECT, &[]));
+    }
+}
+
+/// Keeps a connection to the broker, reconnecting with backoff when it drops.
+/// Readings taken while disconnected are skipped, they are only useful live
+pub struct MqttPublisher {
+    config: MqttConfig,
+    host: String,
+    client_id: String,
+    connection: Option<Connection>,
+    next_attempt: Instant,
+    reconnect_delay: Duration,
+    last_error: Option<String>,
+    /// Sensors announced to Home Assistant on the current connection
+    announced: HashSet<SensorId>,
+}
+
+impl MqttPublisher {
+    pub fn new(config: MqttConfig) -> Self {
+        let host = config.host.clone().unwrap_or_else(hostname);
+        let client_id = config
+            .client_id
+            .clone()
+            .unwrap_or_else(|| format!("senso-{}", host));
+        Self {
+            config,
+            host,
+            client_id,
+            connection: None,
+            next_attempt: Instant::now(),
+            reconnect_delay: MIN_RECONNECT_DELAY,
+            last_error: None,
+            announced: HashSet::new(),
+        }
+    }
+
+    pub fn interval(&self) -> Duration {
+        Duration::from_secs(self.config.interval)
+    }
+
+    /// `senso/<host>/<chip>/<feature>`
+    pub fn state_topic(&self, sensor_id: &SensorId) -> String {
+        format!(
+            "{}/{}/{}/{}",
+            self.config.topic_prefix,
+            topic_segment(&self.host),
+            topic_segment(&sensor_id.chip),
+            topic_segment(&sensor_id.label)
+        )
+    }
+
+    fn discovery_message(&self, reading: &Reading) -> (String, String) {
+        let unique_id = object_id(&format!(
+            "senso_{}_{}_{}",
+            self.host, reading.id.chip, reading.id.label
+        ));
+        let mut config = json!({
+            "name": reading.id.label,
+            "unique_id": unique_id,
+            "state_topic": self.state_topic(&reading.id),
+            "unit_of_measurement": unit_of_measurement(reading.kind),
+            "state_class": "measurement",
+            // Shown as unavailable once senso stops publishing
+            "expire_after": self.config.interval * 3,
+            "device": {
+                "identifiers": [object_id(&format!("senso_{}", self.host))],
+                "name": self.host,
+                "manufacturer": "senso",
+            },
+        });
+        if let Some(device_class) = device_class(reading.kind) {
+            config["device_class"] = json!(device_class);
+        }
+        let topic = format!(
+            "{}/sensor/{}/config",
+            self.config.discovery_prefix, unique_id
+        );
+        (topic, config.to_string())
+    }
+
+    fn connection(&mut self) -> Option<&mut Connection> {
+        if self.connection.is_none() && Instant::now() >= self.next_attempt {
+            match Connection::open(&self.config, &self.client_id) {
+                Ok(connection) => {
+                    info!("mqtt: connected to {}", self.config.broker);
+                    self.connection = Some(connection);
+                    self.reconnect_delay = MIN_RECONNECT_DELAY;
+                    self.last_error = None;
+                    self.announced.clear();
+                }
+                Err(e) => self.disconnected(e),
+            }
+        }
+        self.connection.as_mut()
+    }
+
+    fn disconnected(&mut self, e: io::Error) {
+        let reason = e.to_string();
+        // The same error comes back on every retry
+        if self.last_error.as_ref() != Some(&reason) {
+            warn!(
+                "mqtt: {}: {}, retrying in {}s",
+                self.config.broker,
+                reason,
+                self.reconnect_delay.as_secs()
+            );
+        }
+        self.last_error = Some(reason);
+        self.connection = None;
+        self.next_attempt = Instant::now() + self.reconnect_delay;
+        self.reconnect_delay = (self.reconnect_delay * 2).min(MAX_RECONNECT_DELAY);
+    }
+
+    pub fn publish(&mut self, batch: &Batch) {
+        if self.connection().is_none() {
+            return;
+        }
+        if let Err(e) = self.try_publish(batch) {
+            self.disconnected(e);
+        }
+    }
+
+    fn try_publish(&mut self, batch: &Batch) -> io::Result<()> {
+        let qos = self.config.qos;
+        let retain = self.config.retain;
+        let mut messages = vec![];
+        for (reading, value) in batch.readings() {
+            if self.config.discovery && !self.announced.contains(&reading.id) {
+                let (topic, config) = self.discovery_message(reading);
+                // Retained so Home Assistant finds the sensors after a restart
+                messages.push((topic, config, true, Some(reading.id.clone())));
+            }
+            messages.push((
+                self.state_topic(&reading.id),
+                value.to_string(),
+                retain,
+                None,
+            ));
+        }
+
+        let Some(connection) = self.connection.as_mut() else {
+            return Ok(());
+        };
+        if messages.is_empty() {
+            return connection.ping();
+        }
+        for (topic, payload, retain, announced) in messages {
+            connection.publish(&topic, payload.as_bytes(), qos, retain)?;
+            if let Some(sensor_id) = announced {
+                self.announced.insert(sensor_id);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        net::TcpListener,
+        thread,
+        time::{Duration, SystemTime},
+    };
+
+    use super::{packet, read_packet, MqttConfig, MqttPublisher, CONNACK, CONNECT, PUBACK};
+    use crate::{export::Batch, sensors::SensorKind, snapshot::fixtures::reading};
+
+    fn config(broker: String) -> MqttConfig {
+        MqttConfig {
+            broker,
+            client_id: None,
+            username: Some(String::from("senso")),
+            password: Some(String::from("secret")),
+            qos: 1,
+            retain: false,
+            interval: 10,
+            host: Some(String::from("lab1")),
+            topic_prefix: String::from("senso"),
+            discovery: true,
+            discovery_prefix: String::from("homeassistant"),
+            sensors: None,
+        }
+    }
+
+    fn batch() -> Batch {
+        let reading = reading(
+            "coretemp-isa-0000",
+            "Package id 0",
+            SensorKind::Temperature,
+            55.0,
+        );
+        Batch::new(SystemTime::now(), vec![reading])
+    }
+
+    /// Accepts one client and returns the topics and payloads it published
+    fn broker(
+        listener: TcpListener,
+        publishes: usize,
+    ) -> thread::JoinHandle<Vec<(String, String)>> {
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let (header, _) = read_packet(&mut stream).unwrap();
+            assert!(header == CONNECT);
+            stream.write_all(&packet(CONNACK, &[0, 0])).unwrap();
+
+            let mut messages = vec![];
+            for _ in 0..publishes {
+                let (header, body) = read_packet(&mut stream).unwrap();
+                let topic_len = u16::from_be_bytes([body[0], body[1]]) as usize;
+                let topic = String::from_utf8(body[2..2 + topic_len].to_vec()).unwrap();
+                let mut payload = &body[2 + topic_len..];
+                if (header >> 1) & 0x03 == 1 {
+                    stream.write_all(&packet(PUBACK, &payload[..2])).unwrap();
+                    payload = &payload[2..];
+                }
+                messages.push((topic, String::from_utf8(payload.to_vec()).unwrap()));
+            }
+            messages
+        })
+    }
+
+    #[test]
+    fn test_publishes_readings_and_discovery() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let broker = broker(listener, 3);
+
+        let mut publisher = MqttPublisher::new(config(address));
+        publisher.publish(&batch());
+        publisher.publish(&batch());
+        let messages = broker.join().unwrap();
+
+        // Sensors are announced once per connection
+        assert!(messages.len() == 3);
+        assert!(
+            messages[0].0
+                == "homeassistant/sensor/senso_lab1_coretemp-isa-0000_Package_id_0/config"
+        );
+        assert!(messages[0]
+            .1
+            .contains("\"state_topic\":\"senso/lab1/coretemp-isa-0000/Package_id_0\""));
+        assert!(messages[0].1.contains("\"device_class\":\"temperature\""));
+        assert!(
+            messages[1]
+                == (
+                    String::from("senso/lab1/coretemp-isa-0000/Package_id_0"),
+                    String::from("55")
+                )
+        );
+        assert!(messages[2] == messages[1]);
+    }
+
+    #[test]
+    fn test_unreachable_broker_backs_off() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        let mut publisher = MqttPublisher::new(config(address));
+        publisher.publish(&batch());
+        assert!(publisher.connection.is_none());
+        assert!(publisher.reconnect_delay == Duration::from_secs(2));
+
+        // Not retried before the delay is up
+        publisher.publish(&batch());
+        assert!(publisher.reconnect_delay == Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_qos_2_is_rejected() {
+        let mut config = config(String::from("localhost"));
+        config.qos = 2;
+
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_password_needs_a_username() {
+        let mut config = config(String::from("localhost"));
+        config.username = None;
+
+        assert!(config
+            .validate()
+            .unwrap_err()
+            .contains("without a username"));
+    }
+}
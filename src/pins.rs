@@ -0,0 +1,130 @@
+use serde::{Deserialize, Serialize};
+
+use crate::sensors::SensorId;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum Pin {
+    /// Chip name, as returned by `SharedChip::name`
+    Chip(String),
+    Feature(SensorId),
+    /// Arbitrary features, possibly across chips, overlaid on a single chart
+    Group(Vec<SensorId>),
+}
+
+#[derive(Debug, Default)]
+pub struct Pins {
+    pins: Vec<Pin>,
+    focused: usize,
+}
+
+impl From<Vec<Pin>> for Pins {
+    fn from(pins: Vec<Pin>) -> Self {
+        Self { pins, focused: 0 }
+    }
+}
+
+impl Pins {
+    pub fn get(&self) -> &[Pin] {
+        &self.pins
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pins.is_empty()
+    }
+
+    pub fn focused(&self) -> usize {
+        self.focused
+    }
+
+    pub fn get_focused(&self) -> Option<&Pin> {
+        self.pins.get(self.focused)
+    }
+
+    /// Pins `pin` if it isn't pinned yet, otherwise unpins it
+    pub fn toggle(&mut self, pin: Pin) {
+        if let Some(index) = self.pins.iter().position(|p| *p == pin) {
+            self.pins.remove(index);
+        } else {
+            self.pins.push(pin);
+            self.focused = self.pins.len() - 1;
+        }
+        self.clamp_focus();
+    }
+
+    pub fn remove_focused(&mut self) {
+        if self.focused < self.pins.len() {
+            self.pins.remove(self.focused);
+        }
+        self.clamp_focus();
+    }
+
+    pub fn focus_next(&mut self) {
+        self.focused = (self.focused + 1).min(self.pins.len().saturating_sub(1));
+    }
+
+    pub fn focus_previous(&mut self) {
+        self.focused = self.focused.saturating_sub(1);
+    }
+
+    /// Swaps the focused pin with its neighbour, keeping it focused
+    pub fn move_focused_down(&mut self) {
+        if self.focused + 1 < self.pins.len() {
+            self.pins.swap(self.focused, self.focused + 1);
+            self.focused += 1;
+        }
+    }
+
+    pub fn move_focused_up(&mut self) {
+        if self.focused > 0 && self.focused < self.pins.len() {
+            self.pins.swap(self.focused, self.focused - 1);
+            self.focused -= 1;
+        }
+    }
+
+    fn clamp_focus(&mut self) {
+        self.focused = self.focused.min(self.pins.len().saturating_sub(1));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Pin, Pins};
+    use crate::sensors::SensorId;
+
+    #[test]
+    fn test_toggle() {
+        let mut pins = Pins::default();
+        pins.toggle(Pin::Chip(String::from("coretemp-isa-0000")));
+        pins.toggle(Pin::Feature(SensorId::new("nvme-pci-0100", "Composite")));
+
+        assert!(pins.get().len() == 2);
+        assert!(pins.focused() == 1);
+
+        pins.toggle(Pin::Chip(String::from("coretemp-isa-0000")));
+
+        assert!(pins.get() == [Pin::Feature(SensorId::new("nvme-pci-0100", "Composite"))]);
+        assert!(pins.focused() == 0);
+    }
+
+    #[test]
+    fn test_reorder() {
+        let mut pins = Pins::from(vec![
+            Pin::Chip(String::from("a")),
+            Pin::Chip(String::from("b")),
+            Pin::Chip(String::from("c")),
+        ]);
+
+        pins.move_focused_down();
+        pins.move_focused_down();
+        pins.move_focused_down();
+
+        assert!(pins.focused() == 2);
+        assert!(pins.get()[2] == Pin::Chip(String::from("a")));
+
+        pins.focus_previous();
+        pins.remove_focused();
+
+        assert!(pins.get() == [Pin::Chip(String::from("b")), Pin::Chip(String::from("a"))]);
+        assert!(pins.focused() == 1);
+    }
+}
@@ -0,0 +1,187 @@
+use std::{collections::HashMap, fs, path::Path, time::SystemTime};
+
+use crate::{
+    sensors::SensorKind,
+    snapshot::{ChipSnapshot, FeatureSnapshot, SubFeatureSnapshot},
+};
+
+pub const POWERCAP_ROOT: &str = "/sys/class/powercap";
+const CHIP_NAME: &str = "powercap-virtual-0";
+
+/// A RAPL zone's energy counter, AMD exposes its counters as intel-rapl too
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnergyCounter {
+    /// The zone directory, e.g. `intel-rapl:0:1` for the second subzone of
+    /// package 0
+    pub zone: String,
+    /// e.g. `package-0` or `dram`
+    pub name: String,
+    pub energy_uj: u64,
+    /// The counter wraps around to 0 after this
+    pub max_energy_range_uj: u64,
+}
+
+fn read_counter(zone: &str, dir: &Path) -> Option<EnergyCounter> {
+    let read = |file: &str| fs::read_to_string(dir.join(file)).ok();
+    Some(EnergyCounter {
+        zone: String::from(zone),
+        name: String::from(read("name")?.trim()),
+        energy_uj: read("energy_uj")?.trim().parse().ok()?,
+        max_energy_range_uj: read("max_energy_range_uj")?.trim().parse().ok()?,
+    })
+}
+
+/// Zones whose counter can't be read are left out, recent kernels only let
+/// root read `energy_uj`
+pub fn read_counters(root: &Path) -> Vec<EnergyCounter> {
+    let Ok(entries) = fs::read_dir(root) else {
+        return vec![];
+    };
+    let mut counters: Vec<EnergyCounter> = entries
+        .flatten()
+        .filter_map(|entry| {
+            let zone = entry.file_name().into_string().ok()?;
+            if !zone.starts_with("intel-rapl:") {
+                return None;
+            }
+            read_counter(&zone, &entry.path())
+        })
+        .collect();
+    counters.sort_by(|a, b| a.zone.cmp(&b.zone));
+    counters
+}
+
+/// Subzones repeat names like `dram` on every package, so they are labelled
+/// with their package, e.g. `package-1 dram`
+fn zone_label(counter: &EnergyCounter, counters: &[EnergyCounter]) -> String {
+    let parent = counter
+        .zone
+        .rsplit_once(':')
+        .map(|(parent, _)| parent)
+        .and_then(|parent| counters.iter().find(|other| other.zone == parent));
+    match parent {
+        Some(parent) => format!("{} {}", parent.name, counter.name),
+        None => counter.name.clone(),
+    }
+}
+
+/// Turns energy counters into watts over the time between two reads
+#[derive(Debug, Default)]
+pub struct PowerMeter {
+    last: HashMap<String, (u64, SystemTime)>,
+}
+
+impl PowerMeter {
+    fn watts(&self, counter: &EnergyCounter, time: SystemTime) -> Option<f64> {
+        let (last_energy, last_time) = self.last.get(&counter.zone)?;
+        let seconds = time.duration_since(*last_time).ok()?.as_secs_f64();
+        if seconds <= 0.0 {
+            return None;
+        }
+        let energy = if counter.energy_uj >= *last_energy {
+            counter.energy_uj - last_energy
+        } else {
+            counter.max_energy_range_uj.saturating_sub(*last_energy) + counter.energy_uj
+        };
+        let watts = energy as f64 / 1_000_000.0 / seconds;
+        Some((watts * 100.0).round() / 100.0)
+    }
+
+    /// A virtual chip with a power feature per zone. `None` without readable
+    /// zones, and without features until a second read gives a delta
+    pub fn chip(&mut self, counters: &[EnergyCounter], time: SystemTime) -> Option<ChipSnapshot> {
+        if counters.is_empty() {
+            return None;
+        }
+        let features = counters
+            .iter()
+            .filter_map(|counter| {
+                let watts = self.watts(counter, time)?;
+                Some((zone_label(counter, counters), watts))
+            })
+            .enumerate()
+            .map(|(i, (label, watts))| FeatureSnapshot {
+                name: format!("power{}", i + 1),
+                label,
+                kind: Some(SensorKind::Power),
+                sub_features: vec![SubFeatureSnapshot {
+                    name: format!("power{}_input", i + 1),
+                    value: Ok(watts),
+                }],
+            })
+            .collect();
+        self.last = counters
+            .iter()
+            .map(|counter| (counter.zone.clone(), (counter.energy_uj, time)))
+            .collect();
+        Some(ChipSnapshot {
+            name: String::from(CHIP_NAME),
+            host: None,
+            prefix: String::from("powercap"),
+            bus: Some(String::from("virtual")),
+            features,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, SystemTime};
+
+    use super::{EnergyCounter, PowerMeter};
+
+    fn counter(zone: &str, name: &str, energy_uj: u64) -> EnergyCounter {
+        EnergyCounter {
+            zone: String::from(zone),
+            name: String::from(name),
+            energy_uj,
+            max_energy_range_uj: 262_143_328_850,
+        }
+    }
+
+    fn readings(meter: &mut PowerMeter, energy: [u64; 2], time: SystemTime) -> Vec<(String, f64)> {
+        let counters = [
+            counter("intel-rapl:0", "package-0", energy[0]),
+            counter("intel-rapl:0:0", "dram", energy[1]),
+        ];
+        meter
+            .chip(&counters, time)
+            .unwrap()
+            .readings()
+            .into_iter()
+            .map(|reading| (reading.id.label, reading.value.unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_watts_from_energy_deltas() {
+        let mut meter = PowerMeter::default();
+        let start = SystemTime::now();
+
+        assert!(readings(&mut meter, [1_000_000, 0], start).is_empty());
+        assert!(
+            readings(
+                &mut meter,
+                [31_000_000, 4_000_000],
+                start + Duration::from_secs(2)
+            ) == [
+                (String::from("package-0"), 15.0),
+                (String::from("package-0 dram"), 2.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_counter_wraparound() {
+        let mut meter = PowerMeter::default();
+        let start = SystemTime::now();
+        readings(&mut meter, [262_142_328_850, 0], start);
+
+        assert!(readings(&mut meter, [9_000_000, 0], start + Duration::from_secs(1))[0].1 == 10.0);
+    }
+
+    #[test]
+    fn test_no_chip_without_counters() {
+        assert!(PowerMeter::default().chip(&[], SystemTime::now()).is_none());
+    }
+}
@@ -0,0 +1,123 @@
+use std::{
+    io::{self, BufRead, ErrorKind, Read, Write},
+    time::SystemTime,
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{history::Sample, sensors::SensorId, snapshot::ChipSnapshot};
+
+/// Sent by clients, one JSON object per line
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Request {
+    /// Has to come first when the server requires a token
+    Auth {
+        token: String,
+    },
+    ListChips,
+    Snapshot,
+    History {
+        sensor: SensorId,
+    },
+    /// Switches the connection to a stream of `Response::Snapshot`, one per
+    /// sample. No further requests are read
+    Subscribe,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Response {
+    Authenticated,
+    Chips {
+        chips: Vec<String>,
+        offline_chips: Vec<String>,
+    },
+    Snapshot {
+        time: SystemTime,
+        chips: Vec<ChipSnapshot>,
+    },
+    History {
+        sensor: SensorId,
+        samples: Vec<Sample>,
+    },
+    Error {
+        message: String,
+    },
+}
+
+pub fn write_message<W: Write, T: Serialize>(writer: &mut W, message: &T) -> io::Result<()> {
+    serde_json::to_writer(&mut *writer, message)?;
+    writer.write_all(b"\n")?;
+    writer.flush()
+}
+
+/// The longest request line a server reads, so clients that never send a
+/// newline can't make it buffer without bounds
+pub const MAX_REQUEST_LEN: u64 = 64 * 1024;
+
+/// `None` once the other side closed the connection
+pub fn read_message<R: BufRead, T: DeserializeOwned>(reader: &mut R) -> io::Result<Option<T>> {
+    read_message_with_limit(reader, u64::MAX)
+}
+
+/// Like `read_message`, but fails with `ErrorKind::InvalidInput` on lines
+/// longer than `limit` bytes
+pub fn read_message_with_limit<R: BufRead, T: DeserializeOwned>(
+    reader: &mut R,
+    limit: u64,
+) -> io::Result<Option<T>> {
+    let mut line = String::new();
+    if Read::take(&mut *reader, limit).read_line(&mut line)? == 0 {
+        return Ok(None);
+    }
+    if !line.ends_with('\n') && line.len() as u64 >= limit {
+        return Err(io::Error::new(
+            ErrorKind::InvalidInput,
+            format!("message longer than {} bytes", limit),
+        ));
+    }
+    Ok(Some(serde_json::from_str(&line)?))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufReader, ErrorKind};
+
+    use super::{read_message, read_message_with_limit, write_message, Request};
+    use crate::sensors::SensorId;
+
+    #[test]
+    fn test_messages_are_json_lines() {
+        let mut buf = vec![];
+        write_message(&mut buf, &Request::ListChips).unwrap();
+        write_message(
+            &mut buf,
+            &Request::History {
+                sensor: SensorId::new("a", "temp1"),
+            },
+        )
+        .unwrap();
+
+        assert!(String::from_utf8_lossy(&buf).starts_with("{\"type\":\"list_chips\"}\n"));
+
+        let mut reader = BufReader::new(buf.as_slice());
+        assert!(read_message::<_, Request>(&mut reader).unwrap() == Some(Request::ListChips));
+        assert!(
+            read_message::<_, Request>(&mut reader).unwrap()
+                == Some(Request::History {
+                    sensor: SensorId::new("a", "temp1")
+                })
+        );
+        assert!(read_message::<_, Request>(&mut reader).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_long_lines_are_rejected() {
+        let line = "x".repeat(100);
+        let mut reader = BufReader::new(line.as_bytes());
+        let result = read_message_with_limit::<_, Request>(&mut reader, 10);
+
+        assert!(result.is_err_and(|e| e.kind() == ErrorKind::InvalidInput));
+    }
+}
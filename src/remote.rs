@@ -0,0 +1,424 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    io::{self, BufRead, BufReader, Read, Write},
+    net::TcpStream,
+    os::unix::net::UnixStream,
+    path::PathBuf,
+    sync::mpsc::{self, Receiver, Sender},
+    thread,
+    time::{Duration, Instant, SystemTime},
+};
+
+use log::{info, warn};
+
+use crate::{
+    collector::Collector,
+    daemon::DEFAULT_PORT,
+    history::Sample,
+    protocol::{read_message, write_message, Request, Response},
+    sensors::SensorId,
+    snapshot::{qualified_chip_name, ChipSnapshot},
+    source::{SensorSource, SourceStatus},
+};
+
+/// The daemon sends a snapshot every tick, a connection this quiet is dead
+const READ_TIMEOUT: Duration = Duration::from_secs(10);
+/// Data older than this is shown as stale even if the connection looks fine
+const STALE_AFTER: Duration = Duration::from_secs(5);
+const MIN_RECONNECT_DELAY: Duration = Duration::from_secs(1);
+const MAX_RECONNECT_DELAY: Duration = Duration::from_secs(30);
+
+/// Where a daemon can be reached
+#[derive(Debug, Clone)]
+pub enum Address {
+    Unix(PathBuf),
+    /// `host:port`
+    Tcp(String),
+}
+
+/// Adds `port` to `host` or an IPv6 address like `::1`, and leaves
+/// `host:port` and `[::1]:port` alone
+pub fn with_default_port(address: &str, port: u16) -> String {
+    match address.strip_prefix('[') {
+        Some(rest) if rest.contains("]:") => String::from(address),
+        Some(_) => format!("{}:{}", address, port),
+        None => match address.matches(':').count() {
+            0 => format!("{}:{}", address, port),
+            1 => String::from(address),
+            _ => format!("[{}]:{}", address, port),
+        },
+    }
+}
+
+impl Address {
+    /// `host` or `host:port`, the port defaults to `DEFAULT_PORT`
+    pub fn tcp(address: &str) -> Self {
+        Self::Tcp(with_default_port(address, DEFAULT_PORT))
+    }
+}
+
+impl Display for Address {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Unix(path) => write!(f, "{}", path.display()),
+            Self::Tcp(address) => write!(f, "{}", address),
+        }
+    }
+}
+
+type Connection = (Box<dyn BufRead + Send>, Box<dyn Write + Send>);
+
+fn split<S: Read + Write + Send + 'static>(stream: S, reader: S) -> Connection {
+    (Box::new(BufReader::new(reader)), Box::new(stream))
+}
+
+fn connect(address: &Address) -> io::Result<Connection> {
+    match address {
+        Address::Unix(path) => {
+            let stream = UnixStream::connect(path)?;
+            stream.set_read_timeout(Some(READ_TIMEOUT))?;
+            let reader = stream.try_clone()?;
+            Ok(split(stream, reader))
+        }
+        Address::Tcp(address) => {
+            let stream = TcpStream::connect(address)?;
+            stream.set_read_timeout(Some(READ_TIMEOUT))?;
+            stream.set_nodelay(true)?;
+            let reader = stream.try_clone()?;
+            Ok(split(stream, reader))
+        }
+    }
+}
+
+enum RemoteEvent {
+    Connected,
+    Disconnected(String),
+    History {
+        sensor: SensorId,
+        samples: Vec<Sample>,
+    },
+    Snapshot {
+        time: SystemTime,
+        chips: Vec<ChipSnapshot>,
+    },
+}
+
+fn unexpected(response: Response) -> io::Error {
+    let message = match response {
+        Response::Error { message } => message,
+        response => format!("unexpected response {:?}", response),
+    };
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+fn next_response<R: BufRead>(reader: &mut R) -> io::Result<Response> {
+    read_message(reader)?
+        .ok_or_else(|| io::Error::new(io::ErrorKind::UnexpectedEof, "daemon closed the connection"))
+}
+
+/// Authenticates, fetches the history the daemon already has, then follows its
+/// samples until the connection breaks. Returns `Ok` once the TUI went away
+fn receive(
+    (mut reader, mut writer): Connection,
+    token: Option<&str>,
+    sender: &Sender<RemoteEvent>,
+) -> io::Result<()> {
+    let send = |event| sender.send(event).is_ok();
+
+    if let Some(token) = token {
+        let token = String::from(token);
+        write_message(&mut writer, &Request::Auth { token })?;
+        match next_response(&mut reader)? {
+            Response::Authenticated => {}
+            response => return Err(unexpected(response)),
+        }
+    }
+
+    write_message(&mut writer, &Request::Snapshot)?;
+    let chips = match next_response(&mut reader)? {
+        Response::Snapshot { chips, .. } => chips,
+        response => return Err(unexpected(response)),
+    };
+    for reading in chips.iter().flat_map(ChipSnapshot::readings) {
+        write_message(&mut writer, &Request::History { sensor: reading.id })?;
+        match next_response(&mut reader)? {
+            Response::History { sensor, samples } => {
+                if !send(RemoteEvent::History { sensor, samples }) {
+                    return Ok(());
+                }
+            }
+            // The sensor has no samples yet
+            Response::Error { .. } => {}
+            response => return Err(unexpected(response)),
+        }
+    }
+
+    write_message(&mut writer, &Request::Subscribe)?;
+    if !send(RemoteEvent::Connected) {
+        return Ok(());
+    }
+    loop {
+        match next_response(&mut reader)? {
+            Response::Snapshot { time, chips } => {
+                if !send(RemoteEvent::Snapshot { time, chips }) {
+                    return Ok(());
+                }
+            }
+            response => return Err(unexpected(response)),
+        }
+    }
+}
+
+/// Shows the chips sampled by a running daemon instead of reading them here,
+/// reconnecting whenever the connection drops
+pub struct RemoteSource {
+    address: Address,
+    /// Set when shown alongside other hosts, see `ChipSnapshot::on_host`
+    host: Option<String>,
+    events: Receiver<RemoteEvent>,
+    is_connected: bool,
+    last_error: Option<String>,
+    last_update: Option<Instant>,
+}
+
+impl RemoteSource {
+    /// Fails if the daemon can't be reached at all, later disconnects are retried
+    pub fn attach(address: Address, token: Option<String>) -> Result<Self, Box<dyn Error>> {
+        let connection =
+            connect(&address).map_err(|e| format!("can't connect to {}: {}", address, e))?;
+        info!("connected to {}", address);
+
+        Ok(Self::spawn(address, token, Some(connection)))
+    }
+
+    /// Connects in the background, retrying until the daemon comes up
+    pub fn new(address: Address, token: Option<String>) -> Self {
+        Self::spawn(address, token, None)
+    }
+
+    /// Tags the chips with `host` so several remote sources can share a collector
+    pub fn on_host(mut self, host: &str) -> Self {
+        self.host = Some(String::from(host));
+        self
+    }
+
+    fn spawn(address: Address, token: Option<String>, mut connection: Option<Connection>) -> Self {
+        let (sender, events) = mpsc::channel();
+        let thread_address = address.clone();
+        thread::spawn(move || {
+            let mut delay = MIN_RECONNECT_DELAY;
+            loop {
+                let started = Instant::now();
+                let connection = match connection.take() {
+                    Some(connection) => Ok(connection),
+                    None => connect(&thread_address),
+                };
+                let reason = match connection
+                    .and_then(|connection| receive(connection, token.as_deref(), &sender))
+                {
+                    Ok(()) => return,
+                    Err(e) => e.to_string(),
+                };
+                if sender.send(RemoteEvent::Disconnected(reason)).is_err() {
+                    return;
+                }
+                // Back off while the daemon stays unreachable, a connection that
+                // lasted a while starts over
+                if started.elapsed() > MAX_RECONNECT_DELAY {
+                    delay = MIN_RECONNECT_DELAY;
+                }
+                thread::sleep(delay);
+                delay = (delay * 2).min(MAX_RECONNECT_DELAY);
+            }
+        });
+
+        Self {
+            address,
+            host: None,
+            events,
+            is_connected: false,
+            last_error: None,
+            last_update: None,
+        }
+    }
+
+    /// When the last data came in
+    pub fn get_last_update(&self) -> Option<Instant> {
+        self.last_update
+    }
+}
+
+impl SensorSource for RemoteSource {
+    fn poll(&mut self, collector: &mut Collector) {
+        for event in self.events.try_iter() {
+            match event {
+                RemoteEvent::Connected => {
+                    if self.last_error.take().is_some() {
+                        info!("reconnected to {}", self.address);
+                    }
+                    self.is_connected = true;
+                }
+                RemoteEvent::Disconnected(reason) => {
+                    // Only log changes, the same error comes back on every retry
+                    if self.last_error.as_ref() != Some(&reason) {
+                        warn!("lost connection to {}: {}", self.address, reason);
+                    }
+                    self.is_connected = false;
+                    self.last_error = Some(reason);
+                }
+                RemoteEvent::History { mut sensor, samples } => {
+                    if let Some(host) = &self.host {
+                        sensor.chip = qualified_chip_name(host, &sensor.chip);
+                    }
+                    collector.import_history(&sensor, samples)
+                }
+                RemoteEvent::Snapshot { time, chips } => {
+                    match &self.host {
+                        Some(host) => {
+                            let chips = chips.into_iter().map(|chip| chip.on_host(host)).collect();
+                            collector.ingest_host(host, chips, time)
+                        }
+                        None => collector.ingest(chips, time),
+                    }
+                    self.last_update = Some(Instant::now());
+                }
+            }
+        }
+    }
+
+    /// The daemon rescans on its own
+    fn rescan(&mut self) {}
+
+    fn status(&self) -> SourceStatus {
+        let age = self.last_update.map(|time| time.elapsed());
+        if !self.is_connected {
+            let reason = match &self.last_error {
+                Some(e) => format!("reconnecting to {} ({})", self.address, e),
+                None => format!("connecting to {}", self.address),
+            };
+            return SourceStatus::Stale { age, reason };
+        }
+        match age {
+            Some(age) if age < STALE_AFTER => SourceStatus::Live,
+            _ => SourceStatus::Stale {
+                age,
+                reason: format!("waiting for data from {}", self.address),
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        net::TcpListener,
+        sync::Arc,
+        thread,
+        time::{Duration, Instant, SystemTime},
+    };
+
+    use super::{with_default_port, Address, RemoteSource};
+    use crate::{
+        collector::Collector,
+        daemon::{publish, serve_tcp, SharedState},
+        sensors::SensorKind,
+        snapshot::fixtures::{chip, input},
+        source::{SensorSource, SourceStatus},
+    };
+
+    struct FakeSource;
+
+    impl SensorSource for FakeSource {
+        fn poll(&mut self, collector: &mut Collector) {
+            let chip = chip(
+                "nvme-pci-0100",
+                vec![input("temp1", "Composite", SensorKind::Temperature, 38.0)],
+            );
+            collector.ingest(vec![chip], SystemTime::now());
+        }
+
+        fn rescan(&mut self) {}
+    }
+
+    /// Polls `source` until `condition` holds or a few seconds passed
+    fn poll_until(
+        source: &mut RemoteSource,
+        collector: &mut Collector,
+        condition: impl Fn(&RemoteSource, &Collector) -> bool,
+    ) -> bool {
+        let started = Instant::now();
+        while started.elapsed() < Duration::from_secs(5) {
+            source.poll(collector);
+            if condition(source, collector) {
+                return true;
+            }
+            thread::sleep(Duration::from_millis(10));
+        }
+        false
+    }
+
+    #[test]
+    fn test_follows_a_daemon_over_loopback() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let shared: SharedState = Arc::default();
+        let server_shared = Arc::clone(&shared);
+        let token = Some(String::from("secret"));
+        thread::spawn(move || serve_tcp(listener, server_shared, token));
+        thread::spawn(move || loop {
+            publish(&shared, &mut FakeSource);
+            thread::sleep(Duration::from_millis(10));
+        });
+
+        let mut source =
+            RemoteSource::attach(Address::tcp(&address), Some(String::from("secret"))).unwrap();
+        let mut collector = Collector::default();
+        assert!(matches!(
+            source.status(),
+            SourceStatus::Stale { age: None, .. }
+        ));
+        assert!(poll_until(
+            &mut source,
+            &mut collector,
+            |source, collector| {
+                source.status() == SourceStatus::Live
+                    && collector.get_chip("nvme-pci-0100").is_some()
+            }
+        ));
+    }
+
+    #[test]
+    fn test_wrong_token_is_reported_as_stale() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        let shared: SharedState = Arc::default();
+        let token = Some(String::from("secret"));
+        thread::spawn(move || serve_tcp(listener, shared, token));
+
+        let mut source =
+            RemoteSource::attach(Address::tcp(&address), Some(String::from("guess"))).unwrap();
+        let mut collector = Collector::default();
+        assert!(poll_until(&mut source, &mut collector, |source, _| {
+            matches!(source.status(), SourceStatus::Stale { reason, .. } if reason.contains("authentication failed"))
+        }));
+    }
+
+    #[test]
+    fn test_unreachable_daemon_fails_to_attach() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        drop(listener);
+
+        assert!(RemoteSource::attach(Address::tcp(&address), None).is_err());
+    }
+
+    #[test]
+    fn test_default_port() {
+        assert!(with_default_port("lab1", 7700) == "lab1:7700");
+        assert!(with_default_port("lab1:80", 7700) == "lab1:80");
+        assert!(with_default_port("::1", 7700) == "[::1]:7700");
+        assert!(with_default_port("[fe80::1]", 7700) == "[fe80::1]:7700");
+        assert!(with_default_port("[fe80::1]:80", 7700) == "[fe80::1]:80");
+    }
+}
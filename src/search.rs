@@ -0,0 +1,137 @@
+use regex::{Regex, RegexBuilder};
+
+use crate::{selector::Selector, snapshot::ChipSnapshot};
+
+// Queries are case-insensitive substrings, unless prefixed with `~` in which
+// case the rest of the query is compiled as a (case-insensitive) regex, or
+// with `:` for a sensor selector like `:kind=fan`
+const REGEX_PREFIX: char = '~';
+const SELECTOR_PREFIX: char = ':';
+
+#[derive(Debug)]
+enum Matcher {
+    Substring(String),
+    Regex(Regex),
+    /// Only matches features, chip names alone never match
+    Selector(Selector),
+    Invalid(String),
+}
+
+#[derive(Debug)]
+pub struct Search {
+    query: String,
+    matcher: Matcher,
+}
+
+impl Search {
+    pub fn new(query: &str) -> Self {
+        let matcher = if let Some(pattern) = query.strip_prefix(REGEX_PREFIX) {
+            match RegexBuilder::new(pattern).case_insensitive(true).build() {
+                Ok(regex) => Matcher::Regex(regex),
+                Err(e) => Matcher::Invalid(e.to_string()),
+            }
+        } else if let Some(selector) = query.strip_prefix(SELECTOR_PREFIX) {
+            match Selector::parse(selector) {
+                Ok(selector) => Matcher::Selector(selector),
+                Err(e) => Matcher::Invalid(e),
+            }
+        } else {
+            Matcher::Substring(query.to_lowercase())
+        };
+        Self {
+            query: String::from(query),
+            matcher,
+        }
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.query.is_empty()
+    }
+
+    pub fn error(&self) -> Option<&str> {
+        if let Matcher::Invalid(e) = &self.matcher {
+            Some(e)
+        } else {
+            None
+        }
+    }
+
+    pub fn is_match(&self, text: &str) -> bool {
+        match &self.matcher {
+            Matcher::Substring(needle) => text.to_lowercase().contains(needle.as_str()),
+            Matcher::Regex(regex) => regex.is_match(text),
+            Matcher::Selector(_) | Matcher::Invalid(_) => false,
+        }
+    }
+
+    /// Labels are matched like any text, selectors get to see the whole feature
+    pub fn is_feature_match(&self, chip: &ChipSnapshot, label: &str) -> bool {
+        match &self.matcher {
+            Matcher::Selector(selector) => chip
+                .features
+                .iter()
+                .find(|feature| feature.label == label)
+                .is_some_and(|feature| selector.matches(chip, feature)),
+            _ => self.is_match(label),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Search;
+    use crate::{
+        sensors::SensorKind,
+        snapshot::fixtures::{chip, feature},
+    };
+
+    #[test]
+    fn test_substring_is_case_insensitive() {
+        let search = Search::new("tctl");
+
+        assert!(search.is_match("Tctl"));
+        assert!(!search.is_match("Tccd1"));
+    }
+
+    #[test]
+    fn test_regex() {
+        let search = Search::new("~^core [0-3]$");
+
+        assert!(search.is_match("Core 2"));
+        assert!(!search.is_match("Core 12"));
+        assert!(search.error().is_none());
+    }
+
+    #[test]
+    fn test_invalid_regex_matches_nothing() {
+        let search = Search::new("~core (");
+
+        assert!(search.error().is_some());
+        assert!(!search.is_match("core ("));
+    }
+
+    #[test]
+    fn test_selector() {
+        let chip = chip(
+            "nvme-pci-0100",
+            vec![feature("temp1", "Composite", SensorKind::Temperature, &[])],
+        );
+
+        assert!(Search::new(":chip.bus=pci").is_feature_match(&chip, "Composite"));
+        assert!(!Search::new(":chip.bus=pci").is_match("nvme-pci-0100"));
+        assert!(!Search::new(":kind=fan").is_feature_match(&chip, "Composite"));
+        assert!(Search::new(":kind=").error().is_some());
+    }
+
+    #[test]
+    fn test_empty_query_matches_everything() {
+        let search = Search::new("");
+
+        assert!(search.is_empty());
+        assert!(search.is_match("nvme-pci-0100"));
+    }
+}
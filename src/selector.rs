@@ -0,0 +1,494 @@
+use std::{
+    error::Error,
+    fmt::{self, Display, Formatter},
+    mem,
+};
+
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    collector::Collector,
+    sensors::{Reading, Status},
+    snapshot::{ChipSnapshot, FeatureSnapshot},
+    source::SensorSource,
+};
+
+const KINDS: [&str; 7] = [
+    "temperature",
+    "fan",
+    "voltage",
+    "power",
+    "current",
+    "humidity",
+    "other",
+];
+
+const STATUSES: [&str; 5] = ["normal", "high", "critical", "emergency", "unknown"];
+
+/// `*` matches anything and `?` one character, the rest is literal. Case is ignored
+pub fn glob_regex(glob: &str) -> Regex {
+    let pattern = glob
+        .split('*')
+        .map(|part| {
+            part.split('?')
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join(".")
+        })
+        .collect::<Vec<_>>()
+        .join(".*");
+    RegexBuilder::new(&format!("^{}$", pattern))
+        .case_insensitive(true)
+        .build()
+        .expect("escaped glob is a valid regex")
+}
+
+fn is_glob(text: &str) -> bool {
+    text.contains(['*', '?'])
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Key {
+    Kind,
+    Label,
+    Name,
+    Status,
+    Host,
+    Chip,
+    Bus,
+    Prefix,
+}
+
+const KEYS: [(&str, Key); 9] = [
+    ("kind", Key::Kind),
+    ("label", Key::Label),
+    ("name", Key::Name),
+    ("status", Key::Status),
+    ("host", Key::Host),
+    ("chip", Key::Chip),
+    ("chip.name", Key::Chip),
+    ("chip.bus", Key::Bus),
+    ("chip.prefix", Key::Prefix),
+];
+
+impl Key {
+    fn value(&self, chip: &ChipSnapshot, feature: &FeatureSnapshot) -> String {
+        match self {
+            Self::Kind => String::from(feature.kind.map_or("other", |kind| kind.name())),
+            Self::Label => feature.label.clone(),
+            Self::Name => feature.name.clone(),
+            Self::Status => String::from(
+                feature
+                    .reading(&chip.name)
+                    .map_or(Status::Unknown, |reading| reading.status())
+                    .name(),
+            ),
+            Self::Host => chip.host.clone().unwrap_or_default(),
+            Self::Chip => String::from(chip.local_name()),
+            Self::Bus => chip.bus.clone().unwrap_or_default(),
+            Self::Prefix => chip.prefix.clone(),
+        }
+    }
+
+    /// Values a typo can be caught in
+    fn known_values(&self) -> Option<&'static [&'static str]> {
+        match self {
+            Self::Kind => Some(&KINDS),
+            Self::Status => Some(&STATUSES),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Term {
+    /// `label`, `chip/label` or `host/chip/label`
+    Path {
+        host: Option<Regex>,
+        chip: Option<Regex>,
+        label: Regex,
+    },
+    Key {
+        key: Key,
+        value: Regex,
+        is_negated: bool,
+    },
+}
+
+impl Term {
+    fn matches(&self, chip: &ChipSnapshot, feature: &FeatureSnapshot) -> bool {
+        match self {
+            Self::Path {
+                host,
+                chip: chip_name,
+                label,
+            } => {
+                let is_match = |glob: &Option<Regex>, text: &str| match glob {
+                    Some(glob) => glob.is_match(text),
+                    None => true,
+                };
+                is_match(host, chip.host.as_deref().unwrap_or(""))
+                    && is_match(chip_name, chip.local_name())
+                    && label.is_match(&feature.label)
+            }
+            Self::Key {
+                key,
+                value,
+                is_negated,
+            } => value.is_match(&key.value(chip, feature)) != *is_negated,
+        }
+    }
+}
+
+fn parse_path(path: &str) -> Term {
+    let parts: Vec<&str> = path.splitn(3, '/').collect();
+    let (host, chip, label) = match parts.as_slice() {
+        [label] => (None, None, label),
+        [chip, label] => (None, Some(chip), label),
+        [host, chip, label] => (Some(host), Some(chip), label),
+        _ => unreachable!("splitn(3) returns one to three parts"),
+    };
+    Term::Path {
+        host: host.map(|host| glob_regex(host)),
+        chip: chip.map(|chip| glob_regex(chip)),
+        label: glob_regex(label),
+    }
+}
+
+/// `kind=fan` or `chip.bus!=pci`, `None` if the token isn't a key at all
+fn parse_key_term(token: &str) -> Option<Result<Term, String>> {
+    let (name, value) = token.split_once('=')?;
+    let (name, is_negated) = match name.strip_suffix('!') {
+        Some(name) => (name, true),
+        None => (name, false),
+    };
+    if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c == '.') {
+        return None;
+    }
+    let Some((_, key)) = KEYS.iter().find(|(key, _)| *key == name) else {
+        let keys = KEYS.map(|(key, _)| key).join(", ");
+        return Some(Err(format!(
+            "unknown key `{}`, expected one of {}",
+            name, keys
+        )));
+    };
+    if let Some(known_values) = key.known_values() {
+        if !is_glob(value) && !known_values.contains(&value.to_lowercase().as_str()) {
+            return Some(Err(format!(
+                "unknown {} `{}`, expected one of {}",
+                name,
+                value,
+                known_values.join(", ")
+            )));
+        }
+    }
+    Some(Ok(Term::Key {
+        key: *key,
+        value: glob_regex(value),
+        is_negated,
+    }))
+}
+
+/// Splits on unquoted whitespace and commas, the outer list holds the
+/// alternatives
+fn tokenize(text: &str) -> Result<Vec<Vec<String>>, String> {
+    let mut alternatives = vec![];
+    let mut tokens = vec![];
+    let mut token = String::new();
+    let mut has_token = false;
+    let mut is_quoted = false;
+    let flush = |tokens: &mut Vec<String>, token: &mut String, has_token: &mut bool| {
+        if mem::take(has_token) {
+            tokens.push(mem::take(token));
+        }
+    };
+    for c in text.chars() {
+        match c {
+            '"' => {
+                is_quoted = !is_quoted;
+                has_token = true;
+            }
+            c if is_quoted => token.push(c),
+            ',' => {
+                flush(&mut tokens, &mut token, &mut has_token);
+                alternatives.push(mem::take(&mut tokens));
+            }
+            c if c.is_whitespace() => flush(&mut tokens, &mut token, &mut has_token),
+            c => {
+                token.push(c);
+                has_token = true;
+            }
+        }
+    }
+    if is_quoted {
+        return Err(String::from("unclosed \" in selector"));
+    }
+    flush(&mut tokens, &mut token, &mut has_token);
+    alternatives.push(tokens);
+    Ok(alternatives)
+}
+
+/// Picks sensors, e.g. `coretemp-*/Core *`, `nvme-*/Composite`, `kind=fan` or
+/// `chip.bus=pci kind=temperature`. The terms of a selector all have to match,
+/// commas separate alternatives. Globs ignore case, paths are `label`,
+/// `chip/label` or `host/chip/label` and `!=` negates a key
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Selector {
+    text: String,
+    alternatives: Vec<Vec<Term>>,
+}
+
+impl Selector {
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let mut alternatives = vec![];
+        for tokens in tokenize(text)? {
+            let mut terms = vec![];
+            // Unquoted labels with spaces come in as several tokens
+            let mut path: Vec<String> = vec![];
+            for token in tokens {
+                match parse_key_term(&token) {
+                    Some(term) => {
+                        if !path.is_empty() {
+                            terms.push(parse_path(&mem::take(&mut path).join(" ")));
+                        }
+                        terms.push(term?);
+                    }
+                    None => path.push(token),
+                }
+            }
+            if !path.is_empty() {
+                terms.push(parse_path(&path.join(" ")));
+            }
+            if terms.is_empty() {
+                return Err(format!("empty selector in {:?}", text));
+            }
+            alternatives.push(terms);
+        }
+        Ok(Self {
+            text: String::from(text),
+            alternatives,
+        })
+    }
+
+    pub fn matches(&self, chip: &ChipSnapshot, feature: &FeatureSnapshot) -> bool {
+        self.alternatives
+            .iter()
+            .any(|terms| terms.iter().all(|term| term.matches(chip, feature)))
+    }
+
+    /// The chips with a matching feature, keeping only the matching features
+    pub fn filter_chips(&self, chips: &[ChipSnapshot]) -> Vec<ChipSnapshot> {
+        chips
+            .iter()
+            .filter_map(|chip| {
+                let features: Vec<FeatureSnapshot> = chip
+                    .features
+                    .iter()
+                    .filter(|feature| self.matches(chip, feature))
+                    .cloned()
+                    .collect();
+                if features.is_empty() {
+                    return None;
+                }
+                Some(ChipSnapshot {
+                    features,
+                    ..chip.clone()
+                })
+            })
+            .collect()
+    }
+
+    pub fn readings(&self, chips: &[ChipSnapshot]) -> Vec<Reading> {
+        self.filter_chips(chips)
+            .iter()
+            .flat_map(ChipSnapshot::readings)
+            .collect()
+    }
+}
+
+/// The readings of every chip, or only the selected ones
+pub fn select_readings(selector: Option<&Selector>, chips: &[ChipSnapshot]) -> Vec<Reading> {
+    match selector {
+        Some(selector) => selector.readings(chips),
+        None => chips.iter().flat_map(ChipSnapshot::readings).collect(),
+    }
+}
+
+impl PartialEq for Selector {
+    fn eq(&self, other: &Self) -> bool {
+        self.text == other.text
+    }
+}
+
+impl Display for Selector {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.text)
+    }
+}
+
+impl TryFrom<String> for Selector {
+    type Error = String;
+
+    fn try_from(text: String) -> Result<Self, Self::Error> {
+        Self::parse(&text)
+    }
+}
+
+impl From<Selector> for String {
+    fn from(selector: Selector) -> Self {
+        selector.text
+    }
+}
+
+/// `chip/label`, kind and value of every selected feature, in the selector's
+/// path syntax
+pub fn list_lines(sensors: Option<&Selector>, chips: &[ChipSnapshot]) -> Vec<String> {
+    let rows: Vec<(String, &str, String)> = chips
+        .iter()
+        .flat_map(|chip| chip.features.iter().map(move |feature| (chip, feature)))
+        .filter(|(chip, feature)| match sensors {
+            Some(sensors) => sensors.matches(chip, feature),
+            None => true,
+        })
+        .map(|(chip, feature)| {
+            let value = match feature.reading(&chip.name) {
+                Some(Reading {
+                    value: Ok(value),
+                    kind,
+                    ..
+                }) => format!("{}{}", value, kind.unit()),
+                Some(_) => String::from("N/A"),
+                None => String::from("-"),
+            };
+            (
+                format!("{}/{}", chip.name, feature.label),
+                feature.kind.map_or("other", |kind| kind.name()),
+                value,
+            )
+        })
+        .collect();
+    let width = rows
+        .iter()
+        .map(|(path, _, _)| path.len())
+        .max()
+        .unwrap_or(0);
+    rows.into_iter()
+        .map(|(path, kind, value)| format!("{:<width$}  {:<11}  {}", path, kind, value))
+        .collect()
+}
+
+/// Prints the selected sensors once, failing if there are none
+pub fn run_list(
+    sensors: Option<&Selector>,
+    mut source: Box<dyn SensorSource>,
+) -> Result<(), Box<dyn Error>> {
+    let mut collector = Collector::default();
+    source.poll(&mut collector);
+    let lines = list_lines(sensors, collector.get_chips());
+    if let (Some(sensors), true) = (sensors, lines.is_empty()) {
+        return Err(format!("no sensors match {:?}", sensors.to_string()).into());
+    }
+    for line in lines {
+        println!("{}", line);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{list_lines, Selector};
+    use crate::{
+        sensors::SensorKind,
+        snapshot::{
+            fixtures::{chip, input},
+            ChipSnapshot,
+        },
+    };
+
+    fn chips() -> Vec<ChipSnapshot> {
+        vec![
+            chip(
+                "coretemp-isa-0000",
+                vec![
+                    input("temp1", "Package id 0", SensorKind::Temperature, 45.0),
+                    input("temp2", "Core 0", SensorKind::Temperature, 40.0),
+                    input("temp3", "Core 1", SensorKind::Temperature, 41.0),
+                ],
+            ),
+            chip(
+                "nvme-pci-0100",
+                vec![input("temp1", "Composite", SensorKind::Temperature, 38.0)],
+            ),
+            chip(
+                "it8688-isa-0a40",
+                vec![input("fan1", "fan1", SensorKind::Fan, 1200.0)],
+            ),
+        ]
+    }
+
+    /// `chip/label` of every selected sensor
+    fn select(selector: &str) -> Vec<String> {
+        let selector = Selector::parse(selector).unwrap();
+        selector
+            .readings(&chips())
+            .iter()
+            .map(|reading| format!("{}/{}", reading.id.chip, reading.id.label))
+            .collect()
+    }
+
+    #[test]
+    fn test_paths() {
+        assert!(
+            select("coretemp-*/Core *") == ["coretemp-isa-0000/Core 0", "coretemp-isa-0000/Core 1"]
+        );
+        assert!(select("nvme-pci-*/Composite") == ["nvme-pci-0100/Composite"]);
+        assert!(select("package id ?") == ["coretemp-isa-0000/Package id 0"]);
+        assert!(select("*/fan1").len() == 1);
+    }
+
+    #[test]
+    fn test_keys() {
+        assert!(select("kind=fan") == ["it8688-isa-0a40/fan1"]);
+        assert!(select("chip.bus=pci") == ["nvme-pci-0100/Composite"]);
+        assert!(select("chip.bus=isa kind!=fan").len() == 3);
+        assert!(select(r#"label="Core 1""#) == ["coretemp-isa-0000/Core 1"]);
+    }
+
+    #[test]
+    fn test_alternatives() {
+        assert!(select("kind=fan, */Composite").len() == 2);
+    }
+
+    #[test]
+    fn test_list_lines() {
+        let selector = Selector::parse("kind=fan, */Composite").unwrap();
+        let lines = list_lines(Some(&selector), &chips());
+
+        assert!(
+            lines
+                == [
+                    "nvme-pci-0100/Composite  temperature  38C",
+                    "it8688-isa-0a40/fan1     fan          1200RPM"
+                ]
+        );
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(Selector::parse("").is_err());
+        assert!(Selector::parse("kind=fan,").is_err());
+        assert!(Selector::parse("kind=fans").is_err());
+        assert!(Selector::parse("chip.driver=nvme").is_err());
+        assert!(Selector::parse("\"Core 0").is_err());
+    }
+
+    #[test]
+    fn test_serde_keeps_the_text() {
+        let selector: Selector = serde_json::from_str(r#""kind=fan""#).unwrap();
+
+        assert!(serde_json::to_string(&selector).unwrap() == r#""kind=fan""#);
+        assert!(serde_json::from_str::<Selector>(r#""kind=""#).is_err());
+    }
+}
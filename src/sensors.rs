@@ -1,9 +1,259 @@
-use lm_sensors::{Initializer, LMSensors};
-use std::error::Error;
+use lm_sensors::{feature, FeatureRef, Initializer, LMSensors};
+use serde::{Deserialize, Serialize};
+use std::{error::Error, fmt};
 
-#[allow(dead_code)]
-pub fn get_all_sensors() -> Result<LMSensors, Box<dyn Error>> {
-    let sensors = Initializer::default().initialize()?;
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SensorError {
+    /// libsensors could not be initialized, usually a missing or broken config
+    Initialize(String),
+    /// The sub-feature exists but its value could not be read
+    Read(String),
+}
+
+impl fmt::Display for SensorError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SensorError::Initialize(e) => write!(f, "failed to initialize lm-sensors: {}", e),
+            SensorError::Read(e) => write!(f, "read failed: {}", e),
+        }
+    }
+}
+
+impl Error for SensorError {}
+
+/// Identifies a single feature across chips, `label` alone is not unique
+/// (e.g. several chips expose a `temp1`)
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SensorId {
+    pub chip: String,
+    pub label: String,
+}
+
+impl SensorId {
+    pub fn new(chip: &str, label: &str) -> Self {
+        Self {
+            chip: String::from(chip),
+            label: String::from(label),
+        }
+    }
+}
+
+/// The feature kinds senso knows how to read and display, ordered as the
+/// overview groups them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum SensorKind {
+    Temperature,
+    Fan,
+    Voltage,
+    Power,
+    Current,
+    Humidity,
+}
+
+impl SensorKind {
+    pub fn from_feature(feature: &FeatureRef) -> Option<Self> {
+        match feature.kind()? {
+            feature::Kind::Temperature => Some(Self::Temperature),
+            feature::Kind::Fan => Some(Self::Fan),
+            feature::Kind::Voltage => Some(Self::Voltage),
+            feature::Kind::Power => Some(Self::Power),
+            feature::Kind::Current => Some(Self::Current),
+            feature::Kind::Humidity => Some(Self::Humidity),
+            _ => None,
+        }
+    }
+
+    /// Lowercase, for exported metrics
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Temperature => "temperature",
+            Self::Fan => "fan",
+            Self::Voltage => "voltage",
+            Self::Power => "power",
+            Self::Current => "current",
+            Self::Humidity => "humidity",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Self::Temperature,
+            Self::Fan,
+            Self::Voltage,
+            Self::Power,
+            Self::Current,
+            Self::Humidity,
+        ]
+        .into_iter()
+        .find(|kind| kind.name() == name)
+    }
+
+    pub fn unit(&self) -> &'static str {
+        match self {
+            Self::Temperature => "C",
+            Self::Fan => "RPM",
+            Self::Voltage => "V",
+            Self::Power => "W",
+            Self::Current => "A",
+            Self::Humidity => "%",
+        }
+    }
+
+    // Some power meters only report an average, hence more than one input.
+    // These are sub-feature names without the feature part, e.g. `crit` for `temp1_crit`
+    pub fn inputs(&self) -> &'static [&'static str] {
+        match self {
+            Self::Power => &["input", "average"],
+            _ => &["input"],
+        }
+    }
+
+    pub fn maximum(&self) -> Option<&'static str> {
+        match self {
+            Self::Humidity => None,
+            _ => Some("max"),
+        }
+    }
+
+    pub fn critical(&self) -> Option<&'static str> {
+        match self {
+            Self::Fan | Self::Humidity => None,
+            _ => Some("crit"),
+        }
+    }
+
+    pub fn emergency(&self) -> Option<&'static str> {
+        match self {
+            Self::Temperature => Some("emergency"),
+            _ => None,
+        }
+    }
+}
+
+/// Charts fall back to this critical temperature when the chip doesn't report one
+pub const DEFAULT_TEMPERATURE_CRITICAL: f64 = 100.0;
+
+/// The highest threshold a reading has crossed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Normal,
+    High,
+    Critical,
+    Emergency,
+    Unknown,
+}
+
+impl Status {
+    /// Lowercase, e.g. for CSS classes
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Normal => "normal",
+            Self::High => "high",
+            Self::Critical => "critical",
+            Self::Emergency => "emergency",
+            Self::Unknown => "unknown",
+        }
+    }
+
+    /// Unknown ranks lowest so sensors without thresholds don't mask the others
+    pub fn severity(&self) -> u8 {
+        match self {
+            Self::Unknown => 0,
+            Self::Normal => 1,
+            Self::High => 2,
+            Self::Critical => 3,
+            Self::Emergency => 4,
+        }
+    }
+}
+
+/// A single sampled value of a feature along with its limits
+#[derive(Debug, Clone)]
+pub struct Reading {
+    pub id: SensorId,
+    pub kind: SensorKind,
+    pub value: Result<f64, SensorError>,
+    pub maximum: Option<f64>,
+    pub critical: Option<f64>,
+    pub emergency: Option<f64>,
+    pub is_alarm: bool,
+}
+
+impl Reading {
+    /// Distance to the closest upper limit the chip reports
+    pub fn headroom(&self) -> Option<f64> {
+        let value = self.value.as_ref().ok()?;
+        self.critical.or(self.maximum).map(|limit| limit - value)
+    }
+
+    /// Temperatures without any threshold are measured against
+    /// `DEFAULT_TEMPERATURE_CRITICAL`, like in the charts
+    pub fn status(&self) -> Status {
+        let Ok(value) = self.value else {
+            return Status::Unknown;
+        };
+        let critical = match (self.kind, self.maximum, self.critical, self.emergency) {
+            (SensorKind::Temperature, None, None, None) => Some(DEFAULT_TEMPERATURE_CRITICAL),
+            _ => self.critical,
+        };
+        let is_crossed = |threshold: Option<f64>| threshold.is_some_and(|t| value >= t);
+        if is_crossed(self.emergency) {
+            Status::Emergency
+        } else if is_crossed(critical) {
+            Status::Critical
+        } else if is_crossed(self.maximum) {
+            Status::High
+        } else if self.maximum.is_some() || critical.is_some() || self.emergency.is_some() {
+            Status::Normal
+        } else {
+            Status::Unknown
+        }
+    }
+}
+
+pub fn get_all_sensors() -> Result<LMSensors, SensorError> {
+    let sensors = Initializer::default()
+        .initialize()
+        .map_err(|e| SensorError::Initialize(e.to_string()))?;
 
     Ok(sensors)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{Reading, SensorError, SensorKind, Status};
+    use crate::snapshot::fixtures;
+
+    fn reading(kind: SensorKind, value: f64, maximum: Option<f64>, critical: Option<f64>) -> Reading {
+        Reading {
+            maximum,
+            critical,
+            ..fixtures::reading("chip", "temp1", kind, value)
+        }
+    }
+
+    #[test]
+    fn test_status_follows_crossed_threshold() {
+        let temperature = |value| reading(SensorKind::Temperature, value, Some(80.0), Some(95.0));
+
+        assert!(temperature(60.0).status() == Status::Normal);
+        assert!(temperature(80.0).status() == Status::High);
+        assert!(temperature(97.0).status() == Status::Critical);
+    }
+
+    #[test]
+    fn test_status_without_thresholds() {
+        assert!(reading(SensorKind::Temperature, 60.0, None, None).status() == Status::Normal);
+        assert!(reading(SensorKind::Temperature, 100.0, None, None).status() == Status::Critical);
+        assert!(reading(SensorKind::Fan, 1200.0, None, None).status() == Status::Unknown);
+    }
+
+    #[test]
+    fn test_unreadable_value() {
+        let mut reading = reading(SensorKind::Temperature, 0.0, Some(80.0), Some(95.0));
+        reading.value = Err(SensorError::Read(String::from("I/O error")));
+
+        assert!(reading.status() == Status::Unknown);
+        assert!(reading.headroom().is_none());
+    }
+}
@@ -0,0 +1,496 @@
+use std::{
+    error::Error,
+    fs,
+    path::PathBuf,
+    process::{Command, ExitStatus},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use clap::ValueEnum;
+use log::info;
+use serde::Serialize;
+use signal_hook::consts::SIGINT;
+
+use crate::{
+    collector::Collector,
+    selector::{select_readings, Selector},
+    sensors::{Reading, SensorId, SensorKind},
+    source::SensorSource,
+    stats::{mean, sum},
+};
+
+/// A sensor counts as cooled down once it is back within this share of its
+/// rise above the baseline
+const COOLED_DOWN_SHARE: f64 = 0.1;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ReportFormat {
+    Markdown,
+    Json,
+}
+
+#[derive(Debug, Clone)]
+pub struct SessionOptions {
+    pub interval: Duration,
+    /// Idle sampling before the command starts
+    pub baseline: Duration,
+    /// The longest wait for temperatures to settle after the command exits
+    pub cool_down: Duration,
+    pub format: ReportFormat,
+    pub output: Option<PathBuf>,
+    pub sensors: Option<Selector>,
+}
+
+/// Seconds `(time, value)` samples spent at or above `threshold`. Each sample
+/// stands for the time until the next one, the last one for `interval`
+pub fn time_above(samples: &[(f64, f64)], threshold: f64, interval: f64) -> f64 {
+    sum(samples
+        .iter()
+        .enumerate()
+        .filter(|(_, (_, value))| *value >= threshold)
+        .map(|(i, (time, _))| match samples.get(i + 1) {
+            Some((next, _)) => next - time,
+            None => interval,
+        }))
+}
+
+/// The values of one sensor over the session
+#[derive(Debug, Clone)]
+struct Track {
+    id: SensorId,
+    kind: SensorKind,
+    maximum: Option<f64>,
+    critical: Option<f64>,
+    /// Seconds since the command started, negative during the baseline
+    samples: Vec<(f64, f64)>,
+}
+
+impl Track {
+    fn values(&self, from: f64, to: f64) -> impl Iterator<Item = (f64, f64)> + '_ {
+        self.samples
+            .iter()
+            .copied()
+            .filter(move |(time, _)| *time >= from && *time <= to)
+    }
+
+    fn mean(&self, from: f64, to: f64) -> Option<f64> {
+        mean(self.values(from, to).map(|(_, value)| value))
+    }
+
+    fn peak(&self) -> Option<(f64, f64)> {
+        self.values(0.0, f64::INFINITY)
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))
+    }
+
+    fn time_above(&self, threshold: f64, interval: f64) -> f64 {
+        let samples: Vec<(f64, f64)> = self.values(0.0, f64::INFINITY).collect();
+        time_above(&samples, threshold, interval)
+    }
+
+    /// The highest baseline sample, or the baseline plus a share of the rise
+    /// for sensors that rose well above their noise
+    fn cooled_down_level(&self) -> Option<f64> {
+        let baseline = self.mean(f64::NEG_INFINITY, -f64::EPSILON)?;
+        let noise = self
+            .values(f64::NEG_INFINITY, -f64::EPSILON)
+            .map(|(_, value)| value)
+            .fold(baseline, f64::max);
+        let (_, peak) = self.peak()?;
+        Some(noise.max(baseline + (peak - baseline) * COOLED_DOWN_SHARE))
+    }
+
+    /// Seconds after `load_end` until the value got back down
+    fn cool_down(&self, load_end: f64) -> Option<f64> {
+        let level = self.cooled_down_level()?;
+        self.values(load_end, f64::INFINITY)
+            .find(|(_, value)| *value <= level)
+            .map(|(time, _)| time - load_end)
+    }
+}
+
+/// What a session measured for one sensor, times are in seconds since the
+/// command started
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SensorSummary {
+    pub chip: String,
+    pub label: String,
+    pub kind: &'static str,
+    pub unit: &'static str,
+    pub baseline: Option<f64>,
+    pub peak: Option<f64>,
+    pub time_to_peak: Option<f64>,
+    /// `None` if the sensor has no such threshold
+    pub time_above_max: Option<f64>,
+    pub time_above_crit: Option<f64>,
+    pub load_average: Option<f64>,
+    /// Seconds after the command exited, `None` if it didn't cool down in time
+    pub cool_down: Option<f64>,
+}
+
+/// Samples of every sensor before, during and after the command
+#[derive(Debug, Clone)]
+pub struct Recording {
+    interval: f64,
+    /// Seconds since the command started at which it exited
+    load_end: Option<f64>,
+    tracks: Vec<Track>,
+}
+
+impl Recording {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval: interval.as_secs_f64(),
+            load_end: None,
+            tracks: vec![],
+        }
+    }
+
+    pub fn record(&mut self, time: f64, readings: &[Reading]) {
+        for reading in readings {
+            let Ok(value) = reading.value else {
+                continue;
+            };
+            let index = match self.tracks.iter().position(|track| track.id == reading.id) {
+                Some(index) => index,
+                None => {
+                    self.tracks.push(Track {
+                        id: reading.id.clone(),
+                        kind: reading.kind,
+                        maximum: reading.maximum,
+                        critical: reading.critical,
+                        samples: vec![],
+                    });
+                    self.tracks.len() - 1
+                }
+            };
+            self.tracks[index].samples.push((time, value));
+        }
+    }
+
+    /// Makes the times so far relative to the start of the command, they were
+    /// relative to the start of the baseline
+    pub fn start_load(&mut self, baseline: f64) {
+        for track in &mut self.tracks {
+            for (time, _) in &mut track.samples {
+                *time -= baseline;
+            }
+        }
+    }
+
+    pub fn end_load(&mut self, time: f64) {
+        self.load_end = Some(time);
+    }
+
+    /// Whether every temperature is back near its baseline, other kinds like
+    /// fans may take their time
+    pub fn is_cooled_down(&self) -> bool {
+        let Some(load_end) = self.load_end else {
+            return false;
+        };
+        self.tracks
+            .iter()
+            .filter(|track| track.kind == SensorKind::Temperature)
+            .all(
+                |track| match (track.cooled_down_level(), track.samples.last()) {
+                    (Some(level), Some((time, value))) => *time >= load_end && *value <= level,
+                    _ => true,
+                },
+            )
+    }
+
+    pub fn summarize(&self) -> Vec<SensorSummary> {
+        let load_end = self.load_end.unwrap_or(f64::INFINITY);
+        self.tracks
+            .iter()
+            .map(|track| {
+                let peak = track.peak();
+                SensorSummary {
+                    chip: track.id.chip.clone(),
+                    label: track.id.label.clone(),
+                    kind: track.kind.name(),
+                    unit: track.kind.unit(),
+                    baseline: track.mean(f64::NEG_INFINITY, -f64::EPSILON),
+                    peak: peak.map(|(_, value)| value),
+                    time_to_peak: peak.map(|(time, _)| time),
+                    time_above_max: track
+                        .maximum
+                        .map(|maximum| track.time_above(maximum, self.interval)),
+                    time_above_crit: track
+                        .critical
+                        .map(|critical| track.time_above(critical, self.interval)),
+                    load_average: track.mean(0.0, load_end),
+                    cool_down: track.cool_down(load_end),
+                }
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    pub command: String,
+    /// `None` if the command was killed by a signal
+    pub exit_code: Option<i32>,
+    pub baseline_seconds: f64,
+    pub load_seconds: f64,
+    pub cool_down_seconds: f64,
+    pub sensors: Vec<SensorSummary>,
+}
+
+/// A report table cell, `-` without a value
+pub fn cell(value: Option<f64>, unit: &str) -> String {
+    match value {
+        Some(value) => format!("{:.1}{}", value, unit),
+        None => String::from("-"),
+    }
+}
+
+/// The `chip/label` cell of a report table, labels may contain `|`
+pub fn sensor_cell(chip: &str, label: &str) -> String {
+    format!("{}/{}", chip, label).replace('|', "\\|")
+}
+
+impl Report {
+    pub fn to_markdown(&self) -> String {
+        let exit = match self.exit_code {
+            Some(code) => format!("exit code {}", code),
+            None => String::from("killed by a signal"),
+        };
+        let mut lines = vec![
+            format!("# senso run: `{}`", self.command),
+            String::new(),
+            format!(
+                "Load: {:.1} s, {}. Baseline: {:.1} s, cool-down: {:.1} s.",
+                self.load_seconds, exit, self.baseline_seconds, self.cool_down_seconds
+            ),
+            String::new(),
+            String::from("| Sensor | Baseline | Peak | Time to peak | Above max | Above crit | Load average | Cool-down |"),
+            String::from("|---|---:|---:|---:|---:|---:|---:|---:|"),
+        ];
+        lines.extend(self.sensors.iter().map(|sensor| {
+            let cool_down = match (sensor.cool_down, sensor.baseline) {
+                (Some(seconds), _) => cell(Some(seconds), " s"),
+                (None, Some(_)) => String::from("not reached"),
+                (None, None) => String::from("-"),
+            };
+            format!(
+                "| {} | {} | {} | {} | {} | {} | {} | {} |",
+                sensor_cell(&sensor.chip, &sensor.label),
+                cell(sensor.baseline, sensor.unit),
+                cell(sensor.peak, sensor.unit),
+                cell(sensor.time_to_peak, " s"),
+                cell(sensor.time_above_max, " s"),
+                cell(sensor.time_above_crit, " s"),
+                cell(sensor.load_average, sensor.unit),
+                cool_down,
+            )
+        }));
+        lines.join("\n") + "\n"
+    }
+
+    pub fn to_json(&self) -> String {
+        serde_json::to_string_pretty(self).expect("reports serialize") + "\n"
+    }
+}
+
+/// Samples every `interval` until `is_done`, times are relative to `origin`
+fn sample_until(
+    origin: Instant,
+    options: &SessionOptions,
+    recording: &mut Recording,
+    collector: &mut Collector,
+    source: &mut dyn SensorSource,
+    mut is_done: impl FnMut(&Recording) -> bool,
+) {
+    loop {
+        source.poll(collector);
+        let readings = select_readings(options.sensors.as_ref(), collector.get_chips());
+        recording.record(origin.elapsed().as_secs_f64(), &readings);
+        if is_done(recording) {
+            return;
+        }
+        thread::sleep(options.interval);
+    }
+}
+
+/// Records the sensors before, while and after `command` runs, then writes a
+/// report. Ctrl-C ends the current phase instead of senso
+pub fn run_session(
+    command: &[String],
+    options: SessionOptions,
+    mut source: Box<dyn SensorSource>,
+) -> Result<(), Box<dyn Error>> {
+    let (program, args) = command.split_first().ok_or("no command given")?;
+    let interrupted = Arc::new(AtomicBool::new(false));
+    signal_hook::flag::register(SIGINT, Arc::clone(&interrupted))?;
+    let is_interrupted = || interrupted.swap(false, Ordering::Relaxed);
+
+    let mut recording = Recording::new(options.interval);
+    let mut collector = Collector::default();
+    let start = Instant::now();
+    info!("sampling a baseline for {:?}", options.baseline);
+    sample_until(
+        start,
+        &options,
+        &mut recording,
+        &mut collector,
+        source.as_mut(),
+        |_| is_interrupted() || start.elapsed() >= options.baseline,
+    );
+
+    let load_start = Instant::now();
+    let baseline_seconds = load_start.duration_since(start).as_secs_f64();
+    recording.start_load(baseline_seconds);
+    let mut child = Command::new(program)
+        .args(args)
+        .spawn()
+        .map_err(|e| format!("can't run {}: {}", program, e))?;
+    let mut status: Option<ExitStatus> = None;
+    sample_until(
+        load_start,
+        &options,
+        &mut recording,
+        &mut collector,
+        source.as_mut(),
+        |_| {
+            // The command gets the same Ctrl-C and is left to exit on its own
+            is_interrupted();
+            status = child.try_wait().ok().flatten();
+            status.is_some()
+        },
+    );
+    let load_seconds = load_start.elapsed().as_secs_f64();
+    recording.end_load(load_seconds);
+    let status = match status {
+        Some(status) => status,
+        None => child.wait()?,
+    };
+
+    let cool_down_start = Instant::now();
+    info!("{} exited with {}, cooling down", program, status);
+    sample_until(
+        load_start,
+        &options,
+        &mut recording,
+        &mut collector,
+        source.as_mut(),
+        |recording| {
+            is_interrupted()
+                || recording.is_cooled_down()
+                || cool_down_start.elapsed() >= options.cool_down
+        },
+    );
+
+    let report = Report {
+        command: command.join(" "),
+        exit_code: status.code(),
+        baseline_seconds,
+        load_seconds,
+        cool_down_seconds: cool_down_start.elapsed().as_secs_f64(),
+        sensors: recording.summarize(),
+    };
+    let text = match options.format {
+        ReportFormat::Markdown => report.to_markdown(),
+        ReportFormat::Json => report.to_json(),
+    };
+    match &options.output {
+        Some(path) => {
+            fs::write(path, text).map_err(|e| format!("can't write {}: {}", path.display(), e))?
+        }
+        None => print!("{}", text),
+    }
+    if !status.success() {
+        return Err(format!("{} exited with {}", program, status).into());
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::{sensor_cell, Recording, Report};
+    use crate::{
+        sensors::{Reading, SensorKind},
+        snapshot::fixtures,
+    };
+
+    fn reading(value: f64) -> Reading {
+        fixtures::reading(
+            "coretemp-isa-0000",
+            "Package id 0",
+            SensorKind::Temperature,
+            value,
+        )
+    }
+
+    /// Idle at 40, up to 90 under load for 4 s, then back down
+    fn recording() -> Recording {
+        let mut recording = Recording::new(Duration::from_secs(1));
+        let values = [40.0, 40.0, 61.0, 85.0, 90.0, 82.0, 60.0, 44.0, 40.0];
+        for (i, value) in values.into_iter().enumerate() {
+            recording.record(i as f64 - 2.0, &[reading(value)]);
+            if i == 5 {
+                recording.end_load(3.0);
+            }
+        }
+        recording
+    }
+
+    #[test]
+    fn test_summary() {
+        let summary = &recording().summarize()[0];
+
+        assert!(summary.baseline == Some(40.0));
+        assert!(summary.peak == Some(90.0));
+        assert!(summary.time_to_peak == Some(2.0));
+        assert!(summary.time_above_max == Some(3.0));
+        assert!(summary.time_above_crit == Some(0.0));
+        assert!(summary.load_average == Some(79.5));
+        assert!(summary.cool_down == Some(2.0));
+    }
+
+    #[test]
+    fn test_is_cooled_down() {
+        let mut recording = Recording::new(Duration::from_secs(1));
+        recording.record(-1.0, &[reading(40.0)]);
+        recording.record(0.0, &[reading(70.0)]);
+        assert!(!recording.is_cooled_down());
+
+        recording.end_load(1.0);
+        recording.record(1.0, &[reading(60.0)]);
+        assert!(!recording.is_cooled_down());
+
+        recording.record(2.0, &[reading(42.0)]);
+        assert!(recording.is_cooled_down());
+    }
+
+    #[test]
+    fn test_markdown() {
+        let report = Report {
+            command: String::from("stress-ng --cpu 8"),
+            exit_code: Some(0),
+            baseline_seconds: 2.0,
+            load_seconds: 3.0,
+            cool_down_seconds: 3.0,
+            sensors: recording().summarize(),
+        };
+        let markdown = report.to_markdown();
+
+        assert!(markdown.starts_with("# senso run: `stress-ng --cpu 8`"));
+        assert!(markdown.contains(
+            "| coretemp-isa-0000/Package id 0 | 40.0C | 90.0C | 2.0 s | 3.0 s | 0.0 s | 79.5C | 2.0 s |"
+        ));
+        assert!(report.to_json().contains("\"time_to_peak\": 2.0"));
+    }
+
+    #[test]
+    fn test_sensor_cell_escapes_pipes() {
+        assert!(sensor_cell("nct6775-isa-0290", "CPU|SYS") == r"nct6775-isa-0290/CPU\|SYS");
+    }
+}
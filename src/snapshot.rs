@@ -0,0 +1,333 @@
+use lm_sensors::{prelude::SharedChip, ChipRef, FeatureRef, LMSensors, SubFeatureRef};
+use serde::{Deserialize, Serialize};
+
+use crate::sensors::{Reading, SensorError, SensorId, SensorKind};
+
+/// A sub-feature value as read at snapshot time, e.g. `temp1_crit`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SubFeatureSnapshot {
+    pub name: String,
+    pub value: Result<f64, SensorError>,
+}
+
+impl SubFeatureSnapshot {
+    fn read(sub_feature: &SubFeatureRef) -> Option<Self> {
+        let name = sub_feature.name()?.ok()?;
+        Some(Self {
+            name: String::from(name),
+            value: sub_feature
+                .value()
+                .map(|value| value.raw_value())
+                .map_err(|e| SensorError::Read(e.to_string())),
+        })
+    }
+
+    /// The name without the feature part, `temp1_crit_hyst` reads better as `crit_hyst`
+    pub fn short_name(&self) -> &str {
+        self.name
+            .split_once('_')
+            .map_or(&self.name, |(_, name)| name)
+    }
+
+    pub fn is_alarm(&self) -> bool {
+        self.name.ends_with("_alarm")
+    }
+
+    /// Alarm sub-features (`temp1_alarm`, `temp1_crit_alarm`, ...) are nonzero while raised
+    pub fn is_active_alarm(&self) -> bool {
+        self.is_alarm() && self.value.as_ref().is_ok_and(|value| *value != 0.0)
+    }
+
+    /// Whether the value is measured in the feature's unit, as opposed to flags
+    /// and settings like alarms or fan divisors
+    pub fn has_unit(&self) -> bool {
+        ![
+            "alarm", "beep", "fault", "type", "div", "pulses", "interval", "enable",
+        ]
+        .iter()
+        .any(|suffix| self.name.ends_with(suffix))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureSnapshot {
+    /// The raw feature name, e.g. `temp1`
+    pub name: String,
+    /// The configured label, falls back to the raw name
+    pub label: String,
+    /// `None` for feature kinds senso doesn't chart, they still count for alarms
+    pub kind: Option<SensorKind>,
+    pub sub_features: Vec<SubFeatureSnapshot>,
+}
+
+impl FeatureSnapshot {
+    fn read(feature: &FeatureRef) -> Option<Self> {
+        let name = feature.name().and_then(|name| name.ok()).map(String::from);
+        let label = feature.label().ok().or_else(|| name.clone())?;
+        Some(Self {
+            name: name.unwrap_or_else(|| label.clone()),
+            label,
+            kind: SensorKind::from_feature(feature),
+            sub_features: feature
+                .sub_feature_iter()
+                .filter_map(|sub_feature| SubFeatureSnapshot::read(&sub_feature))
+                .collect(),
+        })
+    }
+
+    pub fn sub_feature(&self, short_name: &str) -> Option<&SubFeatureSnapshot> {
+        self.sub_features
+            .iter()
+            .find(|sub_feature| sub_feature.short_name() == short_name)
+    }
+
+    /// Features without an input sub-feature have nothing to show
+    pub fn reading(&self, chip: &str) -> Option<Reading> {
+        let kind = self.kind?;
+        let value = kind
+            .inputs()
+            .iter()
+            .find_map(|input| self.sub_feature(input))?
+            .value
+            .clone();
+        let limit = |short_name: Option<&str>| {
+            self.sub_feature(short_name?)
+                .and_then(|sub_feature| sub_feature.value.clone().ok())
+        };
+
+        Some(Reading {
+            id: SensorId::new(chip, &self.label),
+            kind,
+            value,
+            maximum: limit(kind.maximum()),
+            critical: limit(kind.critical()),
+            emergency: limit(kind.emergency()),
+            is_alarm: self
+                .sub_features
+                .iter()
+                .any(SubFeatureSnapshot::is_active_alarm),
+        })
+    }
+}
+
+/// Everything senso shows about a chip, detached from libsensors so it can be
+/// kept after the chip disappears or sent to another process
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ChipSnapshot {
+    /// Unique on a host, e.g. `coretemp-isa-0000`. Prefixed with the host when
+    /// chips of several hosts are shown together, see `on_host`
+    pub name: String,
+    /// Only set when chips of several hosts are shown together
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub host: Option<String>,
+    /// The driver, e.g. `coretemp`
+    pub prefix: String,
+    /// The bus type in lowercase, e.g. `pci`
+    pub bus: Option<String>,
+    pub features: Vec<FeatureSnapshot>,
+}
+
+impl ChipSnapshot {
+    pub fn read(chip: &ChipRef) -> Self {
+        let prefix = chip
+            .prefix()
+            .and_then(|prefix| prefix.ok())
+            .map(String::from);
+        Self {
+            host: None,
+            name: chip
+                .name()
+                .ok()
+                .or_else(|| prefix.clone())
+                .unwrap_or_else(|| String::from("unknown")),
+            prefix: prefix.unwrap_or_else(|| String::from("?")),
+            bus: chip
+                .bus()
+                .kind()
+                .map(|kind| format!("{:?}", kind).to_lowercase()),
+            features: chip
+                .feature_iter()
+                .filter_map(|feature| FeatureSnapshot::read(&feature))
+                .collect(),
+        }
+    }
+
+    /// Tags the chip with the host it was read on. Chip names are only unique
+    /// per host, so the host becomes part of the name
+    pub fn on_host(mut self, host: &str) -> Self {
+        self.name = qualified_chip_name(host, &self.name);
+        self.host = Some(String::from(host));
+        self
+    }
+
+    /// The name as the host knows it, without the host prefix
+    pub fn local_name(&self) -> &str {
+        match &self.host {
+            Some(host) => self
+                .name
+                .strip_prefix(host.as_str())
+                .and_then(|name| name.strip_prefix('/'))
+                .unwrap_or(&self.name),
+            None => &self.name,
+        }
+    }
+
+    pub fn display_name(&self) -> String {
+        format!("{}/{}", self.prefix, self.local_name())
+    }
+
+    pub fn readings(&self) -> Vec<Reading> {
+        self.features
+            .iter()
+            .filter_map(|feature| feature.reading(&self.name))
+            .collect()
+    }
+
+    /// The kind of feature charted in the TUI. Temperatures, unless the chip
+    /// only measures power like the RAPL counters
+    pub fn charted_kind(&self) -> SensorKind {
+        let has_kind = |kind| self.features.iter().any(|feature| feature.kind == Some(kind));
+        if !has_kind(SensorKind::Temperature) && has_kind(SensorKind::Power) {
+            SensorKind::Power
+        } else {
+            SensorKind::Temperature
+        }
+    }
+
+    /// Whether any feature, including ones senso doesn't chart, has a raised alarm
+    pub fn has_active_alarm(&self) -> bool {
+        self.features
+            .iter()
+            .flat_map(|feature| feature.sub_features.iter())
+            .any(SubFeatureSnapshot::is_active_alarm)
+    }
+}
+
+pub fn qualified_chip_name(host: &str, chip: &str) -> String {
+    format!("{}/{}", host, chip)
+}
+
+pub fn read_chips(sensors: &LMSensors) -> Vec<ChipSnapshot> {
+    sensors
+        .chip_iter(None)
+        .map(|chip| ChipSnapshot::read(&chip))
+        .collect()
+}
+
+/// Snapshot and reading builders shared by the tests
+#[cfg(test)]
+pub mod fixtures {
+    use super::{ChipSnapshot, FeatureSnapshot, SubFeatureSnapshot};
+    use crate::sensors::{Reading, SensorId, SensorKind};
+
+    /// `values` pairs short sub-feature names with their value, e.g. `("input", 45.0)`
+    pub fn feature(
+        name: &str,
+        label: &str,
+        kind: SensorKind,
+        values: &[(&str, f64)],
+    ) -> FeatureSnapshot {
+        FeatureSnapshot {
+            name: String::from(name),
+            label: String::from(label),
+            kind: Some(kind),
+            sub_features: values
+                .iter()
+                .map(|(sub_feature, value)| SubFeatureSnapshot {
+                    name: format!("{}_{}", name, sub_feature),
+                    value: Ok(*value),
+                })
+                .collect(),
+        }
+    }
+
+    /// A feature with nothing but an input
+    pub fn input(name: &str, label: &str, kind: SensorKind, input: f64) -> FeatureSnapshot {
+        feature(name, label, kind, &[("input", input)])
+    }
+
+    /// The prefix and bus come from the name, e.g. `coretemp-isa-0000`
+    pub fn chip(name: &str, features: Vec<FeatureSnapshot>) -> ChipSnapshot {
+        let parts: Vec<&str> = name.split('-').collect();
+        ChipSnapshot {
+            name: String::from(name),
+            host: None,
+            prefix: String::from(parts[0]),
+            bus: parts.get(1).map(|bus| String::from(*bus)),
+            features,
+        }
+    }
+
+    /// Maximum at 80 and critical at 100
+    pub fn reading(chip: &str, label: &str, kind: SensorKind, value: f64) -> Reading {
+        Reading {
+            id: SensorId::new(chip, label),
+            kind,
+            value: Ok(value),
+            maximum: Some(80.0),
+            critical: Some(100.0),
+            emergency: None,
+            is_alarm: false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        fixtures::{chip, feature},
+        ChipSnapshot,
+    };
+    use crate::sensors::{SensorError, SensorKind};
+
+    fn coretemp() -> ChipSnapshot {
+        chip(
+            "coretemp-isa-0000",
+            vec![feature(
+                "temp1",
+                "Package id 0",
+                SensorKind::Temperature,
+                &[
+                    ("input", 45.0),
+                    ("max", 80.0),
+                    ("crit", 100.0),
+                    ("crit_alarm", 0.0),
+                ],
+            )],
+        )
+    }
+
+    #[test]
+    fn test_readings_pick_up_limits() {
+        let readings = coretemp().readings();
+
+        assert!(readings.len() == 1);
+        assert!(readings[0].id.label == "Package id 0");
+        assert!(readings[0].value == Ok(45.0));
+        assert!(readings[0].maximum == Some(80.0));
+        assert!(readings[0].critical == Some(100.0));
+        assert!(readings[0].emergency.is_none());
+        assert!(!readings[0].is_alarm);
+    }
+
+    #[test]
+    fn test_alarm_and_read_errors() {
+        let mut chip = coretemp();
+        chip.features[0].sub_features[0].value = Err(SensorError::Read(String::from("EIO")));
+        chip.features[0].sub_features[3].value = Ok(1.0);
+
+        assert!(chip.has_active_alarm());
+        assert!(chip.readings()[0].value.is_err());
+        assert!(chip.readings()[0].is_alarm);
+    }
+
+    #[test]
+    fn test_chips_on_a_host_keep_their_local_name() {
+        let chip = coretemp().on_host("lab1");
+
+        assert!(chip.name == "lab1/coretemp-isa-0000");
+        assert!(chip.local_name() == "coretemp-isa-0000");
+        assert!(chip.display_name() == "coretemp/coretemp-isa-0000");
+        assert!(chip.readings()[0].id.chip == "lab1/coretemp-isa-0000");
+    }
+}
@@ -0,0 +1,149 @@
+use std::{
+    path::Path,
+    time::{Duration, Instant, SystemTime},
+};
+
+use lm_sensors::LMSensors;
+use log::warn;
+
+use crate::{
+    collector::Collector,
+    cpu::{read_cpu_times, read_cpus, CPU_ROOT},
+    powercap::{read_counters, PowerMeter, POWERCAP_ROOT},
+    sensors::{self, SensorError},
+    snapshot::read_chips,
+};
+
+/// How often chips are rescanned to pick up hotplugged devices
+const RESCAN_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Whether the data shown is current
+#[derive(Debug, Clone, PartialEq)]
+pub enum SourceStatus {
+    Live,
+    /// `age` is how long ago the last data came in, `None` if none has yet
+    Stale {
+        age: Option<Duration>,
+        reason: String,
+    },
+}
+
+/// One of the hosts shown by a fleet
+#[derive(Debug, Clone, PartialEq)]
+pub struct HostStatus {
+    pub name: String,
+    pub status: SourceStatus,
+}
+
+/// Where the chips shown by senso come from
+pub trait SensorSource {
+    /// Feeds whatever is new since the last call into `collector`
+    fn poll(&mut self, collector: &mut Collector);
+
+    /// Picks up chips that appeared or disappeared since the last scan
+    fn rescan(&mut self);
+
+    /// Local sensors are read on every tick, so they are always live
+    fn status(&self) -> SourceStatus {
+        SourceStatus::Live
+    }
+
+    /// The hosts the chips come from, in display order. Empty unless chips of
+    /// several hosts are shown, which then carry their host's name
+    fn hosts(&self) -> Vec<HostStatus> {
+        vec![]
+    }
+}
+
+/// Reads chips from libsensors on this machine
+pub struct LocalSource {
+    /// Only `None` if a rescan failed to reinitialize lm-sensors
+    sensors: Option<LMSensors>,
+    last_rescan: Instant,
+    /// Adds the RAPL energy counters as a chip, lm-sensors doesn't show them
+    power_meter: PowerMeter,
+}
+
+impl LocalSource {
+    pub fn new() -> Result<Self, SensorError> {
+        Ok(Self {
+            sensors: Some(sensors::get_all_sensors()?),
+            last_rescan: Instant::now(),
+            power_meter: PowerMeter::default(),
+        })
+    }
+}
+
+impl SensorSource for LocalSource {
+    fn poll(&mut self, collector: &mut Collector) {
+        if self.last_rescan.elapsed() >= RESCAN_INTERVAL {
+            self.rescan();
+        }
+        let mut chips = self.sensors.as_ref().map(read_chips).unwrap_or_default();
+        let time = SystemTime::now();
+        chips.extend(
+            self.power_meter
+                .chip(&read_counters(Path::new(POWERCAP_ROOT)), time),
+        );
+        collector.ingest(chips, time);
+        collector.ingest_cpus(&read_cpus(Path::new(CPU_ROOT)), &read_cpu_times(), time);
+    }
+
+    fn rescan(&mut self) {
+        self.last_rescan = Instant::now();
+        // libsensors keeps global state, the old instance has to be cleaned up first
+        self.sensors = None;
+        self.sensors = match sensors::get_all_sensors() {
+            Ok(sensors) => Some(sensors),
+            Err(e) => {
+                warn!("rescan failed: {}", e);
+                None
+            }
+        };
+    }
+}
+
+/// Parses `500ms`, `1s`, `2m` or plain seconds
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let text = text.trim();
+    let split = text
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .unwrap_or(text.len());
+    let (number, unit) = text.split_at(split);
+    let number: f64 = number
+        .parse()
+        .map_err(|_| format!("invalid duration {:?}, expected e.g. 500ms, 1s or 2m", text))?;
+    let seconds = match unit {
+        "ms" => number / 1000.0,
+        "" | "s" => number,
+        "m" => number * 60.0,
+        _ => {
+            return Err(format!(
+                "unknown unit {:?} in {:?}, use ms, s or m",
+                unit, text
+            ))
+        }
+    };
+    if seconds <= 0.0 {
+        return Err(String::from("the interval has to be positive"));
+    }
+    Ok(Duration::from_secs_f64(seconds))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::parse_duration;
+
+    #[test]
+    fn test_parse_duration() {
+        assert!(parse_duration("1s") == Ok(Duration::from_secs(1)));
+        assert!(parse_duration("500ms") == Ok(Duration::from_millis(500)));
+        assert!(parse_duration("2m") == Ok(Duration::from_secs(120)));
+        assert!(parse_duration("3") == Ok(Duration::from_secs(3)));
+        assert!(parse_duration("0s").is_err());
+        assert!(parse_duration("1h").is_err());
+        assert!(parse_duration("s").is_err());
+    }
+}
@@ -0,0 +1,51 @@
+use std::{env, error::Error, fs, path::PathBuf};
+
+use log::warn;
+use serde::{Deserialize, Serialize};
+
+use crate::pins::Pin;
+
+/// State that survives restarts, stored as JSON in the XDG state dir
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct PersistedState {
+    #[serde(default)]
+    pub pins: Vec<Pin>,
+}
+
+/// `$XDG_STATE_HOME/senso`, falling back to `~/.local/state/senso`
+pub fn state_dir() -> Option<PathBuf> {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .filter(|dir| dir.is_absolute())
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .map(|dir| dir.join("senso"))
+}
+
+fn state_file_path() -> Option<PathBuf> {
+    state_dir().map(|dir| dir.join("state.json"))
+}
+
+impl PersistedState {
+    pub fn load() -> Self {
+        let Some(path) = state_file_path() else {
+            return Self::default();
+        };
+        match fs::read_to_string(&path) {
+            Ok(contents) => serde_json::from_str(&contents).unwrap_or_else(|e| {
+                warn!("ignoring invalid state file {}: {}", path.display(), e);
+                Self::default()
+            }),
+            Err(_) => Self::default(),
+        }
+    }
+
+    pub fn save(&self) -> Result<(), Box<dyn Error>> {
+        let path = state_file_path().ok_or("could not determine state directory")?;
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,32 @@
+/// Like `Iterator::sum`, but 0 rather than -0 when there are no values
+pub fn sum(values: impl IntoIterator<Item = f64>) -> f64 {
+    values.into_iter().fold(0.0, |total, value| total + value)
+}
+
+/// The arithmetic mean, `None` when there are no values
+pub fn mean(values: impl IntoIterator<Item = f64>) -> Option<f64> {
+    let (total, count) = values
+        .into_iter()
+        .fold((0.0, 0), |(total, count), value| (total + value, count + 1));
+    match count {
+        0 => None,
+        count => Some(total / count as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{mean, sum};
+
+    #[test]
+    fn test_empty() {
+        assert!(sum([]).to_string() == "0");
+        assert!(mean([]).is_none());
+    }
+
+    #[test]
+    fn test_values() {
+        assert!(sum([1.5, 2.5, 5.0]) == 9.0);
+        assert!(mean([1.5, 2.5, 5.0]) == Some(3.0));
+    }
+}
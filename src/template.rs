@@ -0,0 +1,954 @@
+use std::{
+    error::Error,
+    io::{self, ErrorKind, Write},
+    mem, slice, thread,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Local, SecondsFormat};
+use clap::ValueEnum;
+
+use crate::{
+    collector::Collector,
+    cpu::is_cpu_chip,
+    selector::{select_readings, Selector},
+    sensors::{Reading, SensorKind, Status},
+    snapshot::{ChipSnapshot, FeatureSnapshot, SubFeatureSnapshot},
+    source::SensorSource,
+    stats::{mean, sum},
+};
+
+pub const DEFAULT_TEMPLATE: &str = "{chip}/{label}: {value}{unit}";
+
+/// What the template is filled in for, each one becomes a line
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Scope {
+    /// Every feature of every chip
+    Feature,
+    /// Every chip
+    Chip,
+    /// The whole system at once
+    System,
+}
+
+impl Scope {
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Feature => "feature",
+            Self::Chip => "chip",
+            Self::System => "system",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Field {
+    Time,
+    Host,
+    Chip,
+    Prefix,
+    Bus,
+    Name,
+    Label,
+    Kind,
+    Value,
+    Unit,
+    Max,
+    Crit,
+    Emergency,
+    Headroom,
+    Status,
+    Alarm,
+}
+
+const FIELDS: [(&str, Field); 16] = [
+    ("time", Field::Time),
+    ("host", Field::Host),
+    ("chip", Field::Chip),
+    ("prefix", Field::Prefix),
+    ("bus", Field::Bus),
+    ("name", Field::Name),
+    ("label", Field::Label),
+    ("kind", Field::Kind),
+    ("value", Field::Value),
+    ("unit", Field::Unit),
+    ("max", Field::Max),
+    ("crit", Field::Crit),
+    ("emergency", Field::Emergency),
+    ("headroom", Field::Headroom),
+    ("status", Field::Status),
+    ("alarm", Field::Alarm),
+];
+
+impl Field {
+    /// Status and alarm cover whatever the scope is, the rest belongs to a
+    /// chip or a feature
+    fn is_available(&self, scope: Scope) -> bool {
+        match self {
+            Self::Time | Self::Status | Self::Alarm => true,
+            Self::Host | Self::Chip | Self::Prefix | Self::Bus => scope != Scope::System,
+            _ => scope == Scope::Feature,
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(
+            self,
+            Self::Value | Self::Max | Self::Crit | Self::Emergency | Self::Headroom
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Function {
+    Min,
+    Max,
+    Avg,
+    Sum,
+    Count,
+}
+
+/// A single sensor looked up by a template
+#[derive(Debug, Clone)]
+enum Sensor {
+    /// `value("coretemp-*/Package id 0")`, the first sensor the selector picks
+    Selected(Selector),
+    /// `hottest`, the hottest temperature of the CPU chips, drives and GPUs
+    /// run hotter without the system being hot
+    Hottest,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Field(Field),
+    /// A sub-feature by its short name, e.g. `crit_hyst`
+    SubFeature(String),
+    /// Over the readings of one kind, optionally only the selected ones
+    Aggregate {
+        function: Function,
+        kind: SensorKind,
+        sensors: Option<Selector>,
+    },
+    /// The value of one sensor, wherever the item is
+    Sensor(Sensor),
+    /// Only on the right of comparisons
+    Number(f64),
+}
+
+impl Expr {
+    fn is_numeric(&self) -> bool {
+        match self {
+            Self::Field(field) => field.is_numeric(),
+            Self::SubFeature(_) | Self::Aggregate { .. } | Self::Sensor(_) | Self::Number(_) => {
+                true
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Operator {
+    Less,
+    LessOrEqual,
+    Equal,
+    NotEqual,
+    GreaterOrEqual,
+    Greater,
+}
+
+/// Two character operators come first so `>=` isn't read as `>`
+const OPERATORS: [(&str, Operator); 6] = [
+    ("<=", Operator::LessOrEqual),
+    ("==", Operator::Equal),
+    ("!=", Operator::NotEqual),
+    (">=", Operator::GreaterOrEqual),
+    ("<", Operator::Less),
+    (">", Operator::Greater),
+];
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Alarm,
+    /// The status is at least this bad
+    Reached(Status),
+    Compare(Expr, Operator, Expr),
+    Not(Box<Condition>),
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Expr {
+        expr: Expr,
+        precision: Option<usize>,
+    },
+    If {
+        condition: Condition,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Piece {
+    Text(String),
+    Tag(String),
+}
+
+/// Splits the template into text and the contents of `{...}` tags
+fn split_tags(template: &str) -> Result<Vec<Piece>, String> {
+    let mut pieces = vec![];
+    let mut text = String::new();
+    let mut chars = template.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' if chars.peek() == Some(&'{') => {
+                chars.next();
+                text.push('{');
+            }
+            '}' if chars.peek() == Some(&'}') => {
+                chars.next();
+                text.push('}');
+            }
+            '{' => {
+                let mut tag = String::new();
+                let mut is_closed = false;
+                for c in chars.by_ref() {
+                    if c == '}' {
+                        is_closed = true;
+                        break;
+                    }
+                    tag.push(c);
+                }
+                if !is_closed {
+                    return Err(format!("unclosed {{{} in template", tag));
+                }
+                if tag.trim().is_empty() {
+                    return Err(String::from("empty {} in template"));
+                }
+                if !text.is_empty() {
+                    pieces.push(Piece::Text(mem::take(&mut text)));
+                }
+                pieces.push(Piece::Tag(String::from(tag.trim())));
+            }
+            '}' => return Err(String::from("unmatched } in template, use }} for a brace")),
+            c => text.push(c),
+        }
+    }
+    if !text.is_empty() {
+        pieces.push(Piece::Text(text));
+    }
+    Ok(pieces)
+}
+
+/// Selectors are quoted so commas in them aren't read as arguments
+fn parse_selector(text: &str) -> Result<Selector, String> {
+    let text = text.trim();
+    let text = text
+        .strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text);
+    Selector::parse(text)
+}
+
+fn parse_aggregate(function: &str, args: &str) -> Result<Expr, String> {
+    let function = match function.trim() {
+        "min" => Function::Min,
+        "max" => Function::Max,
+        "avg" => Function::Avg,
+        "sum" => Function::Sum,
+        "count" => Function::Count,
+        other => {
+            return Err(format!(
+                "unknown function `{}`, expected value, min, max, avg, sum or count",
+                other
+            ))
+        }
+    };
+    let (kind, sensors) = match args.split_once(',') {
+        Some((kind, sensors)) => (kind.trim(), Some(sensors.trim())),
+        None => (args.trim(), None),
+    };
+    let Some(kind) = SensorKind::from_name(kind) else {
+        return Err(format!(
+            "unknown sensor kind `{}`, expected temperature, fan, voltage, power, current or humidity",
+            kind
+        ));
+    };
+    let sensors = sensors.map(parse_selector).transpose()?;
+    Ok(Expr::Aggregate {
+        function,
+        kind,
+        sensors,
+    })
+}
+
+fn parse_expr(text: &str, scope: Scope) -> Result<Expr, String> {
+    let text = text.trim();
+    if let Some(name) = text.strip_prefix("sub.") {
+        if scope != Scope::Feature {
+            return Err(format!(
+                "`{}` needs --scope feature, sub-features belong to a feature",
+                text
+            ));
+        }
+        return Ok(Expr::SubFeature(String::from(name)));
+    }
+    if text == "hottest" {
+        return Ok(Expr::Sensor(Sensor::Hottest));
+    }
+    if let Some((function, args)) = text.strip_suffix(')').and_then(|text| text.split_once('(')) {
+        if function.trim() == "value" {
+            return Ok(Expr::Sensor(Sensor::Selected(parse_selector(args)?)));
+        }
+        return parse_aggregate(function, args);
+    }
+    match FIELDS.iter().find(|(name, _)| *name == text) {
+        Some((_, field)) if field.is_available(scope) => Ok(Expr::Field(*field)),
+        Some(_) => Err(format!(
+            "`{}` isn't available with --scope {}",
+            text,
+            scope.name()
+        )),
+        // The bar names sensors directly, as in `{Package id 0}°C {fan1}rpm`
+        None if scope == Scope::System && Selector::parse(text).is_ok() => {
+            Ok(Expr::Sensor(Sensor::Selected(Selector::parse(text)?)))
+        }
+        None => {
+            let fields = FIELDS
+                .iter()
+                .filter(|(_, field)| field.is_available(scope))
+                .map(|(name, _)| *name)
+                .collect::<Vec<_>>()
+                .join(", ");
+            Err(format!(
+                "unknown field `{}`, with --scope {} these are {}. Sensors are looked up with value(\"chip/label\")",
+                text,
+                scope.name(),
+                fields
+            ))
+        }
+    }
+}
+
+/// The first comparison operator outside quoted selectors and function
+/// arguments, `kind!=fan` in a selector isn't a comparison
+fn split_operator(text: &str) -> Option<(&str, Operator, &str)> {
+    let mut is_quoted = false;
+    let mut depth = 0;
+    for (i, c) in text.char_indices() {
+        match c {
+            '"' => is_quoted = !is_quoted,
+            '(' if !is_quoted => depth += 1,
+            ')' if !is_quoted => depth -= 1,
+            _ if is_quoted || depth > 0 => {}
+            _ => {
+                let rest = &text[i..];
+                if let Some((symbol, operator)) = OPERATORS
+                    .iter()
+                    .find(|(symbol, _)| rest.starts_with(symbol))
+                {
+                    return Some((&text[..i], *operator, &rest[symbol.len()..]));
+                }
+            }
+        }
+    }
+    None
+}
+
+fn parse_condition(text: &str, scope: Scope) -> Result<Condition, String> {
+    let text = text.trim();
+    if let Some(condition) = text.strip_prefix("not ") {
+        return Ok(Condition::Not(Box::new(parse_condition(condition, scope)?)));
+    }
+    match text {
+        "alarm" => return Ok(Condition::Alarm),
+        "high" => return Ok(Condition::Reached(Status::High)),
+        "critical" => return Ok(Condition::Reached(Status::Critical)),
+        "emergency" => return Ok(Condition::Reached(Status::Emergency)),
+        _ => {}
+    }
+    if let Some((left, operator, right)) = split_operator(text) {
+        let operand = |text: &str| match text.trim().parse() {
+            Ok(number) => Ok(Expr::Number(number)),
+            Err(_) => parse_expr(text, scope),
+        };
+        let (left, right) = (operand(left)?, operand(right)?);
+        if !left.is_numeric() || !right.is_numeric() {
+            return Err(format!("`{}` compares something that isn't a number", text));
+        }
+        return Ok(Condition::Compare(left, operator, right));
+    }
+    Err(format!(
+        "can't read condition `{}`, expected alarm, high, critical, emergency or a comparison like `value > 80`",
+        text
+    ))
+}
+
+/// `value:.1` is `value` with one decimal
+fn parse_value_tag(tag: &str, scope: Scope) -> Result<Node, String> {
+    let (expr, precision) = match tag.rsplit_once(':') {
+        // A colon inside a glob isn't a format
+        Some((expr, format)) if !format.contains(['"', ')']) => {
+            let precision = format
+                .trim()
+                .strip_prefix('.')
+                .and_then(|digits| digits.parse().ok())
+                .ok_or_else(|| {
+                    format!(
+                        "unsupported format `{}` in {{{}}}, only precisions like :.1 are",
+                        format, tag
+                    )
+                })?;
+            (parse_expr(expr, scope)?, Some(precision))
+        }
+        _ => (parse_expr(tag, scope)?, None),
+    };
+    if precision.is_some() && !expr.is_numeric() {
+        return Err(format!(
+            "{{{}}} isn't a number, it can't take a precision",
+            tag
+        ));
+    }
+    Ok(Node::Expr { expr, precision })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BlockEnd {
+    Else,
+    End,
+}
+
+/// Nodes up to the `{else}` or `{end}` closing the block, or the end of the template
+fn parse_block(
+    pieces: &mut impl Iterator<Item = Piece>,
+    scope: Scope,
+) -> Result<(Vec<Node>, Option<BlockEnd>), String> {
+    let mut nodes = vec![];
+    while let Some(piece) = pieces.next() {
+        let tag = match piece {
+            Piece::Text(text) => {
+                nodes.push(Node::Text(text));
+                continue;
+            }
+            Piece::Tag(tag) => tag,
+        };
+        match tag.as_str() {
+            "else" => return Ok((nodes, Some(BlockEnd::Else))),
+            "end" => return Ok((nodes, Some(BlockEnd::End))),
+            _ => {}
+        }
+        let Some(condition) = tag.strip_prefix("if ") else {
+            nodes.push(parse_value_tag(&tag, scope)?);
+            continue;
+        };
+        let condition = parse_condition(condition, scope)?;
+        let unclosed = || format!("{{{}}} has no {{end}}", tag);
+        let (then, otherwise) = match parse_block(pieces, scope)? {
+            (then, Some(BlockEnd::End)) => (then, vec![]),
+            (then, Some(BlockEnd::Else)) => match parse_block(pieces, scope)? {
+                (otherwise, Some(BlockEnd::End)) => (then, otherwise),
+                (_, Some(BlockEnd::Else)) => return Err(format!("{{{}}} has two {{else}}", tag)),
+                (_, None) => return Err(unclosed()),
+            },
+            (_, None) => return Err(unclosed()),
+        };
+        nodes.push(Node::If {
+            condition,
+            then,
+            otherwise,
+        });
+    }
+    Ok((nodes, None))
+}
+
+/// A sensor's value rounded as usual for its kind, for sensors looked up
+/// without a precision
+pub fn format_reading(reading: &Reading) -> String {
+    match (&reading.value, reading.kind) {
+        (Err(_), _) => String::from("N/A"),
+        (Ok(value), SensorKind::Voltage | SensorKind::Current) => format!("{:.2}", value),
+        (Ok(value), SensorKind::Power) => format!("{:.1}", value),
+        (Ok(value), _) => format!("{:.0}", value),
+    }
+}
+
+/// A template like `{chip}:{label}={value:.1}{unit}`, filled in once per
+/// feature, chip or for the whole system depending on the scope.
+/// `{max(temperature, "Core *")}` aggregates the readings picked by a
+/// selector, over the chip unless the scope is the system,
+/// `{value("nvme-*/Composite")}` looks up a single sensor, or
+/// `{nvme-*/Composite}` for the whole system, and
+/// `{if value >= crit}...{else}...{end}` checks thresholds
+#[derive(Debug, Clone)]
+pub struct Template {
+    scope: Scope,
+    nodes: Vec<Node>,
+}
+
+/// What a template is filled in for
+struct Item<'a> {
+    time: SystemTime,
+    chip: Option<&'a ChipSnapshot>,
+    feature: Option<&'a FeatureSnapshot>,
+    /// The item's chip, or every chip for the system. Aggregates run over these
+    chips: &'a [ChipSnapshot],
+}
+
+enum Value {
+    Text(String),
+    Number(Option<f64>),
+    Flag(bool),
+}
+
+impl Item<'_> {
+    fn reading(&self) -> Option<Reading> {
+        self.feature?.reading(&self.chip?.name)
+    }
+
+    /// The worst status of whatever the item covers
+    fn status(&self) -> Status {
+        let readings = match self.feature {
+            Some(_) => self.reading().into_iter().collect(),
+            None => select_readings(None, self.chips),
+        };
+        readings
+            .iter()
+            .map(Reading::status)
+            .max_by_key(Status::severity)
+            .unwrap_or(Status::Unknown)
+    }
+
+    fn has_alarm(&self) -> bool {
+        match self.feature {
+            Some(feature) => feature
+                .sub_features
+                .iter()
+                .any(SubFeatureSnapshot::is_active_alarm),
+            None => self.chips.iter().any(ChipSnapshot::has_active_alarm),
+        }
+    }
+
+    fn field(&self, field: Field) -> Value {
+        let text = |text: Option<&str>| Value::Text(String::from(text.unwrap_or("")));
+        let reading = self.reading();
+        let kind = self.feature.and_then(|feature| feature.kind);
+        match field {
+            Field::Time => Value::Text(
+                DateTime::<Local>::from(self.time).to_rfc3339_opts(SecondsFormat::Secs, false),
+            ),
+            Field::Host => text(self.chip.and_then(|chip| chip.host.as_deref())),
+            Field::Chip => text(self.chip.map(ChipSnapshot::local_name)),
+            Field::Prefix => text(self.chip.map(|chip| chip.prefix.as_str())),
+            Field::Bus => text(self.chip.and_then(|chip| chip.bus.as_deref())),
+            Field::Name => text(self.feature.map(|feature| feature.name.as_str())),
+            Field::Label => text(self.feature.map(|feature| feature.label.as_str())),
+            Field::Kind => text(Some(kind.map_or("other", |kind| kind.name()))),
+            // Features senso doesn't chart may still have an input
+            Field::Value => Value::Number(match reading {
+                Some(reading) => reading.value.ok(),
+                None => self.sub_feature("input"),
+            }),
+            Field::Unit => text(kind.map(|kind| kind.unit())),
+            Field::Max => Value::Number(reading.and_then(|reading| reading.maximum)),
+            Field::Crit => Value::Number(reading.and_then(|reading| reading.critical)),
+            Field::Emergency => Value::Number(reading.and_then(|reading| reading.emergency)),
+            Field::Headroom => Value::Number(reading.and_then(|reading| reading.headroom())),
+            Field::Status => text(Some(self.status().name())),
+            Field::Alarm => Value::Flag(self.has_alarm()),
+        }
+    }
+
+    fn sub_feature(&self, short_name: &str) -> Option<f64> {
+        self.feature?
+            .sub_feature(short_name)?
+            .value
+            .as_ref()
+            .ok()
+            .copied()
+    }
+
+    fn aggregate(
+        &self,
+        function: Function,
+        kind: SensorKind,
+        sensors: Option<&Selector>,
+    ) -> Option<f64> {
+        let values = select_readings(sensors, self.chips)
+            .iter()
+            .filter(|reading| reading.kind == kind)
+            .filter_map(|reading| reading.value.as_ref().ok().copied())
+            .collect::<Vec<_>>();
+        let count = values.len() as f64;
+        match function {
+            Function::Min => values.into_iter().min_by(f64::total_cmp),
+            Function::Max => values.into_iter().max_by(f64::total_cmp),
+            Function::Avg => mean(values),
+            Function::Sum => Some(sum(values)),
+            Function::Count => Some(count),
+        }
+    }
+
+    fn sensor(&self, sensor: &Sensor) -> Option<Reading> {
+        match sensor {
+            Sensor::Selected(selector) => select_readings(Some(selector), self.chips)
+                .into_iter()
+                .next(),
+            Sensor::Hottest => self
+                .chips
+                .iter()
+                .filter(|chip| is_cpu_chip(chip))
+                .flat_map(ChipSnapshot::readings)
+                .filter(|reading| reading.kind == SensorKind::Temperature)
+                .filter_map(|reading| Some((*reading.value.as_ref().ok()?, reading)))
+                .max_by(|(a, _), (b, _)| a.total_cmp(b))
+                .map(|(_, reading)| reading),
+        }
+    }
+
+    fn eval(&self, expr: &Expr) -> Value {
+        match expr {
+            Expr::Field(field) => self.field(*field),
+            Expr::SubFeature(name) => Value::Number(self.sub_feature(name)),
+            Expr::Aggregate {
+                function,
+                kind,
+                sensors,
+            } => Value::Number(self.aggregate(*function, *kind, sensors.as_ref())),
+            Expr::Sensor(sensor) => {
+                Value::Number(self.sensor(sensor).and_then(|reading| reading.value.ok()))
+            }
+            Expr::Number(number) => Value::Number(Some(*number)),
+        }
+    }
+
+    fn number(&self, expr: &Expr) -> Option<f64> {
+        match self.eval(expr) {
+            Value::Number(number) => number,
+            Value::Text(_) | Value::Flag(_) => None,
+        }
+    }
+
+    /// Comparisons with a missing value are false
+    fn check(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Alarm => self.has_alarm(),
+            Condition::Reached(status) => self.status().severity() >= status.severity(),
+            Condition::Compare(left, operator, right) => {
+                let (Some(left), Some(right)) = (self.number(left), self.number(right)) else {
+                    return false;
+                };
+                match operator {
+                    Operator::Less => left < right,
+                    Operator::LessOrEqual => left <= right,
+                    Operator::Equal => left == right,
+                    Operator::NotEqual => left != right,
+                    Operator::GreaterOrEqual => left >= right,
+                    Operator::Greater => left > right,
+                }
+            }
+            Condition::Not(condition) => !self.check(condition),
+        }
+    }
+
+    fn push_sensor(&self, expr: &Expr, out: &mut Vec<Reading>) {
+        let Expr::Sensor(sensor) = expr else {
+            return;
+        };
+        if let Some(reading) = self.sensor(sensor) {
+            if !out.iter().any(|other| other.id == reading.id) {
+                out.push(reading);
+            }
+        }
+    }
+
+    /// The sensors looked up by the filled in parts of `nodes`
+    fn looked_up(&self, nodes: &[Node], out: &mut Vec<Reading>) {
+        for node in nodes {
+            match node {
+                Node::Text(_) => {}
+                Node::Expr { expr, .. } => self.push_sensor(expr, out),
+                Node::If {
+                    condition,
+                    then,
+                    otherwise,
+                } => {
+                    let mut compared = condition;
+                    while let Condition::Not(inner) = compared {
+                        compared = inner;
+                    }
+                    if let Condition::Compare(left, _, right) = compared {
+                        self.push_sensor(left, out);
+                        self.push_sensor(right, out);
+                    }
+                    if self.check(condition) {
+                        self.looked_up(then, out)
+                    } else {
+                        self.looked_up(otherwise, out)
+                    }
+                }
+            }
+        }
+    }
+
+    fn fill(&self, nodes: &[Node], out: &mut String) {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Expr {
+                    expr: Expr::Sensor(sensor),
+                    precision: None,
+                } => match self.sensor(sensor) {
+                    Some(reading) => out.push_str(&format_reading(&reading)),
+                    None => out.push_str("N/A"),
+                },
+                Node::Expr { expr, precision } => match (self.eval(expr), precision) {
+                    (Value::Text(text), _) => out.push_str(&text),
+                    (Value::Flag(flag), _) => out.push_str(if flag { "true" } else { "false" }),
+                    (Value::Number(None), _) => out.push_str("N/A"),
+                    (Value::Number(Some(number)), Some(precision)) => {
+                        out.push_str(&format!("{:.*}", precision, number))
+                    }
+                    (Value::Number(Some(number)), None) => out.push_str(&number.to_string()),
+                },
+                Node::If {
+                    condition,
+                    then,
+                    otherwise,
+                } => {
+                    if self.check(condition) {
+                        self.fill(then, out)
+                    } else {
+                        self.fill(otherwise, out)
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl Template {
+    pub fn parse(template: &str, scope: Scope) -> Result<Self, String> {
+        let mut pieces = split_tags(template)?.into_iter();
+        match parse_block(&mut pieces, scope)? {
+            (nodes, None) => Ok(Self { scope, nodes }),
+            (_, Some(BlockEnd::Else)) => Err(String::from("{else} without {if ...}")),
+            (_, Some(BlockEnd::End)) => Err(String::from("{end} without {if ...}")),
+        }
+    }
+
+    /// The sensors `value(...)` and `hottest` pick over the whole system, in
+    /// template order
+    pub fn readings(&self, chips: &[ChipSnapshot], time: SystemTime) -> Vec<Reading> {
+        let item = Item {
+            time,
+            chip: None,
+            feature: None,
+            chips,
+        };
+        let mut readings = vec![];
+        item.looked_up(&self.nodes, &mut readings);
+        readings
+    }
+
+    /// One line per feature, chip or system. Lines that come out empty, e.g.
+    /// because of an `{if}`, are left out
+    pub fn render<'a>(&self, chips: &'a [ChipSnapshot], time: SystemTime) -> String {
+        let item = |chip: Option<&'a ChipSnapshot>, feature| Item {
+            time,
+            chip,
+            feature,
+            chips: chip.map_or(chips, slice::from_ref),
+        };
+        let items: Vec<Item> = match self.scope {
+            Scope::System => vec![item(None, None)],
+            Scope::Chip => chips.iter().map(|chip| item(Some(chip), None)).collect(),
+            Scope::Feature => chips
+                .iter()
+                .flat_map(|chip| chip.features.iter().map(move |feature| (chip, feature)))
+                .map(|(chip, feature)| item(Some(chip), Some(feature)))
+                .collect(),
+        };
+        let mut out = String::new();
+        for item in items {
+            let mut line = String::new();
+            item.fill(&self.nodes, &mut line);
+            if line.is_empty() {
+                continue;
+            }
+            out.push_str(&line);
+            if !line.ends_with('\n') {
+                out.push('\n');
+            }
+        }
+        out
+    }
+}
+
+fn print_templates(
+    template: &Template,
+    sensors: Option<&Selector>,
+    interval: Option<Duration>,
+    source: &mut dyn SensorSource,
+) -> io::Result<()> {
+    let mut collector = Collector::default();
+    let mut stdout = io::stdout().lock();
+    loop {
+        source.poll(&mut collector);
+        let chips = match sensors {
+            Some(sensors) => sensors.filter_chips(collector.get_chips()),
+            None => collector.get_chips().to_vec(),
+        };
+        let text = template.render(&chips, SystemTime::now());
+        stdout.write_all(text.as_bytes())?;
+        stdout.flush()?;
+        let Some(interval) = interval else {
+            return Ok(());
+        };
+        thread::sleep(interval);
+    }
+}
+
+/// Prints the template once, or every `interval` until interrupted. Only the
+/// selected sensors are filled in, aggregates included
+pub fn run_template(
+    template: &Template,
+    sensors: Option<&Selector>,
+    interval: Option<Duration>,
+    mut source: Box<dyn SensorSource>,
+) -> Result<(), Box<dyn Error>> {
+    match print_templates(template, sensors, interval, source.as_mut()) {
+        // e.g. piped into head
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::{Scope, Template};
+    use crate::{
+        sensors::SensorKind,
+        snapshot::{
+            fixtures::{self, chip, input},
+            ChipSnapshot,
+        },
+    };
+
+    fn chips() -> Vec<ChipSnapshot> {
+        let temperature = |name: &str, label: &str, input: f64| {
+            fixtures::feature(
+                name,
+                label,
+                SensorKind::Temperature,
+                &[
+                    ("input", input),
+                    ("max", 80.0),
+                    ("crit", 100.0),
+                    ("crit_hyst", 90.0),
+                ],
+            )
+        };
+        vec![
+            chip(
+                "coretemp-isa-0000",
+                vec![
+                    temperature("temp1", "Package id 0", 85.0),
+                    temperature("temp2", "Core 0", 50.0),
+                    temperature("temp3", "Core 1", 61.0),
+                ],
+            ),
+            chip(
+                "it8688-isa-0a40",
+                vec![input("fan1", "fan1", SensorKind::Fan, 1234.0)],
+            ),
+        ]
+    }
+
+    fn render(template: &str, scope: Scope) -> String {
+        Template::parse(template, scope)
+            .unwrap()
+            .render(&chips(), SystemTime::now())
+    }
+
+    #[test]
+    fn test_feature_fields() {
+        let text = render(
+            "{chip}:{label}={value:.1}{unit} {sub.crit_hyst} {sub.missing}",
+            Scope::Feature,
+        );
+
+        assert!(text.lines().count() == 4);
+        assert!(text.lines().next() == Some("coretemp-isa-0000:Package id 0=85.0C 90 N/A"));
+        assert!(text.lines().last() == Some("it8688-isa-0a40:fan1=1234.0RPM N/A N/A"));
+    }
+
+    #[test]
+    fn test_aggregates() {
+        let text = render(
+            r#"{prefix} {max(temperature)} {avg(temperature, "Core *"):.1} {count(fan)}"#,
+            Scope::Chip,
+        );
+
+        assert!(text == "coretemp 85 55.5 0\nit8688 N/A N/A 1\n");
+        assert!(render("{sum(fan)}", Scope::Chip) == "0\n1234\n");
+        assert!(render("{sum(fan)} {status}", Scope::System) == "1234 high\n");
+    }
+
+    #[test]
+    fn test_sensor_lookups() {
+        let template = Template::parse(
+            r#"{value("coretemp-*/Core 1")} {hottest} {value("kind=fan"):.1}"#,
+            Scope::System,
+        )
+        .unwrap();
+        let readings = template.readings(&chips(), SystemTime::now());
+
+        assert!(template.render(&chips(), SystemTime::now()) == "61 85 1234.0\n");
+        assert!(readings.len() == 3);
+        assert!(readings[1].id.label == "Package id 0");
+        assert!(Template::parse(r#"{value("kind=")}"#, Scope::System).is_err());
+        assert!(render("{Core 1} {coretemp-*/Core 0}", Scope::System) == "61 50\n");
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let text = render(
+            "{if value >= max}{label} is hot{else}{if not high}{label} {headroom}{end}{end}",
+            Scope::Feature,
+        );
+
+        assert!(text == "Package id 0 is hot\nCore 0 50\nCore 1 39\nfan1 N/A\n");
+        assert!(render("{if critical}{chip}{end}", Scope::Chip).is_empty());
+    }
+
+    #[test]
+    fn test_operators_inside_selectors() {
+        let text = render(
+            r#"{if value("chip.bus!=pci kind=temperature") > 80}hot{end} {if max(temperature, "kind!=fan") >= 90}x{else}y{end}"#,
+            Scope::System,
+        );
+
+        assert!(text == "hot y\n");
+    }
+
+    #[test]
+    fn test_errors() {
+        let error = Template::parse("{valu}", Scope::Feature).unwrap_err();
+        assert!(error.contains("unknown field `valu`"));
+        assert!(error.contains("value"));
+
+        assert!(Template::parse("{label}", Scope::Chip).is_err());
+        assert!(Template::parse("{sub.crit}", Scope::System).is_err());
+        assert!(Template::parse("{label:.1}", Scope::Feature).is_err());
+        assert!(Template::parse("{value:>5}", Scope::Feature).is_err());
+        assert!(Template::parse("{median(temperature)}", Scope::Chip).is_err());
+        assert!(Template::parse("{max(temp)}", Scope::Chip).is_err());
+        assert!(Template::parse("{if value > hot}x{end}", Scope::Feature).is_err());
+        assert!(Template::parse("{if alarm}x", Scope::Feature).is_err());
+        assert!(Template::parse("{if alarm}x{else}y{else}z{end}", Scope::Feature).is_err());
+        assert!(Template::parse("x{end}", Scope::Feature).is_err());
+    }
+}
@@ -1,4 +1,18 @@
-use std::{error::Error, io::{self, Stdout}};
+use std::{
+    backtrace::Backtrace,
+    error::Error,
+    io::{self, Stdout},
+    ops::{Deref, DerefMut},
+    panic,
+};
+
+use crossterm::{
+    cursor::Show,
+    event::{DisableMouseCapture, EnableMouseCapture},
+    execute,
+    terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
+};
+use log::error;
 use ratatui::{backend::CrosstermBackend, Terminal};
 
 pub fn get_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>, Box<dyn Error>> {
@@ -6,3 +20,60 @@ pub fn get_terminal() -> Result<Terminal<CrosstermBackend<Stdout>>, Box<dyn Erro
     let backend = CrosstermBackend::new(stdout);
     Ok(Terminal::new(backend)?)
 }
+
+/// Leaves raw mode and the alternate screen. Safe to call more than once, the
+/// panic hook and `TerminalGuard` may both get to it
+pub fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = execute!(io::stdout(), LeaveAlternateScreen, DisableMouseCapture, Show);
+}
+
+/// Owns the terminal while the TUI runs and restores it when dropped, whether
+/// `run_gui` returns normally, with an error or by unwinding
+pub struct TerminalGuard {
+    terminal: Terminal<CrosstermBackend<Stdout>>,
+}
+
+impl TerminalGuard {
+    pub fn new() -> Result<Self, Box<dyn Error>> {
+        let terminal = get_terminal()?;
+        enable_raw_mode()?;
+        // From here on dropping the guard cleans up, even if the rest fails
+        let mut guard = Self { terminal };
+        guard.terminal.autoresize()?;
+        execute!(guard.terminal.backend_mut(), EnterAlternateScreen, EnableMouseCapture)?;
+
+        Ok(guard)
+    }
+}
+
+impl Deref for TerminalGuard {
+    type Target = Terminal<CrosstermBackend<Stdout>>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.terminal
+    }
+}
+
+impl DerefMut for TerminalGuard {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.terminal
+    }
+}
+
+impl Drop for TerminalGuard {
+    fn drop(&mut self) {
+        restore_terminal();
+    }
+}
+
+/// Restores the terminal before the panic is printed, otherwise the message
+/// ends up on the alternate screen and is lost
+pub fn install_panic_hook() {
+    panic::set_hook(Box::new(|info| {
+        restore_terminal();
+        let backtrace = Backtrace::force_capture();
+        error!("{}\n{}", info, backtrace);
+        eprintln!("{}\n{}", info, backtrace);
+    }));
+}
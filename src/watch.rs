@@ -0,0 +1,212 @@
+use std::{
+    collections::HashMap,
+    error::Error,
+    io::{self, ErrorKind, Write},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use chrono::{DateTime, Local, SecondsFormat};
+use clap::ValueEnum;
+use serde_json::json;
+
+use crate::{
+    collector::Collector,
+    selector::{select_readings, Selector},
+    sensors::{Reading, SensorId},
+    snapshot::ChipSnapshot,
+    source::SensorSource,
+};
+
+const CSV_HEADER: &str = "time,chip,label,kind,value,unit,status";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WatchFormat {
+    /// One JSON object per reading and line
+    Jsonl,
+    /// A header, then one row per reading
+    Csv,
+}
+
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        String::from(field)
+    }
+}
+
+pub fn format_record(format: WatchFormat, time: SystemTime, reading: &Reading) -> String {
+    let time = DateTime::<Local>::from(time).to_rfc3339_opts(SecondsFormat::Millis, false);
+    let value = reading.value.as_ref().ok();
+    match format {
+        WatchFormat::Jsonl => json!({
+            "time": time,
+            "chip": reading.id.chip,
+            "label": reading.id.label,
+            "kind": reading.kind.name(),
+            "value": value,
+            "unit": reading.kind.unit(),
+            "status": reading.status().name(),
+            "max": reading.maximum,
+            "crit": reading.critical,
+        })
+        .to_string(),
+        WatchFormat::Csv => [
+            time,
+            csv_field(&reading.id.chip),
+            csv_field(&reading.id.label),
+            String::from(reading.kind.name()),
+            value.map(f64::to_string).unwrap_or_default(),
+            String::from(reading.kind.unit()),
+            String::from(reading.status().name()),
+        ]
+        .join(","),
+    }
+}
+
+/// Picks the readings to print on a tick and remembers the values for
+/// `changes_only`
+pub struct Watcher {
+    sensors: Option<Selector>,
+    changes_only: bool,
+    last_values: HashMap<SensorId, Option<f64>>,
+}
+
+impl Watcher {
+    pub fn new(sensors: Option<Selector>, changes_only: bool) -> Self {
+        Self {
+            sensors,
+            changes_only,
+            last_values: HashMap::new(),
+        }
+    }
+
+    pub fn select(&mut self, chips: &[ChipSnapshot]) -> Vec<Reading> {
+        select_readings(self.sensors.as_ref(), chips)
+            .into_iter()
+            .filter(|reading| {
+                let value = reading.value.as_ref().ok().copied();
+                let last = self.last_values.insert(reading.id.clone(), value);
+                !self.changes_only || last != Some(value)
+            })
+            .collect()
+    }
+}
+
+fn print_records(
+    format: WatchFormat,
+    interval: Duration,
+    count: Option<u64>,
+    watcher: &mut Watcher,
+    source: &mut dyn SensorSource,
+) -> io::Result<()> {
+    // Sampled like `App::tick`, so the values match the TUI
+    let mut collector = Collector::default();
+    let mut stdout = io::stdout().lock();
+    if format == WatchFormat::Csv {
+        writeln!(stdout, "{}", CSV_HEADER)?;
+    }
+    let mut ticks = 0;
+    loop {
+        source.poll(&mut collector);
+        let time = SystemTime::now();
+        for reading in watcher.select(collector.get_chips()) {
+            writeln!(stdout, "{}", format_record(format, time, &reading))?;
+        }
+        stdout.flush()?;
+        ticks += 1;
+        if count.is_some_and(|count| ticks >= count) {
+            return Ok(());
+        }
+        thread::sleep(interval);
+    }
+}
+
+/// Prints the readings every `interval`, `count` times or until interrupted
+pub fn run_watch(
+    format: WatchFormat,
+    interval: Duration,
+    count: Option<u64>,
+    mut watcher: Watcher,
+    mut source: Box<dyn SensorSource>,
+) -> Result<(), Box<dyn Error>> {
+    match print_records(format, interval, count, &mut watcher, source.as_mut()) {
+        // e.g. piped into head
+        Err(e) if e.kind() == ErrorKind::BrokenPipe => Ok(()),
+        result => Ok(result?),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::SystemTime;
+
+    use super::{format_record, WatchFormat, Watcher};
+    use crate::{
+        selector::Selector,
+        sensors::{Reading, SensorError, SensorKind},
+        snapshot::{
+            fixtures::{chip, input, reading},
+            ChipSnapshot,
+        },
+    };
+
+    fn k10temp(tctl: f64, tccd1: f64) -> Vec<ChipSnapshot> {
+        vec![chip(
+            "k10temp-pci-00c3",
+            vec![
+                input("temp1", "Tctl", SensorKind::Temperature, tctl),
+                input("temp3", "Tccd1", SensorKind::Temperature, tccd1),
+            ],
+        )]
+    }
+
+    #[test]
+    fn test_changes_only() {
+        let mut watcher = Watcher::new(None, true);
+
+        assert!(watcher.select(&k10temp(50.0, 45.0)).len() == 2);
+        assert!(watcher.select(&k10temp(50.0, 45.0)).is_empty());
+        assert!(watcher.select(&k10temp(51.0, 45.0)).len() == 1);
+    }
+
+    #[test]
+    fn test_selector() {
+        let selector = Selector::parse("k10temp-*/Tccd*").unwrap();
+        let mut watcher = Watcher::new(Some(selector), false);
+
+        assert!(watcher.select(&k10temp(50.0, 45.0)).len() == 1);
+        assert!(watcher.select(&k10temp(50.0, 45.0)).len() == 1);
+    }
+
+    #[test]
+    fn test_csv_quotes_fields() {
+        let record = format_record(
+            WatchFormat::Csv,
+            SystemTime::now(),
+            &reading(
+                "nct6775-isa-0290",
+                "CPU, socket \"A\"",
+                SensorKind::Temperature,
+                50.5,
+            ),
+        );
+
+        assert!(
+            record.ends_with(r#",nct6775-isa-0290,"CPU, socket ""A""",temperature,50.5,C,normal"#)
+        );
+    }
+
+    #[test]
+    fn test_jsonl_unreadable_value_is_null() {
+        let reading = Reading {
+            value: Err(SensorError::Read(String::from("EIO"))),
+            ..reading("k10temp-pci-00c3", "Tctl", SensorKind::Temperature, 0.0)
+        };
+        let record = format_record(WatchFormat::Jsonl, SystemTime::now(), &reading);
+
+        assert!(record.contains(r#""value":null"#));
+        assert!(record.contains(r#""status":"unknown""#));
+    }
+}